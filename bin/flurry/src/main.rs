@@ -1,12 +1,18 @@
 use std::env;
 use std::fs::File;
-use std::io::{Read};
+use std::io::{Read, Write};
 use std::process::exit;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
-use fractal::{Evaluator, EvaluatorConfig, TypedExpression};
-use parser::{lexer, snowflake::ProgramParser, ast::{Statement, Type, Expression}};
+use fractal::{bytecode::{self, Vm}, ir::Passes, Evaluator, EvaluatorConfig, Value};
+use lalrpop_util::ParseError;
+use parser::{
+    ast::Statement,
+    error::Error,
+    indentation::{IndentationConfig, Tabs},
+    lexer,
+};
 use tag::{TagName};
 
 // Wrapper for unwrapping Results and printing errors cleanly
@@ -40,48 +46,226 @@ macro_rules! unwrap {
     };
 }
 
+// the engines able to run a program
+enum Engine {
+    TreeWalker,
+    Bytecode,
+}
+
+const USAGE: &str = "Usage: flurry [--engine tree|bytecode] [FILE] [CONFIG]
+       flurry compile [FILE] [CONFIG] [OUTPUT]
+       flurry disasm [MODULE]
+       flurry ir [--no-inline] [--no-fold] [--no-dce] [--inline-threshold N] [--entry NAME] [FILE] [CONFIG]
+       flurry exec [MODULE]";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // the optimisation passes may be toggled before the positional arguments of `flurry ir`
+    if args.get(1).map(String::as_str) == Some("ir") {
+        let passes = passes(&mut args);
+        if args.len() >= 4 {
+            let evaluator = populate(&args[2], &args[3])?;
+            let mut program = evaluator.lower()?;
+            passes.run(&mut program);
+            print!("{}", program);
+            return Ok(());
+        }
+    }
+
+    // subcommands working with compiled modules and intermediate forms
+    match args.get(1).map(String::as_str) {
+        Some("compile") if args.len() >= 5 => {
+            let evaluator = populate(&args[2], &args[3])?;
+            let module = evaluator.module()?;
+            let mut output = unwrap!(File::create(&args[4]));
+            output.write_all(&unwrap!(module.encode()))?;
+            return Ok(());
+        }
+        Some("disasm") if args.len() >= 3 => {
+            let module = unwrap!(bytecode::Module::decode(&read(&args[2])?));
+            print!("{}", bytecode::disassemble(&module));
+            return Ok(());
+        }
+        Some("exec") if args.len() >= 3 => {
+            let module = unwrap!(bytecode::load(&read(&args[2])?));
+            let vm = Vm::new(&module.program);
+            unwrap!(vm.call("main", vec![Value::Int(69.into())]));
+            return Ok(());
+        }
+        Some("compile") | Some("disasm") | Some("ir") | Some("exec") => {
+            eprintln!("{}", USAGE);
+            exit(1);
+        }
+        _ => (),
+    }
+
+    // the engine may be chosen before the positional arguments, defaulting to the tree walker
+    let mut engine = Engine::TreeWalker;
+    if args.get(1).map(String::as_str) == Some("--engine") {
+        engine = match args.get(2).map(String::as_str) {
+            Some("tree") => Engine::TreeWalker,
+            Some("bytecode") => Engine::Bytecode,
+            _ => {
+                eprintln!("Error!: the engine must be one of `tree` or `bytecode`");
+                exit(1);
+            }
+        };
+        args.drain(1..3);
+    }
 
     if args.len() < 3 {
-        eprintln!("Usage: flurry [FILE] [CONFIG]");
+        eprintln!("{}", USAGE);
         exit(1);
     }
 
-    let mut file = unwrap!(File::open(&args[1]));
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+    let mut evaluator = populate(&args[1], &args[2])?;
+
+    let main = match evaluator.entry("main") {
+        Some(main) => main.clone(),
+        None => {
+            eprintln!("Error!: no `main` binding was found");
+            exit(1);
+        }
+    };
+
+    let args = vec![Value::Int(69.into())];
+    match engine {
+        Engine::TreeWalker => {
+            evaluator.eval(&main, args)?;
+        }
+        Engine::Bytecode => {
+            let program = evaluator.compile()?;
+            Vm::new(&program).call("main", args)?;
+        }
+    }
+
+    Ok(())
+}
+
+// removes the flags toggling the optimisation passes from the arguments of `flurry ir`, every pass
+// being enabled unless it is disabled by one of them
+fn passes(args: &mut Vec<String>) -> Passes {
+    let mut passes = Passes::default();
+    while let Some(flag) = args.get(2).filter(|arg| arg.starts_with("--")).cloned() {
+        let value = || match args.get(3) {
+            Some(value) => value.clone(),
+            None => {
+                eprintln!("Error!: `{}` must be followed by a value", flag);
+                exit(1);
+            }
+        };
+        let taken = match flag.as_str() {
+            "--no-inline" => {
+                passes.inline = false;
+                1
+            }
+            "--no-fold" => {
+                passes.fold_constants = false;
+                1
+            }
+            "--no-dce" => {
+                passes.eliminate_dead_bindings = false;
+                1
+            }
+            "--inline-threshold" => {
+                passes.inline_threshold = match value().parse() {
+                    Ok(threshold) => threshold,
+                    Err(_) => {
+                        eprintln!("Error!: `--inline-threshold` must be a non-negative integer");
+                        exit(1);
+                    }
+                };
+                2
+            }
+            "--entry" => {
+                passes.entry = value();
+                2
+            }
+            _ => {
+                eprintln!("Error!: unknown flag `{}`\n{}", flag, USAGE);
+                exit(1);
+            }
+        };
+        args.drain(2..2 + taken);
+    }
+    passes
+}
+
+// reads a whole file, exiting if it can't be opened
+fn read(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut file = unwrap!(File::open(path));
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
 
-    let mut config_file = unwrap!(File::open(&args[2]));
-    let mut config = String::new();
-    config_file.read_to_string(&mut config)?;
+// parses a source file and populates an evaluator with it
+fn populate(path: &str, config_path: &str) -> Result<Evaluator<'static>, Box<dyn std::error::Error>> {
+    let contents = String::from_utf8(read(path)?)?;
+    let config = String::from_utf8(read(config_path)?)?;
 
-    let input = lexer::lex(&contents);
-    let program = ProgramParser::new().parse(input).unwrap();
+    let input = lexer::lex_with_config(&contents, indentation_config(&config));
+    let program = match parser::parse(input) {
+        Ok(program) => program,
+        Err(ParseError::User {
+            error: Error::Indentation(err),
+        }) => {
+            let line = contents[..err.span().start].matches('\n').count() + 1;
+            eprintln!("Error!: {}:{}: {}", path, line, err);
+            exit(1);
+        }
+        Err(err) => {
+            eprintln!("Error!: {:?}", err);
+            exit(1);
+        }
+    };
 
     let split: Vec<&str> = config.split(":").collect();
     let proj = split[0];
     let mut file_tags: HashMap<String, Vec<TagName>> = HashMap::new();
-    file_tags.insert(args[2].clone(), Vec::new()); // required for evaluator.prepare to work
+    file_tags.insert(config_path.to_string(), Vec::new()); // required for evaluator.prepare to work
 
     let conf = EvaluatorConfig {
-        project_tag: TagName::Primary(Cow::from(proj)),
+        project_tag: TagName::Primary(Cow::Owned(proj.to_string())),
         file_tags,
     };
     let mut evaluator = Evaluator::new(conf);
 
     let mut source: HashMap<String, Vec<Statement>> = HashMap::new();
-    source.insert(args[2].clone(), program);
+    source.insert(config_path.to_string(), program);
     evaluator.populate(&source)?;
 
-    let main = evaluator.entries
-        .iter_mut()
-        .filter(|t| t.binding.index() == 0)
-        .next()
-        .unwrap()
-        .clone();
-
-    evaluator.eval(&main, vec![TypedExpression(Type::Identifier(String::from("ilarge")), Expression::Integer(69.into()))])?;
+    Ok(evaluator)
+}
 
-    Ok(())
+// reads the indentation settings from the lines after the first in a project config, each of the
+// form `key = value`. tabs are rejected unless `tab_width` is set
+fn indentation_config(config: &str) -> IndentationConfig {
+    let mut indentation = IndentationConfig::default();
+    for line in config.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                eprintln!("Error!: expected `key = value` in the config, found `{}`", line);
+                exit(1);
+            }
+        };
+        let width = match value.parse::<usize>() {
+            Ok(width) if width > 0 => width,
+            _ => {
+                eprintln!("Error!: `{}` must be a positive integer", key);
+                exit(1);
+            }
+        };
+        match key {
+            "indent_width" => indentation.width = Some(width),
+            "tab_width" => indentation.tabs = Tabs::Expand(width),
+            _ => {
+                eprintln!("Error!: unknown config setting `{}`", key);
+                exit(1);
+            }
+        }
+    }
+    indentation
 }
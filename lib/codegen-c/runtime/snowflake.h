@@ -0,0 +1,519 @@
+/*
+ * the runtime of snowflake programs compiled to c
+ *
+ * every value is a pointer to a heap object tagged with its kind. integers have arbitrary
+ * precision, matching the interpreter. nothing is ever freed
+ *
+ * this file is included verbatim at the top of every translation unit emitted by codegen-c, so it
+ * must stay within c99 and the standard library
+ */
+
+#ifndef SNOWFLAKE_H
+#define SNOWFLAKE_H
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct sf_object *sf_value;
+
+/* the uniform calling convention used by function references */
+typedef sf_value (*sf_thunk)(sf_value *args);
+
+enum sf_kind {
+	SF_INT,
+	SF_STRING,
+	SF_BOOL,
+	SF_LIST,
+	SF_VARIANT,
+	SF_FUNCTION,
+	SF_UNIT,
+};
+
+struct sf_object {
+	enum sf_kind kind;
+	union {
+		/* sign and magnitude, with 32-bit limbs stored least significant first. zero has no limbs */
+		struct {
+			int negative;
+			size_t len;
+			uint32_t *limbs;
+		} integer;
+		const char *string;
+		int boolean;
+		struct {
+			size_t len;
+			sf_value *items;
+		} list;
+		struct {
+			const char *name;
+			size_t len;
+			sf_value *fields;
+		} variant;
+		struct {
+			const char *name;
+			size_t arity;
+			sf_thunk call;
+		} function;
+	} as;
+};
+
+/* errors are reported the same way flurry reports them */
+static void sf_fail(const char *message)
+{
+	fprintf(stderr, "Error!: %s\n", message);
+	exit(1);
+}
+
+static void *sf_alloc(size_t size)
+{
+	void *memory = malloc(size ? size : 1);
+	if (!memory)
+		sf_fail("Out of memory");
+	return memory;
+}
+
+static sf_value sf_new(enum sf_kind kind)
+{
+	sf_value value = sf_alloc(sizeof(struct sf_object));
+	value->kind = kind;
+	return value;
+}
+
+static sf_value *sf_copy(size_t len, const sf_value *values)
+{
+	sf_value *copy = sf_alloc(len * sizeof(sf_value));
+	if (len)
+		memcpy(copy, values, len * sizeof(sf_value));
+	return copy;
+}
+
+static void sf_no_match(void)
+{
+	sf_fail("No pattern matched the value");
+}
+
+/* integers */
+
+static sf_value sf_int_new(int negative, size_t len, uint32_t *limbs)
+{
+	sf_value value = sf_new(SF_INT);
+	while (len && !limbs[len - 1])
+		len--;
+	value->as.integer.negative = len ? negative : 0;
+	value->as.integer.len = len;
+	value->as.integer.limbs = limbs;
+	return value;
+}
+
+static sf_value sf_int_from(long long n)
+{
+	unsigned long long magnitude = n < 0 ? 0ull - (unsigned long long)n : (unsigned long long)n;
+	uint32_t *limbs = sf_alloc(2 * sizeof(uint32_t));
+	limbs[0] = (uint32_t)magnitude;
+	limbs[1] = (uint32_t)(magnitude >> 32);
+	return sf_int_new(n < 0, 2, limbs);
+}
+
+/* multiplies a magnitude by a small factor and adds a small term, in place. `limbs` must have
+ * room for one more limb than `*len` */
+static void sf_mag_mul_add(uint32_t *limbs, size_t *len, uint32_t factor, uint32_t term)
+{
+	uint64_t carry = term;
+	size_t i;
+	for (i = 0; i < *len; i++) {
+		carry += (uint64_t)limbs[i] * factor;
+		limbs[i] = (uint32_t)carry;
+		carry >>= 32;
+	}
+	if (carry)
+		limbs[(*len)++] = (uint32_t)carry;
+}
+
+/* parses an integer literal too large for sf_int_from */
+static sf_value sf_int_parse(const char *digits)
+{
+	int negative = *digits == '-';
+	size_t len = 0;
+	uint32_t *limbs = sf_alloc((strlen(digits) / 9 + 2) * sizeof(uint32_t));
+	if (negative)
+		digits++;
+	for (; *digits; digits++)
+		sf_mag_mul_add(limbs, &len, 10, (uint32_t)(*digits - '0'));
+	return sf_int_new(negative, len, limbs);
+}
+
+static int sf_mag_cmp(const uint32_t *a, size_t alen, const uint32_t *b, size_t blen)
+{
+	size_t i;
+	if (alen != blen)
+		return alen < blen ? -1 : 1;
+	for (i = alen; i-- > 0;)
+		if (a[i] != b[i])
+			return a[i] < b[i] ? -1 : 1;
+	return 0;
+}
+
+static uint32_t *sf_mag_add(const uint32_t *a, size_t alen, const uint32_t *b, size_t blen,
+			    size_t *len)
+{
+	size_t n = alen > blen ? alen : blen, i;
+	uint32_t *sum = sf_alloc((n + 1) * sizeof(uint32_t));
+	uint64_t carry = 0;
+	for (i = 0; i < n; i++) {
+		carry += (uint64_t)(i < alen ? a[i] : 0) + (i < blen ? b[i] : 0);
+		sum[i] = (uint32_t)carry;
+		carry >>= 32;
+	}
+	sum[n] = (uint32_t)carry;
+	*len = n + 1;
+	return sum;
+}
+
+/* subtracts b from a, where a is at least as large as b */
+static uint32_t *sf_mag_sub(const uint32_t *a, size_t alen, const uint32_t *b, size_t blen)
+{
+	uint32_t *difference = sf_alloc(alen * sizeof(uint32_t));
+	int64_t borrow = 0;
+	size_t i;
+	for (i = 0; i < alen; i++) {
+		int64_t d = (int64_t)a[i] - (i < blen ? b[i] : 0) - borrow;
+		borrow = d < 0;
+		difference[i] = (uint32_t)(d + (borrow ? ((int64_t)1 << 32) : 0));
+	}
+	return difference;
+}
+
+static sf_value sf_int_signed_add(sf_value a, int bnegative, sf_value b)
+{
+	size_t alen = a->as.integer.len, blen = b->as.integer.len, len;
+	const uint32_t *al = a->as.integer.limbs, *bl = b->as.integer.limbs;
+	if (a->as.integer.negative == bnegative) {
+		uint32_t *sum = sf_mag_add(al, alen, bl, blen, &len);
+		return sf_int_new(bnegative, len, sum);
+	}
+	if (sf_mag_cmp(al, alen, bl, blen) >= 0)
+		return sf_int_new(a->as.integer.negative, alen, sf_mag_sub(al, alen, bl, blen));
+	return sf_int_new(bnegative, blen, sf_mag_sub(bl, blen, al, alen));
+}
+
+static sf_value sf_int_add(sf_value a, sf_value b)
+{
+	return sf_int_signed_add(a, b->as.integer.negative, b);
+}
+
+static sf_value sf_int_sub(sf_value a, sf_value b)
+{
+	return sf_int_signed_add(a, !b->as.integer.negative, b);
+}
+
+static sf_value sf_int_mul(sf_value a, sf_value b)
+{
+	size_t alen = a->as.integer.len, blen = b->as.integer.len, i, j;
+	uint32_t *product = sf_alloc((alen + blen) * sizeof(uint32_t));
+	memset(product, 0, (alen + blen) * sizeof(uint32_t));
+	for (i = 0; i < alen; i++) {
+		uint64_t carry = 0;
+		for (j = 0; j < blen; j++) {
+			carry += (uint64_t)a->as.integer.limbs[i] * b->as.integer.limbs[j] + product[i + j];
+			product[i + j] = (uint32_t)carry;
+			carry >>= 32;
+		}
+		product[i + blen] = (uint32_t)carry;
+	}
+	return sf_int_new(a->as.integer.negative != b->as.integer.negative, alen + blen, product);
+}
+
+/* divides a magnitude by a small divisor in place, returning the remainder */
+static uint32_t sf_mag_div_small(uint32_t *limbs, size_t len, uint32_t divisor)
+{
+	uint64_t remainder = 0;
+	size_t i;
+	for (i = len; i-- > 0;) {
+		remainder = (remainder << 32) | limbs[i];
+		limbs[i] = (uint32_t)(remainder / divisor);
+		remainder %= divisor;
+	}
+	return (uint32_t)remainder;
+}
+
+/* truncating division, as done by the interpreter */
+static sf_value sf_int_div(sf_value a, sf_value b)
+{
+	size_t alen = a->as.integer.len, blen = b->as.integer.len, rlen = 0, i;
+	uint32_t *quotient, *remainder;
+	if (!blen)
+		sf_fail("Division by zero");
+
+	/* shift the bits of the dividend into the remainder one at a time, subtracting the divisor
+	 * whenever possible */
+	quotient = sf_alloc((alen + 1) * sizeof(uint32_t));
+	memset(quotient, 0, (alen + 1) * sizeof(uint32_t));
+	remainder = sf_alloc((blen + 1) * sizeof(uint32_t));
+	for (i = alen * 32; i-- > 0;) {
+		uint32_t bit = (a->as.integer.limbs[i / 32] >> (i % 32)) & 1;
+		sf_mag_mul_add(remainder, &rlen, 2, bit);
+		if (sf_mag_cmp(remainder, rlen, b->as.integer.limbs, blen) >= 0) {
+			uint32_t *difference = sf_mag_sub(remainder, rlen, b->as.integer.limbs, blen);
+			memcpy(remainder, difference, rlen * sizeof(uint32_t));
+			free(difference);
+			while (rlen && !remainder[rlen - 1])
+				rlen--;
+			quotient[i / 32] |= (uint32_t)1 << (i % 32);
+		}
+	}
+	free(remainder);
+	return sf_int_new(a->as.integer.negative != b->as.integer.negative, alen, quotient);
+}
+
+static int sf_int_cmp(sf_value a, sf_value b)
+{
+	int negative = a->as.integer.negative, magnitude;
+	if (negative != b->as.integer.negative)
+		return negative ? -1 : 1;
+	magnitude = sf_mag_cmp(a->as.integer.limbs, a->as.integer.len, b->as.integer.limbs,
+			       b->as.integer.len);
+	return negative ? -magnitude : magnitude;
+}
+
+/* converts an integer to an index, returning zero if it can't be one */
+static int sf_int_index(sf_value value, size_t *index)
+{
+	size_t i = value->as.integer.len;
+	uint64_t n = 0;
+	if (value->as.integer.negative || i > 2)
+		return 0;
+	while (i-- > 0)
+		n = (n << 32) | value->as.integer.limbs[i];
+	if (n > SIZE_MAX)
+		return 0;
+	*index = (size_t)n;
+	return 1;
+}
+
+static void sf_int_print(FILE *out, sf_value value)
+{
+	size_t len = value->as.integer.len, count = 0;
+	uint32_t *limbs;
+	uint32_t *chunks = sf_alloc((len * 10 / 9 + 1) * sizeof(uint32_t));
+
+	/* peel off nine decimal digits at a time */
+	limbs = sf_alloc((len ? len : 1) * sizeof(uint32_t));
+	if (len)
+		memcpy(limbs, value->as.integer.limbs, len * sizeof(uint32_t));
+	do {
+		chunks[count++] = sf_mag_div_small(limbs, len, 1000000000);
+		while (len && !limbs[len - 1])
+			len--;
+	} while (len);
+
+	if (value->as.integer.negative)
+		fputc('-', out);
+	fprintf(out, "%lu", (unsigned long)chunks[--count]);
+	while (count-- > 0)
+		fprintf(out, "%09lu", (unsigned long)chunks[count]);
+	free(limbs);
+	free(chunks);
+}
+
+/* other values */
+
+static sf_value sf_string(const char *string)
+{
+	sf_value value = sf_new(SF_STRING);
+	value->as.string = string;
+	return value;
+}
+
+static int sf_string_eq(sf_value value, const char *string)
+{
+	return strcmp(value->as.string, string) == 0;
+}
+
+static sf_value sf_bool(int boolean)
+{
+	sf_value value = sf_new(SF_BOOL);
+	value->as.boolean = boolean;
+	return value;
+}
+
+static sf_value sf_unit(void)
+{
+	static struct sf_object unit = {SF_UNIT, {{0, 0, 0}}};
+	return &unit;
+}
+
+static sf_value sf_list(size_t len, const sf_value *items)
+{
+	sf_value value = sf_new(SF_LIST);
+	value->as.list.len = len;
+	value->as.list.items = sf_copy(len, items);
+	return value;
+}
+
+static sf_value sf_variant(const char *name, size_t len, const sf_value *fields)
+{
+	sf_value value = sf_new(SF_VARIANT);
+	value->as.variant.name = name;
+	value->as.variant.len = len;
+	value->as.variant.fields = sf_copy(len, fields);
+	return value;
+}
+
+static int sf_is_variant(sf_value value, const char *name)
+{
+	return value->kind == SF_VARIANT && strcmp(value->as.variant.name, name) == 0;
+}
+
+static sf_value sf_function(const char *name, size_t arity, sf_thunk call)
+{
+	sf_value value = sf_new(SF_FUNCTION);
+	value->as.function.name = name;
+	value->as.function.arity = arity;
+	value->as.function.call = call;
+	return value;
+}
+
+static sf_value sf_call(sf_value function, size_t argc, sf_value *args)
+{
+	if (function->kind != SF_FUNCTION || function->as.function.arity != argc)
+		sf_fail("Invalid call to a function reference");
+	return function->as.function.call(args);
+}
+
+/* builtins */
+
+static sf_value sf_println(sf_value value)
+{
+	puts(value->as.string);
+	return sf_unit();
+}
+
+static sf_value sf_println_empty(void)
+{
+	putchar('\n');
+	return sf_unit();
+}
+
+static sf_value sf_len(sf_value list)
+{
+	return sf_int_from((long long)list->as.list.len);
+}
+
+static sf_value sf_get(sf_value list, sf_value index)
+{
+	size_t i;
+	if (!sf_int_index(index, &i) || i >= list->as.list.len) {
+		fputs("Error!: Index ", stderr);
+		sf_int_print(stderr, index);
+		fprintf(stderr, " is out of bounds for a list of length %lu\n",
+			(unsigned long)list->as.list.len);
+		exit(1);
+	}
+	return list->as.list.items[i];
+}
+
+static sf_value sf_push(sf_value list, sf_value item)
+{
+	size_t len = list->as.list.len;
+	sf_value value = sf_list(len, list->as.list.items);
+	value->as.list.items = realloc(value->as.list.items, (len + 1) * sizeof(sf_value));
+	if (!value->as.list.items)
+		sf_fail("Out of memory");
+	value->as.list.items[len] = item;
+	value->as.list.len = len + 1;
+	return value;
+}
+
+static sf_value sf_concat(sf_value xs, sf_value ys)
+{
+	size_t xlen = xs->as.list.len, ylen = ys->as.list.len;
+	sf_value *items = sf_alloc((xlen + ylen) * sizeof(sf_value));
+	sf_value value;
+	if (xlen)
+		memcpy(items, xs->as.list.items, xlen * sizeof(sf_value));
+	if (ylen)
+		memcpy(items + xlen, ys->as.list.items, ylen * sizeof(sf_value));
+	value = sf_list(xlen + ylen, items);
+	free(items);
+	return value;
+}
+
+static sf_value sf_map(sf_value list, sf_value function)
+{
+	size_t len = list->as.list.len, i;
+	sf_value value = sf_list(len, list->as.list.items);
+	for (i = 0; i < len; i++)
+		value->as.list.items[i] = sf_call(function, 1, &list->as.list.items[i]);
+	return value;
+}
+
+static sf_value sf_fold(sf_value list, sf_value init, sf_value function)
+{
+	sf_value args[2];
+	size_t i;
+	args[0] = init;
+	for (i = 0; i < list->as.list.len; i++) {
+		args[1] = list->as.list.items[i];
+		args[0] = sf_call(function, 2, args);
+	}
+	return args[0];
+}
+
+/* displays a value the same way the interpreter does. nested strings are quoted and nested
+ * variants with fields are parenthesized */
+static void sf_show(FILE *out, sf_value value, int nested)
+{
+	size_t i;
+	switch (value->kind) {
+	case SF_INT:
+		sf_int_print(out, value);
+		break;
+	case SF_STRING:
+		if (!nested) {
+			fputs(value->as.string, out);
+			break;
+		}
+		fputc('"', out);
+		for (i = 0; value->as.string[i]; i++) {
+			char c = value->as.string[i];
+			if (c == '"' || c == '\\')
+				fputc('\\', out);
+			fputc(c, out);
+		}
+		fputc('"', out);
+		break;
+	case SF_BOOL:
+		fputs(value->as.boolean ? "true" : "false", out);
+		break;
+	case SF_LIST:
+		fputc('[', out);
+		for (i = 0; i < value->as.list.len; i++) {
+			if (i)
+				fputs(", ", out);
+			sf_show(out, value->as.list.items[i], 1);
+		}
+		fputc(']', out);
+		break;
+	case SF_VARIANT:
+		if (nested && value->as.variant.len)
+			fputc('(', out);
+		fputs(value->as.variant.name, out);
+		for (i = 0; i < value->as.variant.len; i++) {
+			fputc(' ', out);
+			sf_show(out, value->as.variant.fields[i], 1);
+		}
+		if (nested && value->as.variant.len)
+			fputc(')', out);
+		break;
+	case SF_FUNCTION:
+		fprintf(out, "<fn %s>", value->as.function.name);
+		break;
+	case SF_UNIT:
+		fputs("()", out);
+		break;
+	}
+}
+
+#endif
@@ -0,0 +1,689 @@
+// a compiler backend emitting a single c99 translation unit
+//
+// every value is an `sf_value` provided by the runtime in `runtime/snowflake.h`, which is included
+// verbatim at the top of the output so that it can be built with nothing more than a c compiler.
+// functions are named after their binding and primary tag, see `mangle`
+
+use fractal::{builtins::LIST_BUILTINS, unquote, FractalError, UniverseItem};
+use parser::ast::{Expression, OpSymbol, Pattern};
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::TryFrom,
+    fmt::Write,
+};
+use tag::TagName;
+
+/// the runtime included in every translation unit
+pub const RUNTIME: &str = include_str!("../runtime/snowflake.h");
+
+/// the argument passed to `main` by the entry point, matching the one passed by flurry
+const ENTRY_ARGUMENT: i64 = 69;
+
+/// the c expressions holding the values of the local bindings in scope
+type Scope = HashMap<String, String>;
+
+/// a function's name, arguments and body
+type FunctionDecl<'f> = (&'f str, &'f [String], &'f [Box<Expression>]);
+
+/// derives the name of the c function for a binding from its name and primary tag. each part is
+/// prefixed by its length so that distinct bindings can never share a name
+pub fn mangle(tag: Option<&str>, name: &str) -> String {
+    match tag {
+        Some(tag) => format!("sf_{}{}{}{}", tag.len(), tag, name.len(), name),
+        None => format!("sf_{}{}", name.len(), name),
+    }
+}
+
+/// compiles every function in a universe, along with the constructors they refer to, to a c
+/// translation unit. if there is a `main` function, a c `main` calling it is included
+pub fn compile(bindings: &[(&str, &[TagName<'_>], &UniverseItem)]) -> Result<String, FractalError> {
+    let mut codegen = Codegen {
+        items: bindings
+            .iter()
+            .map(|(name, _, item)| (*name, *item))
+            .collect(),
+        names: bindings
+            .iter()
+            .map(|(name, tags, _)| {
+                let tag = tags.iter().find_map(|tag| match tag {
+                    TagName::Primary(tag) => Some(tag.as_ref()),
+                    _ => None,
+                });
+                (*name, mangle(tag, name))
+            })
+            .collect(),
+        references: BTreeSet::new(),
+        body: String::new(),
+        next_temporary: 0,
+        next_match: 0,
+    };
+
+    let mut functions: Vec<FunctionDecl> = bindings
+        .iter()
+        .filter_map(|(name, _, item)| match item {
+            UniverseItem::FnDecl { args, body, .. } => {
+                Some((*name, args.as_slice(), body.as_slice()))
+            }
+            _ => None,
+        })
+        .collect();
+    functions.sort_unstable_by_key(|(name, _, _)| *name);
+
+    let mut prototypes = String::new();
+    for (name, args, body) in &functions {
+        let _ = writeln!(prototypes, "{};", codegen.signature(name, args));
+        codegen.function(name, args, body)?;
+    }
+    let thunks = codegen.thunks()?;
+    for name in &codegen.references {
+        let _ = writeln!(
+            prototypes,
+            "static sf_value {}_thunk(sf_value *args);",
+            codegen.names[name.as_str()]
+        );
+    }
+
+    let mut out = String::from(RUNTIME);
+    let _ = write!(out, "\n{}\n{}{}", prototypes, codegen.body, thunks);
+    if let Some(UniverseItem::FnDecl { args, .. }) = codegen.items.get("main") {
+        let arg = match args.len() {
+            0 => String::new(),
+            1 => format!("sf_int_from({})", ENTRY_ARGUMENT),
+            _ => {
+                return Err(FractalError::Unsupported(String::from(
+                    "`main` taking more than one argument",
+                )))
+            }
+        };
+        let _ = write!(
+            out,
+            "int main(void)\n{{\n\t{}({});\n\treturn 0;\n}}\n",
+            codegen.names["main"], arg
+        );
+    }
+    Ok(out)
+}
+
+struct Codegen<'c> {
+    items: HashMap<&'c str, &'c UniverseItem>,
+
+    /// the c names of every binding
+    names: HashMap<&'c str, String>,
+
+    /// the functions and constructors used as values, each of which needs a thunk
+    references: BTreeSet<String>,
+    body: String,
+
+    // the state of the function being emitted
+    next_temporary: usize,
+    next_match: usize,
+}
+
+impl<'c> Codegen<'c> {
+    fn signature(&self, name: &str, args: &[String]) -> String {
+        let params: Vec<String> = args
+            .iter()
+            .map(|arg| format!("sf_value v_{}", arg))
+            .collect();
+        format!(
+            "static sf_value {}({})",
+            self.names[name],
+            if params.is_empty() {
+                String::from("void")
+            } else {
+                params.join(", ")
+            }
+        )
+    }
+
+    fn function(
+        &mut self,
+        name: &str,
+        args: &[String],
+        body: &[Box<Expression>],
+    ) -> Result<(), FractalError> {
+        self.next_temporary = 0;
+        self.next_match = 0;
+
+        let scope: Scope = args
+            .iter()
+            .map(|arg| (arg.clone(), format!("v_{}", arg)))
+            .collect();
+
+        let _ = writeln!(self.body, "{}\n{{", self.signature(name, args));
+        let value = self.block(&scope, body)?;
+        self.emit(format!("return {};", value));
+        self.body.push_str("}\n\n");
+        Ok(())
+    }
+
+    /// emits the thunks of every function and constructor used as a value, which unpack an array
+    /// of arguments
+    fn thunks(&self) -> Result<String, FractalError> {
+        let mut out = String::new();
+        for name in &self.references {
+            let _ = writeln!(
+                out,
+                "static sf_value {}_thunk(sf_value *args)\n{{",
+                self.names[name.as_str()]
+            );
+            match self.items.get(name.as_str()) {
+                Some(UniverseItem::FnDecl { args, .. }) => {
+                    let args: Vec<String> =
+                        (0..args.len()).map(|i| format!("args[{}]", i)).collect();
+                    let _ = writeln!(
+                        out,
+                        "\treturn {}({});",
+                        self.names[name.as_str()],
+                        args.join(", ")
+                    );
+                }
+                Some(UniverseItem::Constructor { fields, .. }) => {
+                    let _ = writeln!(
+                        out,
+                        "\treturn sf_variant({}, {}, args);",
+                        string(name),
+                        fields.len()
+                    );
+                }
+                _ => return Err(FractalError::UnboundName(name.clone())),
+            }
+            out.push_str("}\n\n");
+        }
+        Ok(out)
+    }
+
+    /// emits a block, returning the value of its last expression
+    fn block(&mut self, scope: &Scope, body: &[Box<Expression>]) -> Result<String, FractalError> {
+        let mut value = String::from("sf_unit()");
+        for expr in body {
+            value = self.expression(scope, expr)?;
+        }
+        Ok(value)
+    }
+
+    /// emits an expression, returning a c expression holding its value
+    fn expression(&mut self, scope: &Scope, expr: &Expression) -> Result<String, FractalError> {
+        let value = match expr {
+            // the most negative integer can't be written as a literal in c
+            Expression::Integer(int) => match i64::try_from(int) {
+                Ok(int) if int != i64::MIN => format!("sf_int_from({}LL)", int),
+                _ => format!("sf_int_parse(\"{}\")", int),
+            },
+            Expression::StringLiteral(literal) => {
+                format!("sf_string({})", string(unquote(literal)))
+            }
+            Expression::TypeDecl { expr, .. } => return self.expression(scope, expr),
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(value) => return Ok(value.clone()),
+                None => match self.items.get(name.as_str()) {
+                    Some(UniverseItem::Constructor { fields, .. }) if fields.is_empty() => {
+                        format!("sf_variant({}, 0, NULL)", string(name))
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) if args.is_empty() => {
+                        format!("{}()", self.names[name.as_str()])
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) => {
+                        self.references.insert(name.clone());
+                        format!(
+                            "sf_function({}, {}, {}_thunk)",
+                            string(name),
+                            args.len(),
+                            self.names[name.as_str()]
+                        )
+                    }
+                    Some(UniverseItem::Constructor { fields, .. }) => {
+                        self.references.insert(name.clone());
+                        format!(
+                            "sf_function({}, {}, {}_thunk)",
+                            string(name),
+                            fields.len(),
+                            self.names[name.as_str()]
+                        )
+                    }
+                    _ => return Err(FractalError::UnboundName(name.clone())),
+                },
+            },
+            Expression::List(items) => {
+                let items = self.expressions(scope, items.iter().map(|item| &**item))?;
+                self.array("sf_list", "", items)
+            }
+            Expression::OpCall { op, args } => match args.as_slice() {
+                [l, r] => {
+                    let l = self.expression(scope, l)?;
+                    let r = self.expression(scope, r)?;
+                    match op {
+                        OpSymbol::Plus => format!("sf_int_add({}, {})", l, r),
+                        OpSymbol::Minus => format!("sf_int_sub({}, {})", l, r),
+                        OpSymbol::Star => format!("sf_int_mul({}, {})", l, r),
+                        OpSymbol::ForwardSlash => format!("sf_int_div({}, {})", l, r),
+                        OpSymbol::LAngleBracket => format!("sf_bool(sf_int_cmp({}, {}) < 0)", l, r),
+                        OpSymbol::RAngleBracket => format!("sf_bool(sf_int_cmp({}, {}) > 0)", l, r),
+                        _ => {
+                            return Err(FractalError::Unsupported(format!(
+                                "the `{}` operator at runtime",
+                                op
+                            )))
+                        }
+                    }
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("operator `{}` applied to {} operands", op, args.len()),
+            },
+            Expression::FnCall { name, args } => self.call(scope, name, args)?,
+            Expression::Match { expr, args } => return self.match_expression(scope, expr, args),
+            _ => {
+                return Err(FractalError::Unsupported(format!(
+                    "compiling {:?} to c",
+                    expr
+                )))
+            }
+        };
+
+        // every value is bound to a temporary so that expressions are evaluated in order
+        let temporary = self.temporary();
+        self.emit(format!("sf_value {} = {};", temporary, value));
+        Ok(temporary)
+    }
+
+    fn expressions<'e, I>(&mut self, scope: &Scope, exprs: I) -> Result<Vec<String>, FractalError>
+    where
+        I: IntoIterator<Item = &'e Expression>,
+    {
+        exprs
+            .into_iter()
+            .map(|expr| self.expression(scope, expr))
+            .collect()
+    }
+
+    /// builds a call to a runtime function taking an array of values. `prefix` holds any
+    /// arguments coming before the length of the array
+    fn array(&mut self, function: &str, prefix: &str, values: Vec<String>) -> String {
+        if values.is_empty() {
+            return format!("{}({}0, NULL)", function, prefix);
+        }
+        let array = self.temporary();
+        self.emit(format!("sf_value {}[] = {{{}}};", array, values.join(", ")));
+        format!("{}({}{}, {})", function, prefix, values.len(), array)
+    }
+
+    fn call(
+        &mut self,
+        scope: &Scope,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<String, FractalError> {
+        let values = self.expressions(scope, args)?;
+
+        // builtins take precedence, as they do in the interpreter
+        if name == "println" {
+            return Ok(match values.as_slice() {
+                [] => String::from("sf_println_empty()"),
+                [value] => format!("sf_println({})", value),
+                _ => {
+                    return Err(FractalError::ArgumentCount {
+                        name: String::from(name),
+                        expected: 1,
+                        found: values.len(),
+                    })
+                }
+            });
+        }
+        if LIST_BUILTINS.contains(&name) {
+            return Ok(format!("sf_{}({})", name, values.join(", ")));
+        }
+
+        if let Some(function) = scope.get(name) {
+            let function = function.clone();
+            return Ok(self.array("sf_call", &format!("{}, ", function), values));
+        }
+        match self.items.get(name) {
+            Some(UniverseItem::Constructor { .. }) => {
+                Ok(self.array("sf_variant", &format!("{}, ", string(name)), values))
+            }
+            Some(UniverseItem::FnDecl { .. }) => {
+                Ok(format!("{}({})", self.names[name], values.join(", ")))
+            }
+            _ => Err(FractalError::UnboundName(String::from(name))),
+        }
+    }
+
+    /// emits a match expression, testing each arm in turn and assigning the value of the first
+    /// one matching to a temporary
+    fn match_expression(
+        &mut self,
+        scope: &Scope,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Result<String, FractalError> {
+        let scrutinee = self.expression(scope, expr)?;
+        let id = self.next_match;
+        self.next_match += 1;
+
+        let result = self.temporary();
+        self.emit(format!("sf_value {};", result));
+        for (i, arm) in arms.iter().enumerate() {
+            match arm {
+                Expression::Destructure { pat, body } => {
+                    // a failed test moves on to the next arm
+                    let fail = if i + 1 < arms.len() {
+                        format!("match{}_arm{}", id, i + 1)
+                    } else {
+                        format!("match{}_none", id)
+                    };
+
+                    // arms bind into their own scope, which is discarded afterwards
+                    let mut arm_scope = scope.clone();
+                    self.pattern(&mut arm_scope, pat, &scrutinee, &fail)?;
+                    let value = self.block(&arm_scope, body)?;
+                    self.emit(format!("{} = {};", result, value));
+                    self.emit(format!("goto match{}_end;", id));
+
+                    // labels are followed by an empty statement, as declarations may follow them
+                    let _ = writeln!(self.body, "{}:;", fail);
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("unexpected match arm: {:?}", arm),
+            }
+        }
+        self.emit("sf_no_match();");
+        let _ = writeln!(self.body, "match{}_end:;", id);
+        Ok(result)
+    }
+
+    /// emits the tests of a pattern against a value, binding any identifiers in it. failed tests
+    /// jump to the `fail` label
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        pat: &Pattern,
+        value: &str,
+        fail: &str,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) => match self.items.get(name.as_str()) {
+                Some(UniverseItem::Constructor { .. }) => {
+                    self.test(format!("sf_is_variant({}, {})", value, string(name)), fail);
+                }
+                _ => {
+                    scope.insert(name.clone(), String::from(value));
+                }
+            },
+            Pattern::Integer(int) => {
+                let int = self.expression(scope, &Expression::Integer(int.clone()))?;
+                self.test(format!("sf_int_cmp({}, {}) == 0", value, int), fail);
+            }
+            Pattern::StringLiteral(literal) => {
+                self.test(
+                    format!("sf_string_eq({}, {})", value, string(unquote(literal))),
+                    fail,
+                );
+            }
+            Pattern::Char(_) => {
+                return Err(FractalError::Unsupported(String::from(
+                    "chars when compiling to c",
+                )))
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let start = self.range_bound(scope, start)?;
+                    self.test(format!("sf_int_cmp({}, {}) >= 0", value, start), fail);
+                }
+                if let Some(end) = end {
+                    let end = self.range_bound(scope, end)?;
+                    self.test(format!("sf_int_cmp({}, {}) < 0", value, end), fail);
+                }
+            }
+            Pattern::Constructor { name, args } => {
+                self.test(format!("sf_is_variant({}, {})", value, string(name)), fail);
+                for (i, arg) in args.iter().enumerate() {
+                    if let Pattern::Wildcard = arg {
+                        continue;
+                    }
+
+                    let field = self.temporary();
+                    self.emit(format!(
+                        "sf_value {} = {}->as.variant.fields[{}];",
+                        field, value, i
+                    ));
+                    self.pattern(scope, arg, &field, fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn range_bound(&mut self, scope: &Scope, bound: &Pattern) -> Result<String, FractalError> {
+        match bound {
+            Pattern::Integer(int) => self.expression(scope, &Expression::Integer(int.clone())),
+            _ => Err(FractalError::Unsupported(format!(
+                "the range bound `{}`",
+                bound
+            ))),
+        }
+    }
+
+    /// emits a jump to `fail` taken when a condition doesn't hold
+    fn test(&mut self, condition: String, fail: &str) {
+        self.emit(format!("if (!({})) goto {};", condition, fail));
+    }
+
+    fn temporary(&mut self) -> String {
+        let temporary = format!("t{}", self.next_temporary);
+        self.next_temporary += 1;
+        temporary
+    }
+
+    fn emit<S: AsRef<str>>(&mut self, statement: S) {
+        let _ = writeln!(self.body, "\t{}", statement.as_ref());
+    }
+}
+
+/// writes a c string literal
+fn string(string: &str) -> String {
+    let mut literal = String::with_capacity(string.len() + 2);
+    literal.push('"');
+    for byte in string.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            // question marks are escaped to avoid forming trigraphs
+            b'?' => literal.push_str("\\?"),
+            0x20..=0x7e => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fractal::{Evaluator, EvaluatorConfig, Value};
+    use indoc::indoc;
+    use num_bigint::BigInt;
+    use parser::lexer;
+    use std::{borrow::Cow, env, fs, process::Command};
+
+    fn populate(source: &str) -> Evaluator<'static> {
+        let mut file_tags = HashMap::new();
+        file_tags.insert(String::from("test.sf"), Vec::new());
+
+        let mut evaluator = Evaluator::new(EvaluatorConfig {
+            project_tag: TagName::Primary(Cow::Borrowed("test")),
+            file_tags,
+        });
+
+        let mut files = HashMap::new();
+        files.insert(
+            String::from("test.sf"),
+            parser::parse(lexer::lex(source)).unwrap(),
+        );
+        evaluator.populate(&files).unwrap();
+        evaluator
+    }
+
+    /// builds and runs a translation unit, returning its output. `None` is returned if there is
+    /// no c compiler to build it with
+    fn run(name: &str, source: &str) -> Option<String> {
+        if Command::new("cc").arg("--version").output().is_err() {
+            eprintln!("cc is missing, skipping");
+            return None;
+        }
+
+        let dir = env::temp_dir().join(format!("codegen-c-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.c");
+        let binary = dir.join("main");
+        fs::write(&file, source).unwrap();
+
+        let output = Command::new("cc")
+            .args(["-std=c99", "-pedantic", "-Werror", "-o"])
+            .arg(&binary)
+            .arg(&file)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let output = Command::new(&binary).output().unwrap();
+        assert!(output.status.success());
+        fs::remove_dir_all(&dir).unwrap();
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    /// writes a c expression building an argument
+    fn argument(value: &Value) -> String {
+        match value {
+            Value::Int(int) => format!("sf_int_parse(\"{}\")", int),
+            Value::String(s) => format!("sf_string({})", string(s)),
+            // TODO(superwhiskers): remove panic
+            _ => panic!("unsupported argument {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_mangle() {
+        assert_eq!(mangle(Some("hello"), "main"), "sf_5hello4main");
+        assert_eq!(mangle(None, "main"), "sf_4main");
+        assert_ne!(mangle(Some("ab"), "c"), mangle(Some("a"), "bc"));
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        let mut evaluator = populate(indoc! {"
+            type shape = circle ilarge | rect ilarge ilarge | empty
+            size :: tag nothing
+            size :: ilarge -> shape
+            size n => match n =>
+              0 => empty
+              1..10 => circle n
+              _ => rect n 2
+
+
+            area :: tag nothing
+            area :: shape -> ilarge
+            area s => match s =>
+              circle r => 3 * r * r
+              rect w h => w * h
+              empty => 0
+
+
+            double :: tag nothing
+            double :: ilarge -> ilarge
+            double n => n * 2
+
+
+            add :: tag nothing
+            add :: ilarge ilarge -> ilarge
+            add a b => a + b
+
+
+            shapes :: tag nothing
+            shapes :: ilarge -> list 3 shape
+            shapes n => map [n, n + 1, n + 20] size
+
+
+            total :: tag nothing
+            total :: ilarge -> ilarge
+            total a => fold (map (map (shapes a) area) double) (get [a, 1] 1) add
+
+
+            big :: tag nothing
+            big :: ilarge -> ilarge
+            big n => (n * 100000000000000000000000 - 3) / 7 - n
+
+
+            name :: tag nothing
+            name :: string -> list 2 string
+            name s => match s =>
+              \"bar\" => push [s] \"is bar\"
+              _ => concat [\"not bar:\"] [s]
+        "});
+        let bindings = evaluator.bindings();
+        let mut source = compile(&bindings).unwrap();
+
+        // call each function from a harness, printing the results
+        let mut calls = Vec::new();
+        for n in &[0, 1, 5, 9, 10, 30, -12345] {
+            for function in &["size", "shapes", "total", "big"] {
+                calls.push((*function, vec![Value::Int(BigInt::from(*n))]));
+            }
+        }
+        calls.push(("name", vec![Value::from("bar")]));
+        calls.push(("name", vec![Value::from("baz")]));
+        calls.push((
+            "big",
+            vec!["-98765432109876543210".parse::<BigInt>().unwrap().into()],
+        ));
+
+        source.push_str("int main(void)\n{\n");
+        for (function, args) in &calls {
+            let args: Vec<String> = args.iter().map(argument).collect();
+            let _ = writeln!(
+                source,
+                "\tsf_show(stdout, {}({}), 0);\n\tputchar('\\n');",
+                mangle(Some("test"), function),
+                args.join(", ")
+            );
+        }
+        source.push_str("\treturn 0;\n}\n");
+
+        let output = match run("interpreter", &source) {
+            Some(output) => output,
+            None => return,
+        };
+        let mut lines = output.lines();
+        for (function, args) in calls {
+            let entry = evaluator.entry(function).unwrap().clone();
+            let expected = evaluator.eval(&entry, args.clone()).unwrap().to_string();
+            assert_eq!(
+                lines.next(),
+                Some(expected.as_str()),
+                "{} {:?}",
+                function,
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_println() {
+        let evaluator = populate(include_str!("../../../examples/question.sf"));
+        let output = match run("println", &compile(&evaluator.bindings()).unwrap()) {
+            Some(output) => output,
+            None => return,
+        };
+        assert_eq!(output, "pick one:\nbar\nbaz\nqux\n");
+    }
+}
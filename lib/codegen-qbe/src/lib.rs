@@ -0,0 +1,584 @@
+// a compiler backend emitting the intermediate language of qbe (https://c9x.me/compile)
+//
+// every value is lowered to a 64-bit `l`: integers are stored directly (wrapping instead of growing
+// like they do when interpreted), strings are pointers to null-terminated data and variants are
+// pointers to heap blocks holding the index of their constructor followed by their fields
+//
+// programs are expected to have been type checked already, so no types are checked here
+
+use fractal::{unquote, FractalError, UniverseItem};
+use parser::ast::{Expression, OpSymbol, Pattern};
+use std::{collections::HashMap, convert::TryFrom, fmt::Write};
+
+/// the argument passed to `main` by the entry point, matching the one passed by flurry
+const ENTRY_ARGUMENT: i64 = 69;
+
+/// the operands holding the values of the local bindings in scope
+type Scope = HashMap<String, String>;
+
+/// compiles every function in a universe to a qbe module, in order of their names. if there is a
+/// `main` function, an exported entry point calling it is included
+pub fn compile(items: &HashMap<&str, &UniverseItem>) -> Result<String, FractalError> {
+    let mut names: Vec<&str> = items
+        .iter()
+        .filter(|(_, item)| matches!(item, UniverseItem::FnDecl { .. }))
+        .map(|(name, _)| *name)
+        .collect();
+    names.sort_unstable();
+
+    let mut codegen = Codegen {
+        items,
+        strings: Vec::new(),
+        out: String::new(),
+        next_temporary: 0,
+        next_label: 0,
+        next_match: 0,
+        block: String::new(),
+    };
+    for name in names {
+        if let UniverseItem::FnDecl { args, body, .. } = items[name] {
+            codegen.function(name, args, body)?;
+        }
+    }
+    codegen.entry()?;
+    codegen.data();
+
+    Ok(codegen.out)
+}
+
+struct Codegen<'c> {
+    items: &'c HashMap<&'c str, &'c UniverseItem>,
+
+    /// the string constants used, each emitted as a data definition named by its index
+    strings: Vec<String>,
+    out: String,
+
+    // the state of the function being emitted
+    next_temporary: usize,
+    next_label: usize,
+    next_match: usize,
+
+    /// the label of the block being emitted
+    block: String,
+}
+
+impl<'c> Codegen<'c> {
+    fn function(
+        &mut self,
+        name: &str,
+        args: &[String],
+        body: &[Box<Expression>],
+    ) -> Result<(), FractalError> {
+        self.next_temporary = 0;
+        self.next_label = 0;
+        self.next_match = 0;
+
+        let scope: Scope = args
+            .iter()
+            .map(|arg| (arg.clone(), format!("%{}", arg)))
+            .collect();
+
+        let params: Vec<String> = args.iter().map(|arg| format!("l %{}", arg)).collect();
+        let _ = writeln!(
+            self.out,
+            "function l $sf_{}({}) {{",
+            name,
+            params.join(", ")
+        );
+        self.label("start");
+        let value = self.block(&scope, body)?;
+        self.emit(format!("ret {}", value));
+        self.out.push_str("}\n\n");
+        Ok(())
+    }
+
+    /// emits the exported entry point, which calls `main` and exits successfully
+    fn entry(&mut self) -> Result<(), FractalError> {
+        let args = match self.items.get("main") {
+            Some(UniverseItem::FnDecl { args, .. }) => match args.len() {
+                0 => String::new(),
+                1 => format!("l {}", ENTRY_ARGUMENT),
+                _ => {
+                    return Err(FractalError::Unsupported(String::from(
+                        "`main` taking more than one argument",
+                    )))
+                }
+            },
+            _ => return Ok(()),
+        };
+
+        self.out.push_str("export function w $main() {\n");
+        self.label("start");
+        self.emit(format!("call $sf_main({})", args));
+        self.emit("ret 0");
+        self.out.push_str("}\n\n");
+        Ok(())
+    }
+
+    /// emits the data definitions of every string constant
+    fn data(&mut self) {
+        for (i, string) in self.strings.iter().enumerate() {
+            let _ = writeln!(
+                self.out,
+                "data $str.{} = {{ b \"{}\", b 0 }}",
+                i,
+                escape(string)
+            );
+        }
+    }
+
+    /// emits a block, returning the value of its last expression
+    fn block(&mut self, scope: &Scope, body: &[Box<Expression>]) -> Result<String, FractalError> {
+        let mut value = String::from("0");
+        for expr in body {
+            value = self.expression(scope, expr)?;
+        }
+        Ok(value)
+    }
+
+    /// emits an expression, returning the operand holding its value
+    fn expression(&mut self, scope: &Scope, expr: &Expression) -> Result<String, FractalError> {
+        Ok(match expr {
+            Expression::Integer(int) => integer(int)?.to_string(),
+            Expression::StringLiteral(string) => self.string(unquote(string)),
+            Expression::TypeDecl { expr, .. } => self.expression(scope, expr)?,
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(value) => value.clone(),
+                None => match self.items.get(name.as_str()) {
+                    Some(UniverseItem::Constructor { fields, .. }) if fields.is_empty() => {
+                        self.construct(name, vec![])?
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) if args.is_empty() => {
+                        self.call(scope, name, &[])?
+                    }
+                    Some(UniverseItem::Constructor { .. }) | Some(UniverseItem::FnDecl { .. }) => {
+                        return Err(FractalError::Unsupported(String::from(
+                            "function references when compiling to qbe",
+                        )))
+                    }
+                    _ => return Err(FractalError::UnboundName(name.clone())),
+                },
+            },
+            Expression::OpCall { op, args } => match args.as_slice() {
+                [l, r] => {
+                    let l = self.expression(scope, l)?;
+                    let r = self.expression(scope, r)?;
+                    let instruction = match op {
+                        OpSymbol::Plus => "add",
+                        OpSymbol::Minus => "sub",
+                        OpSymbol::Star => "mul",
+                        OpSymbol::ForwardSlash => "div",
+                        OpSymbol::LAngleBracket => "csltl",
+                        OpSymbol::RAngleBracket => "csgtl",
+                        _ => {
+                            return Err(FractalError::Unsupported(format!(
+                                "the `{}` operator at runtime",
+                                op
+                            )))
+                        }
+                    };
+                    let result = self.temporary();
+                    self.emit(format!("{} =l {} {}, {}", result, instruction, l, r));
+                    result
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("operator `{}` applied to {} operands", op, args.len()),
+            },
+            Expression::FnCall { name, args } => self.call(scope, name, args)?,
+            Expression::Match { expr, args } => self.match_expression(scope, expr, args)?,
+            Expression::List(_) => {
+                return Err(FractalError::Unsupported(String::from(
+                    "lists when compiling to qbe",
+                )))
+            }
+            _ => {
+                return Err(FractalError::Unsupported(format!(
+                    "compiling {:?} to qbe",
+                    expr
+                )))
+            }
+        })
+    }
+
+    fn call(
+        &mut self,
+        scope: &Scope,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<String, FractalError> {
+        // builtins take precedence, as they do in the interpreter
+        if name == "println" {
+            return self.println(scope, args);
+        }
+        if fractal::builtins::LIST_BUILTINS.contains(&name) {
+            return Err(FractalError::Unsupported(String::from(
+                "lists when compiling to qbe",
+            )));
+        }
+        if scope.contains_key(name) {
+            return Err(FractalError::Unsupported(String::from(
+                "calling function references when compiling to qbe",
+            )));
+        }
+
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.expression(scope, arg)?);
+        }
+
+        match self.items.get(name) {
+            Some(UniverseItem::Constructor { .. }) => self.construct(name, values),
+            Some(UniverseItem::FnDecl { .. }) => {
+                let args: Vec<String> = values.iter().map(|value| format!("l {}", value)).collect();
+                let result = self.temporary();
+                self.emit(format!(
+                    "{} =l call $sf_{}({})",
+                    result,
+                    name,
+                    args.join(", ")
+                ));
+                Ok(result)
+            }
+            _ => Err(FractalError::UnboundName(String::from(name))),
+        }
+    }
+
+    fn println(&mut self, scope: &Scope, args: &[Expression]) -> Result<String, FractalError> {
+        match args {
+            [] => self.emit("call $putchar(w 10)"),
+            // only strings may be printed
+            [arg] => {
+                let value = self.expression(scope, arg)?;
+                self.emit(format!("call $puts(l {})", value));
+            }
+            _ => {
+                return Err(FractalError::ArgumentCount {
+                    name: String::from("println"),
+                    expected: 1,
+                    found: args.len(),
+                })
+            }
+        }
+        Ok(String::from("0"))
+    }
+
+    /// allocates a variant, storing the index of its constructor followed by its fields
+    fn construct(&mut self, name: &str, fields: Vec<String>) -> Result<String, FractalError> {
+        let index = self.constructor(name)?;
+
+        let pointer = self.temporary();
+        self.emit(format!(
+            "{} =l call $malloc(l {})",
+            pointer,
+            8 * (fields.len() + 1)
+        ));
+        self.emit(format!("storel {}, {}", index, pointer));
+        for (i, field) in fields.iter().enumerate() {
+            let address = self.temporary();
+            self.emit(format!("{} =l add {}, {}", address, pointer, 8 * (i + 1)));
+            self.emit(format!("storel {}, {}", field, address));
+        }
+        Ok(pointer)
+    }
+
+    /// emits a match expression, testing each arm in turn and joining their values with a phi
+    fn match_expression(
+        &mut self,
+        scope: &Scope,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Result<String, FractalError> {
+        let scrutinee = self.expression(scope, expr)?;
+        let id = self.next_match;
+        self.next_match += 1;
+
+        let mut incoming = Vec::with_capacity(arms.len());
+        for (i, arm) in arms.iter().enumerate() {
+            match arm {
+                Expression::Destructure { pat, body } => {
+                    // a failed test moves on to the next arm
+                    let fail = if i + 1 < arms.len() {
+                        format!("match.{}.arm.{}", id, i + 1)
+                    } else {
+                        format!("match.{}.none", id)
+                    };
+
+                    // arms bind into their own scope, which is discarded afterwards
+                    let mut arm_scope = scope.clone();
+                    self.pattern(&mut arm_scope, pat, &scrutinee, &fail)?;
+                    let value = self.block(&arm_scope, body)?;
+                    incoming.push(format!("@{} {}", self.block, value));
+                    self.emit(format!("jmp @match.{}.end", id));
+
+                    self.label(&fail);
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("unexpected match arm: {:?}", arm),
+            }
+        }
+        self.emit("call $abort()");
+        self.emit("hlt");
+
+        self.label(&format!("match.{}.end", id));
+        let result = self.temporary();
+        self.emit(format!("{} =l phi {}", result, incoming.join(", ")));
+        Ok(result)
+    }
+
+    /// emits the tests of a pattern against a value, binding any identifiers in it. failed tests
+    /// jump to the `fail` label
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        pat: &Pattern,
+        value: &str,
+        fail: &str,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) => match self.items.get(name.as_str()) {
+                Some(UniverseItem::Constructor { .. }) => {
+                    self.pattern(
+                        scope,
+                        &Pattern::Constructor {
+                            name: name.clone(),
+                            args: vec![],
+                        },
+                        value,
+                        fail,
+                    )?;
+                }
+                _ => {
+                    scope.insert(name.clone(), String::from(value));
+                }
+            },
+            Pattern::Integer(int) => {
+                let int = integer(int)?;
+                self.test(format!("ceql {}, {}", value, int), fail);
+            }
+            Pattern::StringLiteral(string) => {
+                let string = self.string(unquote(string));
+                let comparison = self.temporary();
+                self.emit(format!(
+                    "{} =w call $strcmp(l {}, l {})",
+                    comparison, value, string
+                ));
+                self.test(format!("ceqw {}, 0", comparison), fail);
+            }
+            Pattern::Char(_) => {
+                return Err(FractalError::Unsupported(String::from(
+                    "chars when compiling to qbe",
+                )))
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let start = range_bound(start)?;
+                    self.test(format!("csgel {}, {}", value, start), fail);
+                }
+                if let Some(end) = end {
+                    let end = range_bound(end)?;
+                    self.test(format!("csltl {}, {}", value, end), fail);
+                }
+            }
+            Pattern::Constructor { name, args } => {
+                let index = self.constructor(name)?;
+
+                let tag = self.temporary();
+                self.emit(format!("{} =l loadl {}", tag, value));
+                self.test(format!("ceql {}, {}", tag, index), fail);
+
+                for (i, arg) in args.iter().enumerate() {
+                    if let Pattern::Wildcard = arg {
+                        continue;
+                    }
+
+                    let address = self.temporary();
+                    self.emit(format!("{} =l add {}, {}", address, value, 8 * (i + 1)));
+                    let field = self.temporary();
+                    self.emit(format!("{} =l loadl {}", field, address));
+                    self.pattern(scope, arg, &field, fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// emits a comparison, continuing in a new block if it holds and jumping to `fail` otherwise
+    fn test(&mut self, comparison: String, fail: &str) {
+        let condition = self.temporary();
+        self.emit(format!("{} =w {}", condition, comparison));
+        let pass = format!("test.{}", self.next_label);
+        self.next_label += 1;
+        self.emit(format!("jnz {}, @{}, @{}", condition, pass, fail));
+        self.label(&pass);
+    }
+
+    /// looks up the index of a constructor in its type
+    fn constructor(&self, name: &str) -> Result<usize, FractalError> {
+        if let Some(UniverseItem::Constructor { ty, .. }) = self.items.get(name) {
+            if let Some(UniverseItem::VariantDecl { variants }) = self.items.get(ty.as_str()) {
+                if let Some(index) = variants.iter().position(|variant| variant.name == name) {
+                    return Ok(index);
+                }
+            }
+        }
+        Err(FractalError::UnknownConstructor(String::from(name)))
+    }
+
+    /// refers to a string constant, reusing an existing one if there is one
+    fn string(&mut self, string: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == string) {
+            Some(index) => index,
+            None => {
+                self.strings.push(String::from(string));
+                self.strings.len() - 1
+            }
+        };
+        format!("$str.{}", index)
+    }
+
+    fn temporary(&mut self) -> String {
+        let temporary = format!("%.{}", self.next_temporary);
+        self.next_temporary += 1;
+        temporary
+    }
+
+    fn label(&mut self, label: &str) {
+        let _ = writeln!(self.out, "@{}", label);
+        self.block = String::from(label);
+    }
+
+    fn emit<S: AsRef<str>>(&mut self, instruction: S) {
+        let _ = writeln!(self.out, "\t{}", instruction.as_ref());
+    }
+}
+
+fn integer(int: &num_bigint::BigInt) -> Result<i64, FractalError> {
+    i64::try_from(int).map_err(|_| {
+        FractalError::Unsupported(format!(
+            "the integer {}, which doesn't fit inside of 64 bits",
+            int
+        ))
+    })
+}
+
+fn range_bound(bound: &Pattern) -> Result<i64, FractalError> {
+    match bound {
+        Pattern::Integer(int) => integer(int),
+        _ => Err(FractalError::Unsupported(format!(
+            "the range bound `{}`",
+            bound
+        ))),
+    }
+}
+
+/// escapes a string for use inside of a qbe string literal
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+    for byte in string.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:03o}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fractal::{golden, Evaluator, EvaluatorConfig};
+    use parser::lexer;
+    use std::{
+        borrow::Cow,
+        env, fs,
+        path::{Path, PathBuf},
+        process::Command,
+    };
+    use tag::TagName;
+
+    fn compile_source(source: &str) -> String {
+        let mut file_tags = HashMap::new();
+        file_tags.insert(String::from("test.sf"), Vec::new());
+
+        let mut evaluator = Evaluator::new(EvaluatorConfig {
+            project_tag: TagName::Primary(Cow::Borrowed("test")),
+            file_tags,
+        });
+
+        let mut files = HashMap::new();
+        files.insert(
+            String::from("test.sf"),
+            parser::parse(lexer::lex(source)).unwrap(),
+        );
+        evaluator.populate(&files).unwrap();
+
+        compile(&evaluator.items()).unwrap()
+    }
+
+    /// the programs in the golden directory, each of which has the il it compiles to alongside it
+    /// in a `.ssa` file and its output in a `.out` file
+    fn golden() -> Vec<PathBuf> {
+        golden::programs(&Path::new(env!("CARGO_MANIFEST_DIR")).join("golden"))
+    }
+
+    #[test]
+    fn test_golden() {
+        for program in golden() {
+            let il = compile_source(&fs::read_to_string(&program).unwrap());
+            golden::check(&program.with_extension("ssa"), &il);
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        // binaries can only be built if qbe and a c compiler are installed
+        let available = |tool| Command::new(tool).arg("-h").output().is_ok();
+        if !available("qbe") || !available("cc") {
+            eprintln!("qbe or cc is missing, skipping");
+            return;
+        }
+
+        let dir = env::temp_dir().join(format!("codegen-qbe-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for program in golden() {
+            let name = program.file_stem().unwrap();
+            let il = dir.join(name).with_extension("ssa");
+            let assembly = dir.join(name).with_extension("s");
+            let binary = dir.join(name);
+            fs::write(&il, compile_source(&fs::read_to_string(&program).unwrap())).unwrap();
+
+            let status = Command::new("qbe")
+                .arg("-o")
+                .arg(&assembly)
+                .arg(&il)
+                .status()
+                .unwrap();
+            assert!(status.success(), "qbe failed on {}", program.display());
+            let status = Command::new("cc")
+                .arg("-o")
+                .arg(&binary)
+                .arg(&assembly)
+                .status()
+                .unwrap();
+            assert!(status.success(), "cc failed on {}", program.display());
+
+            let output = Command::new(&binary).output().unwrap();
+            assert!(output.status.success());
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                fs::read_to_string(program.with_extension("out")).unwrap(),
+                "{}",
+                program.display()
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,828 @@
+// a compiler backend emitting webassembly modules, in either the text or the binary format
+//
+// like the qbe backend, every value is lowered to a 64-bit `i64`: integers are stored directly
+// (wrapping instead of growing like they do when interpreted), while strings and variants are
+// addresses in the module's linear memory. strings are stored as their length in bytes, as a 32-bit
+// little-endian integer, followed by their utf-8 encoding, and variants are blocks taken from a
+// bump allocator holding the index of their constructor followed by their fields
+//
+// the module imports a single host function, `env.println`, which is passed the address of a string
+// and is expected to print it on its own line. the module's memory is exported as `memory` so that
+// the host can read it, each function is exported under its own name and, if there is a `main`
+// function, an entry point calling it is exported as `_start`
+//
+// programs are expected to have been type checked already, so no types are checked here
+
+use fractal::{unquote, FractalError, UniverseItem};
+use parser::ast::{Expression, OpSymbol, Pattern};
+use std::{collections::HashMap, convert::TryFrom, fmt::Write};
+
+/// the argument passed to `main` by the entry point, matching the one passed by flurry
+const ENTRY_ARGUMENT: i64 = 69;
+
+/// the address of the first string constant. the first few bytes are left unused so that no value
+/// is ever stored at address zero
+const DATA_START: u32 = 8;
+
+/// the functions every module relies upon, which are emitted before the compiled ones
+const RUNTIME: &str = r#"  (import "env" "println" (func $println (param i32)))
+  (memory (export "memory") 1)
+
+  ;; allocates a block of memory, growing the memory if it runs out
+  (func $alloc (param $size i32) (result i32)
+    (local $address i32)
+    global.get $heap
+    local.set $address
+    global.get $heap
+    local.get $size
+    i32.add
+    global.set $heap
+    block $done
+      global.get $heap
+      memory.size
+      i32.const 16
+      i32.shl
+      i32.le_u
+      br_if $done
+      global.get $heap
+      memory.size
+      i32.const 16
+      i32.shl
+      i32.sub
+      i32.const 65535
+      i32.add
+      i32.const 16
+      i32.shr_u
+      memory.grow
+      i32.const -1
+      i32.ne
+      br_if $done
+      unreachable
+    end
+    local.get $address)
+
+  ;; checks if two strings are equal
+  (func $streq (param $a i32) (param $b i32) (result i32)
+    (local $length i32)
+    (local $i i32)
+    local.get $a
+    i32.load
+    local.tee $length
+    local.get $b
+    i32.load
+    i32.ne
+    if
+      i32.const 0
+      return
+    end
+    block $done
+      loop $compare
+        local.get $i
+        local.get $length
+        i32.ge_u
+        br_if $done
+        local.get $a
+        local.get $i
+        i32.add
+        i32.load8_u offset=4
+        local.get $b
+        local.get $i
+        i32.add
+        i32.load8_u offset=4
+        i32.ne
+        if
+          i32.const 0
+          return
+        end
+        local.get $i
+        i32.const 1
+        i32.add
+        local.set $i
+        br $compare
+      end
+    end
+    i32.const 1)
+
+"#;
+
+/// the locals holding the values of the bindings in scope
+type Scope = HashMap<String, String>;
+
+/// compiles every function in a universe to a webassembly module in the text format, in order of
+/// their names
+pub fn compile(items: &HashMap<&str, &UniverseItem>) -> Result<String, FractalError> {
+    let mut names: Vec<&str> = items
+        .iter()
+        .filter(|(_, item)| matches!(item, UniverseItem::FnDecl { .. }))
+        .map(|(name, _)| *name)
+        .collect();
+    names.sort_unstable();
+
+    let mut codegen = Codegen {
+        items,
+        strings: Vec::new(),
+        next_address: DATA_START,
+        out: String::from("(module\n"),
+        body: String::new(),
+        depth: 0,
+        next_local: 0,
+        next_match: 0,
+    };
+    codegen.out.push_str(RUNTIME);
+    for name in names {
+        if let UniverseItem::FnDecl { args, body, .. } = items[name] {
+            codegen.function(name, args, body)?;
+        }
+    }
+    codegen.entry()?;
+    codegen.data();
+    codegen.out.push_str(")\n");
+
+    Ok(codegen.out)
+}
+
+/// compiles every function in a universe to a webassembly module in the binary format
+pub fn compile_binary(items: &HashMap<&str, &UniverseItem>) -> Result<Vec<u8>, FractalError> {
+    let text = compile(items)?;
+
+    // TODO(superwhiskers): remove panic
+    Ok(wat::parse_str(&text).expect("the emitted module is malformed"))
+}
+
+struct Codegen<'c> {
+    items: &'c HashMap<&'c str, &'c UniverseItem>,
+
+    /// the string constants used and their addresses
+    strings: Vec<(String, u32)>,
+    next_address: u32,
+    out: String,
+
+    // the state of the function being emitted
+    body: String,
+    depth: usize,
+    next_local: usize,
+    next_match: usize,
+}
+
+impl<'c> Codegen<'c> {
+    fn function(
+        &mut self,
+        name: &str,
+        args: &[String],
+        body: &[Box<Expression>],
+    ) -> Result<(), FractalError> {
+        self.body.clear();
+        self.depth = 2;
+        self.next_local = 0;
+        self.next_match = 0;
+
+        let scope: Scope = args
+            .iter()
+            .map(|arg| (arg.clone(), format!("${}", arg)))
+            .collect();
+        self.block(&scope, body)?;
+
+        let _ = write!(self.out, "  (func $sf.{} (export \"{}\")", name, name);
+        for arg in args {
+            let _ = write!(self.out, " (param ${} i64)", arg);
+        }
+        self.out.push_str(" (result i64)\n");
+        for local in 0..self.next_local {
+            let _ = writeln!(self.out, "    (local $t.{} i64)", local);
+        }
+
+        // the closing parenthesis is placed after the last instruction
+        self.body.pop();
+        self.out.push_str(&self.body);
+        self.out.push_str(")\n\n");
+        Ok(())
+    }
+
+    /// emits the exported entry point, which calls `main` and discards its result
+    fn entry(&mut self) -> Result<(), FractalError> {
+        let args = match self.items.get("main") {
+            Some(UniverseItem::FnDecl { args, .. }) => match args.len() {
+                0 => String::new(),
+                1 => format!("i64.const {}\n    ", ENTRY_ARGUMENT),
+                _ => {
+                    return Err(FractalError::Unsupported(String::from(
+                        "`main` taking more than one argument",
+                    )))
+                }
+            },
+            _ => return Ok(()),
+        };
+
+        let _ = writeln!(
+            self.out,
+            "  (func (export \"_start\")\n    {}call $sf.main\n    drop)\n",
+            args
+        );
+        Ok(())
+    }
+
+    /// emits the data segments holding every string constant, followed by the start of the heap,
+    /// which comes after them
+    fn data(&mut self) {
+        for (string, address) in &self.strings {
+            let length = string.len() as u32;
+            let _ = writeln!(
+                self.out,
+                "  (data (i32.const {}) \"{}{}\")",
+                address,
+                escape(&length.to_le_bytes()),
+                escape(string.as_bytes())
+            );
+        }
+        let _ = writeln!(
+            self.out,
+            "  (global $heap (mut i32) (i32.const {}))",
+            align(self.next_address, 8)
+        );
+    }
+
+    /// emits a block, leaving the value of its last expression on the stack
+    fn block(&mut self, scope: &Scope, body: &[Box<Expression>]) -> Result<(), FractalError> {
+        match body.split_last() {
+            Some((last, rest)) => {
+                for expr in rest {
+                    self.expression(scope, expr)?;
+                    self.emit("drop");
+                }
+                self.expression(scope, last)
+            }
+            None => {
+                self.emit("i64.const 0");
+                Ok(())
+            }
+        }
+    }
+
+    /// emits an expression, leaving its value on the stack
+    fn expression(&mut self, scope: &Scope, expr: &Expression) -> Result<(), FractalError> {
+        match expr {
+            Expression::Integer(int) => {
+                let int = integer(int)?;
+                self.emit(format!("i64.const {}", int));
+            }
+            Expression::StringLiteral(string) => {
+                let address = self.string(unquote(string));
+                self.emit(format!("i64.const {}", address));
+            }
+            Expression::TypeDecl { expr, .. } => self.expression(scope, expr)?,
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(local) => self.emit(format!("local.get {}", local)),
+                None => match self.items.get(name.as_str()) {
+                    Some(UniverseItem::Constructor { fields, .. }) if fields.is_empty() => {
+                        self.construct(name, vec![])?
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) if args.is_empty() => {
+                        self.call(scope, name, &[])?
+                    }
+                    Some(UniverseItem::Constructor { .. }) | Some(UniverseItem::FnDecl { .. }) => {
+                        return Err(FractalError::Unsupported(String::from(
+                            "function references when compiling to webassembly",
+                        )))
+                    }
+                    _ => return Err(FractalError::UnboundName(name.clone())),
+                },
+            },
+            Expression::OpCall { op, args } => match args.as_slice() {
+                [l, r] => {
+                    self.expression(scope, l)?;
+                    self.expression(scope, r)?;
+                    match op {
+                        OpSymbol::Plus => self.emit("i64.add"),
+                        OpSymbol::Minus => self.emit("i64.sub"),
+                        OpSymbol::Star => self.emit("i64.mul"),
+                        OpSymbol::ForwardSlash => self.emit("i64.div_s"),
+                        OpSymbol::LAngleBracket => {
+                            self.emit("i64.lt_s");
+                            self.emit("i64.extend_i32_u");
+                        }
+                        OpSymbol::RAngleBracket => {
+                            self.emit("i64.gt_s");
+                            self.emit("i64.extend_i32_u");
+                        }
+                        _ => {
+                            return Err(FractalError::Unsupported(format!(
+                                "the `{}` operator at runtime",
+                                op
+                            )))
+                        }
+                    }
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("operator `{}` applied to {} operands", op, args.len()),
+            },
+            Expression::FnCall { name, args } => self.call(scope, name, args)?,
+            Expression::Match { expr, args } => self.match_expression(scope, expr, args)?,
+            Expression::List(_) => {
+                return Err(FractalError::Unsupported(String::from(
+                    "lists when compiling to webassembly",
+                )))
+            }
+            _ => {
+                return Err(FractalError::Unsupported(format!(
+                    "compiling {:?} to webassembly",
+                    expr
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, scope: &Scope, name: &str, args: &[Expression]) -> Result<(), FractalError> {
+        // builtins take precedence, as they do in the interpreter
+        if name == "println" {
+            return self.println(scope, args);
+        }
+        if fractal::builtins::LIST_BUILTINS.contains(&name) {
+            return Err(FractalError::Unsupported(String::from(
+                "lists when compiling to webassembly",
+            )));
+        }
+        if scope.contains_key(name) {
+            return Err(FractalError::Unsupported(String::from(
+                "calling function references when compiling to webassembly",
+            )));
+        }
+
+        match self.items.get(name) {
+            Some(UniverseItem::Constructor { .. }) => {
+                // the fields are kept in locals until the variant has been allocated
+                let mut fields = Vec::with_capacity(args.len());
+                for arg in args {
+                    self.expression(scope, arg)?;
+                    let field = self.local();
+                    self.emit(format!("local.set {}", field));
+                    fields.push(field);
+                }
+                self.construct(name, fields)
+            }
+            Some(UniverseItem::FnDecl { .. }) => {
+                for arg in args {
+                    self.expression(scope, arg)?;
+                }
+                self.emit(format!("call $sf.{}", name));
+                Ok(())
+            }
+            _ => Err(FractalError::UnboundName(String::from(name))),
+        }
+    }
+
+    fn println(&mut self, scope: &Scope, args: &[Expression]) -> Result<(), FractalError> {
+        match args {
+            [] => {
+                let address = self.string("");
+                self.emit(format!("i32.const {}", address));
+            }
+            // only strings may be printed
+            [arg] => {
+                self.expression(scope, arg)?;
+                self.emit("i32.wrap_i64");
+            }
+            _ => {
+                return Err(FractalError::ArgumentCount {
+                    name: String::from("println"),
+                    expected: 1,
+                    found: args.len(),
+                })
+            }
+        }
+        self.emit("call $println");
+        self.emit("i64.const 0");
+        Ok(())
+    }
+
+    /// allocates a variant, storing the index of its constructor followed by the fields held in
+    /// the given locals
+    fn construct(&mut self, name: &str, fields: Vec<String>) -> Result<(), FractalError> {
+        let index = self.constructor(name)?;
+
+        let pointer = self.local();
+        self.emit(format!("i32.const {}", 8 * (fields.len() + 1)));
+        self.emit("call $alloc");
+        self.emit("i64.extend_i32_u");
+        self.emit(format!("local.set {}", pointer));
+
+        self.address(&pointer);
+        self.emit(format!("i64.const {}", index));
+        self.emit("i64.store");
+        for (i, field) in fields.iter().enumerate() {
+            self.address(&pointer);
+            self.emit(format!("local.get {}", field));
+            self.emit(format!("i64.store offset={}", 8 * (i + 1)));
+        }
+        self.emit(format!("local.get {}", pointer));
+        Ok(())
+    }
+
+    /// emits a match expression as a block containing a nested block for each arm. a failed test
+    /// breaks out of the arm's block, moving on to the next one, while the arm's value is carried
+    /// out of the outer block
+    fn match_expression(
+        &mut self,
+        scope: &Scope,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Result<(), FractalError> {
+        self.expression(scope, expr)?;
+        let scrutinee = self.local();
+        self.emit(format!("local.set {}", scrutinee));
+        let id = self.next_match;
+        self.next_match += 1;
+
+        self.open(format!("block $match.{} (result i64)", id));
+        for (i, arm) in arms.iter().enumerate() {
+            match arm {
+                Expression::Destructure { pat, body } => {
+                    let fail = format!("$match.{}.arm.{}", id, i);
+                    self.open(format!("block {}", fail));
+
+                    // arms bind into their own scope, which is discarded afterwards
+                    let mut arm_scope = scope.clone();
+                    self.pattern(&mut arm_scope, pat, &scrutinee, &fail)?;
+                    self.block(&arm_scope, body)?;
+                    self.emit(format!("br $match.{}", id));
+
+                    self.close();
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("unexpected match arm: {:?}", arm),
+            }
+        }
+        self.emit("unreachable");
+        self.close();
+        Ok(())
+    }
+
+    /// emits the tests of a pattern against the value held in a local, binding any identifiers in
+    /// it. failed tests break out to the `fail` label
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        pat: &Pattern,
+        value: &str,
+        fail: &str,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) => match self.items.get(name.as_str()) {
+                Some(UniverseItem::Constructor { .. }) => {
+                    self.pattern(
+                        scope,
+                        &Pattern::Constructor {
+                            name: name.clone(),
+                            args: vec![],
+                        },
+                        value,
+                        fail,
+                    )?;
+                }
+                _ => {
+                    scope.insert(name.clone(), String::from(value));
+                }
+            },
+            Pattern::Integer(int) => {
+                let int = integer(int)?;
+                self.emit(format!("local.get {}", value));
+                self.emit(format!("i64.const {}", int));
+                self.emit("i64.ne");
+                self.emit(format!("br_if {}", fail));
+            }
+            Pattern::StringLiteral(string) => {
+                let address = self.string(unquote(string));
+                self.address(value);
+                self.emit(format!("i32.const {}", address));
+                self.emit("call $streq");
+                self.emit("i32.eqz");
+                self.emit(format!("br_if {}", fail));
+            }
+            Pattern::Char(_) => {
+                return Err(FractalError::Unsupported(String::from(
+                    "chars when compiling to webassembly",
+                )))
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let start = range_bound(start)?;
+                    self.emit(format!("local.get {}", value));
+                    self.emit(format!("i64.const {}", start));
+                    self.emit("i64.lt_s");
+                    self.emit(format!("br_if {}", fail));
+                }
+                if let Some(end) = end {
+                    let end = range_bound(end)?;
+                    self.emit(format!("local.get {}", value));
+                    self.emit(format!("i64.const {}", end));
+                    self.emit("i64.ge_s");
+                    self.emit(format!("br_if {}", fail));
+                }
+            }
+            Pattern::Constructor { name, args } => {
+                let index = self.constructor(name)?;
+
+                self.address(value);
+                self.emit("i64.load");
+                self.emit(format!("i64.const {}", index));
+                self.emit("i64.ne");
+                self.emit(format!("br_if {}", fail));
+
+                for (i, arg) in args.iter().enumerate() {
+                    if let Pattern::Wildcard = arg {
+                        continue;
+                    }
+
+                    let field = self.local();
+                    self.address(value);
+                    self.emit(format!("i64.load offset={}", 8 * (i + 1)));
+                    self.emit(format!("local.set {}", field));
+                    self.pattern(scope, arg, &field, fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// looks up the index of a constructor in its type
+    fn constructor(&self, name: &str) -> Result<usize, FractalError> {
+        if let Some(UniverseItem::Constructor { ty, .. }) = self.items.get(name) {
+            if let Some(UniverseItem::VariantDecl { variants }) = self.items.get(ty.as_str()) {
+                if let Some(index) = variants.iter().position(|variant| variant.name == name) {
+                    return Ok(index);
+                }
+            }
+        }
+        Err(FractalError::UnknownConstructor(String::from(name)))
+    }
+
+    /// returns the address of a string constant, reusing an existing one if there is one
+    fn string(&mut self, string: &str) -> u32 {
+        if let Some((_, address)) = self.strings.iter().find(|(s, _)| s == string) {
+            return *address;
+        }
+
+        let address = self.next_address;
+        self.next_address = align(address + 4 + string.len() as u32, 4);
+        self.strings.push((String::from(string), address));
+        address
+    }
+
+    /// pushes the address held in a local, which is stored as an `i64` like every other value
+    fn address(&mut self, local: &str) {
+        self.emit(format!("local.get {}", local));
+        self.emit("i32.wrap_i64");
+    }
+
+    fn local(&mut self) -> String {
+        let local = format!("$t.{}", self.next_local);
+        self.next_local += 1;
+        local
+    }
+
+    fn open<S: AsRef<str>>(&mut self, instruction: S) {
+        self.emit(instruction);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.emit("end");
+    }
+
+    fn emit<S: AsRef<str>>(&mut self, instruction: S) {
+        let _ = writeln!(
+            self.body,
+            "{:indent$}{}",
+            "",
+            instruction.as_ref(),
+            indent = 2 * self.depth
+        );
+    }
+}
+
+fn integer(int: &num_bigint::BigInt) -> Result<i64, FractalError> {
+    i64::try_from(int).map_err(|_| {
+        FractalError::Unsupported(format!(
+            "the integer {}, which doesn't fit inside of 64 bits",
+            int
+        ))
+    })
+}
+
+fn range_bound(bound: &Pattern) -> Result<i64, FractalError> {
+    match bound {
+        Pattern::Integer(int) => integer(int),
+        _ => Err(FractalError::Unsupported(format!(
+            "the range bound `{}`",
+            bound
+        ))),
+    }
+}
+
+fn align(address: u32, alignment: u32) -> u32 {
+    address.next_multiple_of(alignment)
+}
+
+/// escapes bytes for use inside of a webassembly string literal
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\{:02x}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fractal::{Evaluator, EvaluatorConfig, Value};
+    use indoc::indoc;
+    use num_bigint::BigInt;
+    use parser::lexer;
+    use std::borrow::Cow;
+    use tag::TagName;
+    use wasmi::{Caller, Engine, Instance, Linker, Module, Store};
+
+    const SHAPES: &str = indoc! {"
+        type shape = circle ilarge | rect ilarge ilarge | empty
+        size :: tag nothing
+        size :: ilarge -> shape
+        size n => match n =>
+          0 => empty
+          1..10 => circle n
+          _ => rect n 2
+
+
+        area :: tag nothing
+        area :: shape -> ilarge
+        area s => match s =>
+          circle r => 3 * r * r
+          rect w h => w * h
+          empty => 0
+
+
+        measure :: tag nothing
+        measure :: ilarge -> ilarge
+        measure n => (area (size n)) * 2 - n
+
+
+        describe :: tag nothing
+        describe :: ilarge -> string
+        describe n => match area (size n) =>
+          0 => \"nothing\"
+          75 => \"a circle of radius 5\"
+          138 => \"a thin rectangle\"
+          _ => \"something\\else\"
+
+
+        greet :: tag nothing
+        greet :: string -> string
+        greet s => match s =>
+          \"bar\" => \"hello bar\"
+          \"foo\" => \"hello foo\"
+          _ => \"who?\"
+
+
+        main :: tag nothing
+        main :: ilarge -> ilarge
+        main a =>
+          println (describe 0)
+          println (describe 5)
+          println (describe a)
+          println (describe (a - 60))
+          println (greet \"bar\")
+          println (greet \"baz\")
+          a / 3
+    "};
+
+    fn populate(source: &str) -> Evaluator<'static> {
+        let mut file_tags = HashMap::new();
+        file_tags.insert(String::from("test.sf"), Vec::new());
+
+        let mut evaluator = Evaluator::new(EvaluatorConfig {
+            project_tag: TagName::Primary(Cow::Borrowed("test")),
+            file_tags,
+        });
+
+        let mut files = HashMap::new();
+        files.insert(
+            String::from("test.sf"),
+            parser::parse(lexer::lex(source)).unwrap(),
+        );
+        evaluator.populate(&files).unwrap();
+        evaluator
+    }
+
+    /// reads a string from a module's memory
+    fn read_string(memory: &[u8], address: usize) -> String {
+        let mut length = [0; 4];
+        length.copy_from_slice(&memory[address..address + 4]);
+        let length = u32::from_le_bytes(length) as usize;
+        String::from_utf8(memory[address + 4..address + 4 + length].to_vec()).unwrap()
+    }
+
+    /// instantiates a module, collecting the lines it prints in the store
+    fn instantiate(binary: &[u8]) -> (Store<Vec<String>>, Instance) {
+        let engine = Engine::default();
+        let module = Module::new(&engine, binary).unwrap();
+        let mut store = Store::new(&engine, Vec::new());
+        let mut linker = <Linker<Vec<String>>>::new(&engine);
+        linker
+            .func_wrap(
+                "env",
+                "println",
+                |mut caller: Caller<'_, Vec<String>>, address: i32| {
+                    let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+                    let line = read_string(memory.data(&caller), address as usize);
+                    caller.data_mut().push(line);
+                },
+            )
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        (store, instance)
+    }
+
+    #[test]
+    fn test_validate() {
+        for source in &[SHAPES, include_str!("../../../examples/hello.sf")] {
+            let binary = compile_binary(&populate(source).items()).unwrap();
+            wasmparser::Validator::new()
+                .validate_all(&binary)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+    }
+
+    #[test]
+    fn test_matches_interpreter() {
+        let mut evaluator = populate(SHAPES);
+        let binary = compile_binary(&evaluator.items()).unwrap();
+        let (mut store, instance) = instantiate(&binary);
+
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        let measure = instance
+            .get_typed_func::<i64, i64>(&store, "measure")
+            .unwrap();
+        let describe = instance
+            .get_typed_func::<i64, i64>(&store, "describe")
+            .unwrap();
+        for n in &[0, 1, 3, 5, 9, 10, 30, 69, -12345] {
+            let args = vec![Value::Int(BigInt::from(*n))];
+
+            let entry = evaluator.entry("measure").unwrap().clone();
+            let expected = evaluator.eval(&entry, args.clone()).unwrap();
+            let found = measure.call(&mut store, *n).unwrap();
+            assert_eq!(Value::Int(BigInt::from(found)), expected, "measure {}", n);
+
+            let entry = evaluator.entry("describe").unwrap().clone();
+            let expected = evaluator.eval(&entry, args).unwrap();
+            let address = describe.call(&mut store, *n).unwrap();
+            let found = read_string(memory.data(&store), address as usize);
+            assert_eq!(Value::from(found.as_str()), expected, "describe {}", n);
+        }
+    }
+
+    #[test]
+    fn test_println() {
+        let binary = compile_binary(&populate(SHAPES).items()).unwrap();
+        let (mut store, instance) = instantiate(&binary);
+        instance
+            .get_typed_func::<(), ()>(&store, "_start")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+        assert_eq!(
+            store.data(),
+            &[
+                "nothing",
+                "a circle of radius 5",
+                "a thin rectangle",
+                "something\\else",
+                "hello bar",
+                "who?",
+            ]
+        );
+
+        let binary =
+            compile_binary(&populate(include_str!("../../../examples/hello.sf")).items()).unwrap();
+        let (mut store, instance) = instantiate(&binary);
+        let main = instance.get_typed_func::<i64, i64>(&store, "main").unwrap();
+        assert_eq!(main.call(&mut store, 1).unwrap(), 69);
+        assert_eq!(store.data(), &["Hello world!"]);
+    }
+}
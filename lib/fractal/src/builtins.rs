@@ -0,0 +1,67 @@
+// runtime implementations of the builtin functions operating on lists
+
+use crate::{FractalError, Value};
+use num_bigint::BigInt;
+use std::convert::TryFrom;
+
+/// the names of the builtins operating on lists
+pub const LIST_BUILTINS: &[&str] = &["len", "get", "push", "concat", "map", "fold"];
+
+/// evaluates a list builtin over its already evaluated arguments
+///
+/// `apply` is used to call the functions passed to `map` and `fold`, allowing each engine to call
+/// them in its own way
+pub(crate) fn eval_list_builtin<F>(
+    name: &str,
+    args: Vec<Value>,
+    mut apply: F,
+) -> Result<Value, FractalError>
+where
+    F: FnMut(&Value, Vec<Value>) -> Result<Value, FractalError>,
+{
+    Ok(match (name, args.as_slice()) {
+        ("len", [Value::List(items)]) => Value::Int(BigInt::from(items.len())),
+        ("get", [Value::List(items), Value::Int(index)]) => {
+            match usize::try_from(index)
+                .ok()
+                .and_then(|index| items.get(index))
+            {
+                Some(item) => item.clone(),
+                None => {
+                    return Err(FractalError::IndexOutOfBounds {
+                        index: index.clone(),
+                        length: items.len(),
+                    })
+                }
+            }
+        }
+        ("push", [Value::List(items), item]) => {
+            let mut items = items.clone();
+            items.push(item.clone());
+            Value::List(items)
+        }
+        ("concat", [Value::List(xs), Value::List(ys)]) => {
+            Value::List(xs.iter().chain(ys).cloned().collect())
+        }
+        ("map", [Value::List(items), f]) => {
+            let mut mapped = Vec::with_capacity(items.len());
+            for item in items {
+                mapped.push(apply(f, vec![item.clone()])?);
+            }
+            Value::List(mapped)
+        }
+        ("fold", [Value::List(items), init, f]) => {
+            let mut accumulator = init.clone();
+            for item in items {
+                accumulator = apply(f, vec![accumulator, item.clone()])?;
+            }
+            accumulator
+        }
+        _ => {
+            return Err(FractalError::InvalidValues(format!(
+                "`{}` called with {:?}",
+                name, args
+            )))
+        }
+    })
+}
@@ -0,0 +1,682 @@
+// lowering of function bodies to bytecode
+
+use super::{Builtin, Function, Instruction, Program};
+use crate::{unquote, FractalError, UniverseItem, Value};
+use parser::ast::{Expression, OpSymbol, Pattern, UnaryOpSymbol};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// the local slots of the bindings in scope
+type Scope = HashMap<String, u16>;
+
+pub(super) fn compile(items: &HashMap<&str, &UniverseItem>) -> Result<Program, FractalError> {
+    let mut names: Vec<&str> = items
+        .iter()
+        .filter(|(_, item)| matches!(item, UniverseItem::FnDecl { .. }))
+        .map(|(name, _)| *name)
+        .collect();
+    names.sort_unstable();
+
+    let indices: HashMap<&str, u32> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (*name, i as u32))
+        .collect();
+
+    let mut constants = Vec::new();
+    // lambdas and sections are lifted into functions of their own, which are placed after the
+    // named ones so that their indices don't change
+    let mut lifted = Vec::new();
+    let mut functions = Vec::with_capacity(names.len());
+    for name in &names {
+        if let UniverseItem::FnDecl { args, body, .. } = items[name] {
+            let compiler = FunctionCompiler {
+                items,
+                indices: &indices,
+                constants: &mut constants,
+                lifted: &mut lifted,
+                base: names.len(),
+                name: String::from(*name),
+                code: Vec::new(),
+                locals: 0,
+            };
+            functions.push(compiler.function(args, body)?);
+        }
+    }
+    functions.extend(lifted);
+
+    Ok(Program {
+        constants,
+        functions,
+    })
+}
+
+struct FunctionCompiler<'c> {
+    items: &'c HashMap<&'c str, &'c UniverseItem>,
+    indices: &'c HashMap<&'c str, u32>,
+    constants: &'c mut Vec<Value>,
+    lifted: &'c mut Vec<Function>,
+    /// the index of the first lifted function
+    base: usize,
+    name: String,
+    code: Vec<Instruction>,
+    locals: usize,
+}
+
+impl<'c> FunctionCompiler<'c> {
+    /// compiles the body of a function taking the provided arguments
+    fn function(
+        mut self,
+        args: &[String],
+        body: &[Box<Expression>],
+    ) -> Result<Function, FractalError> {
+        let mut scope = Scope::new();
+        for arg in args {
+            let slot = self.slot()?;
+            scope.insert(arg.clone(), slot);
+        }
+        self.block(&mut scope, body)?;
+        self.code.push(Instruction::Return);
+
+        Ok(Function {
+            name: self.name,
+            arity: args.len(),
+            locals: self.locals,
+            code: self.code,
+        })
+    }
+
+    /// compiles a block, leaving the value of its last expression on the stack
+    fn block(&mut self, scope: &mut Scope, body: &[Box<Expression>]) -> Result<(), FractalError> {
+        match body.split_last() {
+            Some((last, init)) => {
+                for expr in init {
+                    self.expression(scope, expr)?;
+                    self.code.push(Instruction::Pop);
+                }
+                self.expression(scope, last)
+            }
+            None => {
+                self.code.push(Instruction::Unit);
+                Ok(())
+            }
+        }
+    }
+
+    /// compiles an expression, leaving its value on the stack
+    fn expression(&mut self, scope: &mut Scope, expr: &Expression) -> Result<(), FractalError> {
+        match expr {
+            Expression::Integer(int) => {
+                let constant = self.constant(Value::Int(int.clone()))?;
+                self.code.push(Instruction::Constant(constant));
+            }
+            Expression::Float(float) => {
+                let constant = self.constant(Value::Float(float.0))?;
+                self.code.push(Instruction::Constant(constant));
+            }
+            Expression::StringLiteral(string) => {
+                let constant = self.constant(Value::String(String::from(unquote(string))))?;
+                self.code.push(Instruction::Constant(constant));
+            }
+            Expression::Char(c) => {
+                let constant = self.constant(Value::Char(*c))?;
+                self.code.push(Instruction::Constant(constant));
+            }
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(slot) => self.code.push(Instruction::Load(*slot)),
+                None => match self.items.get(name.as_str()) {
+                    Some(UniverseItem::Constructor { fields, .. }) if fields.is_empty() => {
+                        let name = self.constant(Value::String(name.clone()))?;
+                        self.code.push(Instruction::Construct { name, argc: 0 });
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) if args.is_empty() => {
+                        self.code.push(Instruction::Call {
+                            function: self.indices[name.as_str()],
+                            argc: 0,
+                        });
+                    }
+                    Some(UniverseItem::Constructor { .. }) | Some(UniverseItem::FnDecl { .. }) => {
+                        let constant = self.constant(Value::Function(name.clone()))?;
+                        self.code.push(Instruction::Constant(constant));
+                    }
+                    _ => return Err(FractalError::UnboundName(name.clone())),
+                },
+            },
+            Expression::List(items) => {
+                for item in items {
+                    self.expression(scope, item)?;
+                }
+                let length = u16::try_from(items.len())
+                    .map_err(|_| limit("list literals may have at most 65535 elements"))?;
+                self.code.push(Instruction::List(length));
+            }
+            Expression::OpCall { op, args } => match op {
+                OpSymbol::DoubleAmpersand | OpSymbol::DoublePipe => {
+                    self.short_circuit(scope, op, args)?
+                }
+                // user-defined operators are calls to the function they're bound to
+                OpSymbol::Custom(_) => match self.items.get(op.to_string().as_str()) {
+                    Some(UniverseItem::Operator { function }) => {
+                        let args: Vec<Expression> =
+                            args.iter().map(|arg| arg.as_ref().clone()).collect();
+                        self.call(scope, function, &args)?
+                    }
+                    _ => return Err(FractalError::UnboundName(op.to_string())),
+                },
+                _ => {
+                    for arg in args {
+                        self.expression(scope, arg)?;
+                    }
+                    self.code.push(match op {
+                        OpSymbol::Plus => Instruction::Add,
+                        OpSymbol::Minus => Instruction::Sub,
+                        OpSymbol::Star => Instruction::Mul,
+                        OpSymbol::ForwardSlash => Instruction::Div,
+                        OpSymbol::Percent => Instruction::Rem,
+                        OpSymbol::LAngleBracket => Instruction::Lt,
+                        OpSymbol::RAngleBracket => Instruction::Gt,
+                        OpSymbol::LAngleBracketEqual => Instruction::Le,
+                        OpSymbol::RAngleBracketEqual => Instruction::Ge,
+                        OpSymbol::Ampersand => Instruction::BitAnd,
+                        OpSymbol::Pipe => Instruction::BitOr,
+                        OpSymbol::Xor => Instruction::BitXor,
+                        OpSymbol::DoubleLAngleBracket => Instruction::Shl,
+                        OpSymbol::DoubleRAngleBracket => Instruction::Shr,
+                        OpSymbol::DoubleEqual => Instruction::Eq,
+                        OpSymbol::BangEqual => Instruction::Ne,
+                        _ => {
+                            return Err(FractalError::Unsupported(format!(
+                                "the `{}` operator at runtime",
+                                op
+                            )))
+                        }
+                    });
+                }
+            },
+            Expression::UnaryOpCall { op, arg } => {
+                self.expression(scope, arg)?;
+                self.code.push(match op {
+                    UnaryOpSymbol::Minus => Instruction::Neg,
+                    UnaryOpSymbol::Bang => Instruction::Not,
+                });
+            }
+            Expression::FnCall { name, args } => self.call(scope, name, args)?,
+            Expression::Apply { callee, args } => {
+                let argc = u8::try_from(args.len())
+                    .map_err(|_| limit("calls may have at most 255 arguments"))?;
+                self.expression(scope, callee)?;
+                for arg in args {
+                    self.expression(scope, arg)?;
+                }
+                self.code.push(Instruction::CallValue(argc));
+            }
+            Expression::Match { expr, args } => self.match_expression(scope, expr, args)?,
+            Expression::ValueDecl { assigns, body } => match body {
+                Some(body) => {
+                    let mut scope = scope.clone();
+                    for assign in assigns {
+                        self.expression(&mut scope, assign)?;
+                        self.code.push(Instruction::Pop);
+                    }
+                    self.block(&mut scope, body)?;
+                }
+                // without a body, the bindings are added to the enclosing block
+                None => {
+                    for assign in assigns {
+                        self.expression(scope, assign)?;
+                        self.code.push(Instruction::Pop);
+                    }
+                    self.code.push(Instruction::Unit);
+                }
+            },
+            Expression::ValueAssign { pat, expr } => {
+                self.expression(scope, expr)?;
+                let value = self.slot()?;
+                self.code.push(Instruction::Store(value));
+
+                // patterns that may not match fail at runtime
+                let mut fails = Vec::new();
+                self.pattern(scope, pat, value, &mut fails)?;
+                if !fails.is_empty() {
+                    let end = self.code.len();
+                    self.code.push(Instruction::Jump(0));
+                    let fail = self.here()?;
+                    for jump in fails {
+                        self.patch(jump, fail);
+                    }
+                    self.code.push(Instruction::NoMatch);
+                    let here = self.here()?;
+                    self.patch(end, here);
+                }
+                self.code.push(Instruction::Unit);
+            }
+            // a lambda is passed every local in scope before its own arguments
+            Expression::Lambda { args, body } => {
+                let mut captures: Vec<(&String, &u16)> = scope.iter().collect();
+                captures.sort_unstable();
+
+                let mut params: Vec<String> =
+                    captures.iter().map(|(name, _)| (*name).clone()).collect();
+                params.extend(args.iter().cloned());
+                let function = self.lift(&params, body)?;
+
+                for (_, slot) in &captures {
+                    self.code.push(Instruction::Load(**slot));
+                }
+                self.closure(function, captures.len())?;
+            }
+            // a section is passed the operands it was given before the missing ones
+            Expression::Section { op, l, r } => {
+                let mut params = Vec::with_capacity(2);
+                let mut missing = Vec::with_capacity(2);
+                for (name, operand) in &[("#l", l), ("#r", r)] {
+                    match operand {
+                        Some(operand) => {
+                            self.expression(scope, operand)?;
+                            params.push(String::from(*name));
+                        }
+                        None => missing.push(String::from(*name)),
+                    }
+                }
+                let captures = params.len();
+                params.extend(missing);
+
+                let body = vec![Box::new(Expression::OpCall {
+                    op: op.clone(),
+                    args: vec![
+                        Box::new(Expression::Identifier(String::from("#l"))),
+                        Box::new(Expression::Identifier(String::from("#r"))),
+                    ],
+                })];
+                let function = self.lift(&params, &body)?;
+                self.closure(function, captures)?;
+            }
+            Expression::TypeDecl { expr, .. } => self.expression(scope, expr)?,
+            _ => {
+                return Err(FractalError::Unsupported(format!(
+                    "compiling {:?} to bytecode",
+                    expr
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        scope: &mut Scope,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<(), FractalError> {
+        let argc =
+            u8::try_from(args.len()).map_err(|_| limit("calls may have at most 255 arguments"))?;
+
+        // builtins take precedence, as they do in the tree walker
+        if let Some(builtin) = Builtin::from_name(name) {
+            for arg in args {
+                self.expression(scope, arg)?;
+            }
+            self.code.push(Instruction::Builtin { builtin, argc });
+            return Ok(());
+        }
+
+        let instruction = match scope.get(name) {
+            Some(slot) => {
+                self.code.push(Instruction::Load(*slot));
+                Instruction::CallValue(argc)
+            }
+            None => match self.items.get(name) {
+                Some(UniverseItem::Constructor { .. }) => Instruction::Construct {
+                    name: self.constant(Value::String(String::from(name)))?,
+                    argc,
+                },
+                Some(UniverseItem::FnDecl { .. }) => Instruction::Call {
+                    function: self.indices[name],
+                    argc,
+                },
+                _ => return Err(FractalError::UnboundName(String::from(name))),
+            },
+        };
+
+        for arg in args {
+            self.expression(scope, arg)?;
+        }
+        self.code.push(instruction);
+        Ok(())
+    }
+
+    fn match_expression(
+        &mut self,
+        scope: &mut Scope,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Result<(), FractalError> {
+        self.expression(scope, expr)?;
+        let scrutinee = self.slot()?;
+        self.code.push(Instruction::Store(scrutinee));
+
+        let mut ends = Vec::with_capacity(arms.len());
+        for arm in arms {
+            match arm {
+                Expression::Destructure { pat, body } => {
+                    // arms bind into their own scope, which is discarded afterwards
+                    let mut arm_scope = scope.clone();
+                    let mut fails = Vec::new();
+                    self.pattern(&mut arm_scope, pat, scrutinee, &mut fails)?;
+                    self.block(&mut arm_scope, body)?;
+
+                    ends.push(self.code.len());
+                    self.code.push(Instruction::Jump(0));
+
+                    // a failed test moves on to the next arm
+                    let next = self.here()?;
+                    for fail in fails {
+                        self.patch(fail, next);
+                    }
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("unexpected match arm: {:?}", arm),
+            }
+        }
+        self.code.push(Instruction::NoMatch);
+
+        let end = self.here()?;
+        for jump in ends {
+            self.patch(jump, end);
+        }
+        Ok(())
+    }
+
+    /// compiles the tests of a pattern against the value in a slot, binding any identifiers in
+    /// it. the indices of jumps taken when the pattern doesn't match are added to `fails`
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        pat: &Pattern,
+        slot: u16,
+        fails: &mut Vec<usize>,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) => match self.items.get(name.as_str()) {
+                Some(UniverseItem::Constructor { .. }) => {
+                    self.code.push(Instruction::Load(slot));
+                    let name = self.constant(Value::String(name.clone()))?;
+                    fails.push(self.code.len());
+                    self.code
+                        .push(Instruction::JumpIfNotVariant { name, target: 0 });
+                }
+                _ => {
+                    let binding = self.slot()?;
+                    self.code.push(Instruction::Load(slot));
+                    self.code.push(Instruction::Store(binding));
+                    scope.insert(name.clone(), binding);
+                }
+            },
+            Pattern::Integer(int) => {
+                let constant = self.constant(Value::Int(int.clone()))?;
+                self.test(slot, fails, |target| Instruction::JumpIfNotEqual {
+                    constant,
+                    target,
+                });
+            }
+            Pattern::StringLiteral(string) => {
+                let constant = self.constant(Value::String(String::from(unquote(string))))?;
+                self.test(slot, fails, |target| Instruction::JumpIfNotEqual {
+                    constant,
+                    target,
+                });
+            }
+            Pattern::Char(c) => {
+                let constant = self.constant(Value::Char(*c))?;
+                self.test(slot, fails, |target| Instruction::JumpIfNotEqual {
+                    constant,
+                    target,
+                });
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let constant = self.range_bound(start)?;
+                    self.test(slot, fails, |target| Instruction::JumpIfLess {
+                        constant,
+                        target,
+                    });
+                }
+                if let Some(end) = end {
+                    let constant = self.range_bound(end)?;
+                    self.test(slot, fails, |target| Instruction::JumpIfNotLess {
+                        constant,
+                        target,
+                    });
+                }
+            }
+            Pattern::Constructor { name, args } => {
+                let constant = self.constant(Value::String(name.clone()))?;
+                self.test(slot, fails, |target| Instruction::JumpIfNotVariant {
+                    name: constant,
+                    target,
+                });
+
+                for (i, arg) in args.iter().enumerate() {
+                    if let Pattern::Wildcard = arg {
+                        continue;
+                    }
+
+                    let field = self.slot()?;
+                    self.code.push(Instruction::Load(slot));
+                    self.code
+                        .push(Instruction::Field(u8::try_from(i).map_err(|_| {
+                            limit("constructors may have at most 255 fields")
+                        })?));
+                    self.code.push(Instruction::Store(field));
+                    self.pattern(scope, arg, field, fails)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// compiles `&&` or `||`, only evaluating the right side if the left doesn't decide the result
+    fn short_circuit(
+        &mut self,
+        scope: &mut Scope,
+        op: &OpSymbol,
+        args: &[Box<Expression>],
+    ) -> Result<(), FractalError> {
+        // the value of the left side that decides the result on its own
+        let decisive = *op == OpSymbol::DoublePipe;
+        let (l, r) = match args {
+            [l, r] => (l, r),
+            // TODO(superwhiskers): remove panic
+            _ => panic!("operator calls must have two arguments"),
+        };
+
+        self.expression(scope, l)?;
+        let constant = self.constant(Value::Bool(!decisive))?;
+        let decided = self.code.len();
+        self.code.push(Instruction::JumpIfNotEqual {
+            constant,
+            target: 0,
+        });
+        self.expression(scope, r)?;
+        let end = self.code.len();
+        self.code.push(Instruction::Jump(0));
+
+        let here = self.here()?;
+        self.patch(decided, here);
+        let constant = self.constant(Value::Bool(decisive))?;
+        self.code.push(Instruction::Constant(constant));
+        let here = self.here()?;
+        self.patch(end, here);
+        Ok(())
+    }
+
+    /// compiles a lambda or section into a function of its own, returning its index
+    fn lift(&mut self, params: &[String], body: &[Box<Expression>]) -> Result<u32, FractalError> {
+        let name = format!("{}#{}", self.name, self.lifted.len());
+        let compiler = FunctionCompiler {
+            items: self.items,
+            indices: self.indices,
+            constants: &mut *self.constants,
+            lifted: &mut *self.lifted,
+            base: self.base,
+            name,
+            code: Vec::new(),
+            locals: 0,
+        };
+        let function = compiler.function(params, body)?;
+
+        // anything lifted out of the function comes before it
+        let index = u32::try_from(self.base + self.lifted.len())
+            .map_err(|_| limit("programs may have at most 4294967296 functions"))?;
+        self.lifted.push(function);
+        Ok(index)
+    }
+
+    /// pops the given number of captured values into a closure over a lifted function
+    fn closure(&mut self, function: u32, captures: usize) -> Result<(), FractalError> {
+        let captures =
+            u8::try_from(captures).map_err(|_| limit("closures may capture at most 255 values"))?;
+        self.code
+            .push(Instruction::MakeClosure { function, captures });
+        Ok(())
+    }
+
+    /// loads the value in a slot and tests it with a jump to be patched later
+    fn test<F>(&mut self, slot: u16, fails: &mut Vec<usize>, jump: F)
+    where
+        F: FnOnce(u32) -> Instruction,
+    {
+        self.code.push(Instruction::Load(slot));
+        fails.push(self.code.len());
+        self.code.push(jump(0));
+    }
+
+    fn range_bound(&mut self, bound: &Pattern) -> Result<u32, FractalError> {
+        match bound {
+            Pattern::Integer(int) => self.constant(Value::Int(int.clone())),
+            Pattern::Char(c) => self.constant(Value::Char(*c)),
+            _ => Err(FractalError::Unsupported(format!(
+                "the range bound `{}`",
+                bound
+            ))),
+        }
+    }
+
+    /// sets the target of the jump at the provided index
+    fn patch(&mut self, at: usize, to: u32) {
+        match &mut self.code[at] {
+            Instruction::Jump(target)
+            | Instruction::JumpIfNotEqual { target, .. }
+            | Instruction::JumpIfNotVariant { target, .. }
+            | Instruction::JumpIfLess { target, .. }
+            | Instruction::JumpIfNotLess { target, .. } => *target = to,
+            // TODO(superwhiskers): remove panic
+            instruction => panic!("attempted to patch a non-jump: {:?}", instruction),
+        }
+    }
+
+    /// the index of the next instruction
+    fn here(&self) -> Result<u32, FractalError> {
+        u32::try_from(self.code.len()).map_err(|_| limit("functions are too large"))
+    }
+
+    /// allocates a new local slot
+    fn slot(&mut self) -> Result<u16, FractalError> {
+        let slot = u16::try_from(self.locals)
+            .map_err(|_| limit("functions may have at most 65536 locals"))?;
+        self.locals += 1;
+        Ok(slot)
+    }
+
+    /// adds a value to the constant pool, reusing an existing entry if there is one
+    fn constant(&mut self, value: Value) -> Result<u32, FractalError> {
+        let index = match self
+            .constants
+            .iter()
+            .position(|constant| *constant == value)
+        {
+            Some(index) => index,
+            None => {
+                self.constants.push(value);
+                self.constants.len() - 1
+            }
+        };
+        u32::try_from(index).map_err(|_| limit("too many constants"))
+    }
+}
+
+fn limit(reason: &str) -> FractalError {
+    FractalError::Unsupported(String::from(reason))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser::ast::Type;
+
+    #[test]
+    fn test_compile_match() {
+        // `f n => match n => 0 => 1 \n _ => n * 2`
+        let item = UniverseItem::FnDecl {
+            sig: Type::None,
+            args: vec![String::from("n")],
+            body: vec![Box::new(Expression::Match {
+                expr: Box::new(Expression::Identifier(String::from("n"))),
+                args: vec![
+                    Expression::Destructure {
+                        pat: Pattern::Integer(0.into()),
+                        body: vec![Box::new(Expression::Integer(1.into()))],
+                    },
+                    Expression::Destructure {
+                        pat: Pattern::Wildcard,
+                        body: vec![Box::new(Expression::OpCall {
+                            op: OpSymbol::Star,
+                            args: vec![
+                                Box::new(Expression::Identifier(String::from("n"))),
+                                Box::new(Expression::Integer(2.into())),
+                            ],
+                        })],
+                    },
+                ],
+            })],
+        };
+        let mut items = HashMap::new();
+        items.insert("f", &item);
+
+        let program = compile(&items).unwrap();
+        assert_eq!(
+            program.constants,
+            vec![
+                Value::Int(0.into()),
+                Value::Int(1.into()),
+                Value::Int(2.into())
+            ]
+        );
+        assert_eq!(
+            program.functions,
+            vec![Function {
+                name: String::from("f"),
+                arity: 1,
+                locals: 2,
+                code: vec![
+                    Instruction::Load(0),
+                    Instruction::Store(1),
+                    Instruction::Load(1),
+                    Instruction::JumpIfNotEqual {
+                        constant: 0,
+                        target: 6
+                    },
+                    Instruction::Constant(1),
+                    Instruction::Jump(11),
+                    Instruction::Load(0),
+                    Instruction::Constant(2),
+                    Instruction::Mul,
+                    Instruction::Jump(11),
+                    Instruction::NoMatch,
+                    Instruction::Return,
+                ],
+            }]
+        );
+    }
+}
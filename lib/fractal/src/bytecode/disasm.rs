@@ -0,0 +1,208 @@
+// a textual listing of modules, used for debugging the compiler
+
+use super::{Instruction, Item, Module, Program};
+use crate::Value;
+use std::fmt::{self, Write};
+use tag::TagName;
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Constant(constant) => write!(f, "constant {}", constant),
+            Instruction::Unit => write!(f, "unit"),
+            Instruction::Load(slot) => write!(f, "load {}", slot),
+            Instruction::Store(slot) => write!(f, "store {}", slot),
+            Instruction::Pop => write!(f, "pop"),
+            Instruction::Add => write!(f, "add"),
+            Instruction::Sub => write!(f, "sub"),
+            Instruction::Mul => write!(f, "mul"),
+            Instruction::Div => write!(f, "div"),
+            Instruction::Lt => write!(f, "lt"),
+            Instruction::Gt => write!(f, "gt"),
+            Instruction::Rem => write!(f, "rem"),
+            Instruction::Le => write!(f, "le"),
+            Instruction::Ge => write!(f, "ge"),
+            Instruction::BitAnd => write!(f, "bit-and"),
+            Instruction::BitOr => write!(f, "bit-or"),
+            Instruction::BitXor => write!(f, "bit-xor"),
+            Instruction::Shl => write!(f, "shl"),
+            Instruction::Shr => write!(f, "shr"),
+            Instruction::Eq => write!(f, "eq"),
+            Instruction::Ne => write!(f, "ne"),
+            Instruction::Neg => write!(f, "neg"),
+            Instruction::Not => write!(f, "not"),
+            Instruction::List(length) => write!(f, "list {}", length),
+            Instruction::Call { function, argc } => write!(f, "call {} {}", function, argc),
+            Instruction::CallValue(argc) => write!(f, "call-value {}", argc),
+            Instruction::MakeClosure { function, captures } => {
+                write!(f, "make-closure {} {}", function, captures)
+            }
+            Instruction::Construct { name, argc } => write!(f, "construct {} {}", name, argc),
+            Instruction::Builtin { builtin, argc } => {
+                write!(f, "builtin {} {}", builtin.name(), argc)
+            }
+            Instruction::Field(field) => write!(f, "field {}", field),
+            Instruction::Jump(target) => write!(f, "jump {}", target),
+            Instruction::JumpIfNotEqual { constant, target } => {
+                write!(f, "jump-if-not-equal {} {}", constant, target)
+            }
+            Instruction::JumpIfNotVariant { name, target } => {
+                write!(f, "jump-if-not-variant {} {}", name, target)
+            }
+            Instruction::JumpIfLess { constant, target } => {
+                write!(f, "jump-if-less {} {}", constant, target)
+            }
+            Instruction::JumpIfNotLess { constant, target } => {
+                write!(f, "jump-if-not-less {} {}", constant, target)
+            }
+            Instruction::NoMatch => write!(f, "no-match"),
+            Instruction::Return => write!(f, "return"),
+        }
+    }
+}
+
+/// lists the constants, bindings and functions of a module
+pub fn disassemble(module: &Module) -> String {
+    // writing to a string never fails
+    let mut out = String::new();
+    let program = &module.program;
+
+    let _ = writeln!(out, "constants:");
+    for (i, constant) in program.constants.iter().enumerate() {
+        let _ = writeln!(out, "  {}: {}", i, literal(constant));
+    }
+
+    let _ = writeln!(out, "\nbindings:");
+    for binding in &module.bindings {
+        let tags: Vec<String> = binding
+            .tags
+            .iter()
+            .map(|tag| match tag {
+                TagName::Primary(name) => format!("*{}", name),
+                TagName::Secondary(name) => name.to_string(),
+            })
+            .collect();
+        let item = match &binding.item {
+            Item::Function(index) => format!("function {}", index),
+            Item::Constructor { ty, arity } => format!("constructor of {} ({})", ty, arity),
+            Item::Type => String::from("type"),
+            Item::Operator { function } => format!("operator calling {}", function),
+            Item::None => String::from("none"),
+        };
+        let _ = writeln!(out, "  {} [{}]: {}", binding.name, tags.join(", "), item);
+    }
+
+    for (i, function) in program.functions.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "\nfunction {} {} (arity {}, locals {}):",
+            i, function.name, function.arity, function.locals
+        );
+        for (at, instruction) in function.code.iter().enumerate() {
+            match comment(program, *instruction) {
+                Some(comment) => {
+                    let _ = writeln!(
+                        out,
+                        "  {:>4}  {:<28}; {}",
+                        at,
+                        instruction.to_string(),
+                        comment
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "  {:>4}  {}", at, instruction);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// displays a constant as it would be written in source
+fn literal(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("{:?}", string),
+        Value::Char(c) => format!("{:?}", c),
+        value => value.to_string(),
+    }
+}
+
+/// describes the constant or function an instruction refers to
+fn comment(program: &Program, instruction: Instruction) -> Option<String> {
+    let constant = |index: u32| program.constants.get(index as usize).map(literal);
+    match instruction {
+        Instruction::Constant(index)
+        | Instruction::Construct { name: index, .. }
+        | Instruction::JumpIfNotEqual {
+            constant: index, ..
+        }
+        | Instruction::JumpIfNotVariant { name: index, .. }
+        | Instruction::JumpIfLess {
+            constant: index, ..
+        }
+        | Instruction::JumpIfNotLess {
+            constant: index, ..
+        } => constant(index),
+        Instruction::Call { function, .. } => program
+            .functions
+            .get(function as usize)
+            .map(|function| function.name.clone()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::{Binding, Function};
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_disassemble() {
+        let module = Module {
+            program: Program {
+                constants: vec![Value::from("none")],
+                functions: vec![Function {
+                    name: "nothing".into(),
+                    arity: 0,
+                    locals: 0,
+                    code: vec![
+                        Instruction::Construct { name: 0, argc: 0 },
+                        Instruction::Return,
+                    ],
+                }],
+            },
+            bindings: vec![
+                Binding {
+                    name: "nothing".into(),
+                    tags: vec![TagName::Primary(Cow::Borrowed("test"))],
+                    item: Item::Function(0),
+                },
+                Binding {
+                    name: "none".into(),
+                    tags: vec![TagName::Secondary(Cow::Borrowed("option"))],
+                    item: Item::Constructor {
+                        ty: "option".into(),
+                        arity: 0,
+                    },
+                },
+            ],
+        };
+
+        assert_eq!(
+            disassemble(&module),
+            "constants:
+  0: \"none\"
+
+bindings:
+  nothing [*test]: function 0
+  none [option]: constructor of option (0)
+
+function 0 nothing (arity 0, locals 0):
+     0  construct 0 0               ; \"none\"
+     1  return
+"
+        );
+    }
+}
@@ -0,0 +1,681 @@
+// the on-disk format of modules
+//
+// every integer is little-endian. a module consists of:
+//
+// - a header: the magic bytes `SNFB` and a u16 version
+// - the constant pool: a u32 count followed by that many values
+// - the binding table: a u32 count followed by each binding's name, tags and item
+// - the functions: a u32 count followed by each function's name, arity, local count and code
+//
+// strings are a u32 length followed by that many bytes of UTF-8
+
+use super::{Binding, Builtin, BytecodeError, Function, Instruction, Item, Module, Program};
+use crate::Value;
+use num_bigint::BigInt;
+use std::borrow::Cow;
+use tag::TagName;
+
+const MAGIC: &[u8; 4] = b"SNFB";
+
+/// the version of the format written by this version of fractal
+pub const VERSION: u16 = 1;
+
+pub(super) fn encode(module: &Module) -> Result<Vec<u8>, BytecodeError> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u16(VERSION);
+
+    writer.length(module.program.constants.len());
+    for constant in &module.program.constants {
+        writer.value(constant)?;
+    }
+
+    writer.length(module.bindings.len());
+    for binding in &module.bindings {
+        writer.string(&binding.name);
+        writer.length(binding.tags.len());
+        for tag in &binding.tags {
+            match tag {
+                TagName::Primary(name) => {
+                    writer.u8(0);
+                    writer.string(name);
+                }
+                TagName::Secondary(name) => {
+                    writer.u8(1);
+                    writer.string(name);
+                }
+            }
+        }
+        match &binding.item {
+            Item::Function(index) => {
+                writer.u8(0);
+                writer.u32(*index);
+            }
+            Item::Constructor { ty, arity } => {
+                writer.u8(1);
+                writer.string(ty);
+                writer.u32(*arity);
+            }
+            Item::Type => writer.u8(2),
+            Item::None => writer.u8(3),
+            Item::Operator { function } => {
+                writer.u8(4);
+                writer.string(function);
+            }
+        }
+    }
+
+    writer.length(module.program.functions.len());
+    for function in &module.program.functions {
+        writer.string(&function.name);
+        writer.length(function.arity);
+        writer.length(function.locals);
+        writer.length(function.code.len());
+        for instruction in &function.code {
+            writer.instruction(*instruction)?;
+        }
+    }
+
+    Ok(writer.bytes)
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<Module, BytecodeError> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(BytecodeError::BadMagic);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+
+    let count = reader.length()?;
+    let mut constants = Vec::new();
+    for _ in 0..count {
+        constants.push(reader.value()?);
+    }
+
+    let count = reader.length()?;
+    let mut bindings = Vec::new();
+    for _ in 0..count {
+        let name = reader.string()?;
+        let tag_count = reader.length()?;
+        let mut tags = Vec::new();
+        for _ in 0..tag_count {
+            tags.push(match reader.u8()? {
+                0 => TagName::Primary(Cow::Owned(reader.string()?)),
+                1 => TagName::Secondary(Cow::Owned(reader.string()?)),
+                kind => return Err(BytecodeError::InvalidEncoding("tag", kind)),
+            });
+        }
+        let item = match reader.u8()? {
+            0 => Item::Function(reader.u32()?),
+            1 => Item::Constructor {
+                ty: reader.string()?,
+                arity: reader.u32()?,
+            },
+            2 => Item::Type,
+            3 => Item::None,
+            4 => Item::Operator {
+                function: reader.string()?,
+            },
+            kind => return Err(BytecodeError::InvalidEncoding("binding", kind)),
+        };
+        bindings.push(Binding { name, tags, item });
+    }
+
+    let count = reader.length()?;
+    let mut functions = Vec::new();
+    for _ in 0..count {
+        let name = reader.string()?;
+        let arity = reader.length()?;
+        let locals = reader.length()?;
+        let length = reader.length()?;
+        let mut code = Vec::new();
+        for _ in 0..length {
+            code.push(reader.instruction()?);
+        }
+        functions.push(Function {
+            name,
+            arity,
+            locals,
+            code,
+        });
+    }
+
+    if reader.at != bytes.len() {
+        return Err(BytecodeError::TrailingBytes);
+    }
+
+    Ok(Module {
+        program: Program {
+            constants,
+            functions,
+        },
+        bindings,
+    })
+}
+
+// the opcodes of each instruction
+const CONSTANT: u8 = 0;
+const UNIT: u8 = 1;
+const LOAD: u8 = 2;
+const STORE: u8 = 3;
+const POP: u8 = 4;
+const ADD: u8 = 5;
+const SUB: u8 = 6;
+const MUL: u8 = 7;
+const DIV: u8 = 8;
+const LT: u8 = 9;
+const GT: u8 = 10;
+const LIST: u8 = 11;
+const CALL: u8 = 12;
+const CALL_VALUE: u8 = 13;
+const CONSTRUCT: u8 = 14;
+const BUILTIN: u8 = 15;
+const FIELD: u8 = 16;
+const JUMP: u8 = 17;
+const JUMP_IF_NOT_EQUAL: u8 = 18;
+const JUMP_IF_NOT_VARIANT: u8 = 19;
+const JUMP_IF_LESS: u8 = 20;
+const JUMP_IF_NOT_LESS: u8 = 21;
+const NO_MATCH: u8 = 22;
+const RETURN: u8 = 23;
+const REM: u8 = 24;
+const LE: u8 = 25;
+const GE: u8 = 26;
+const BIT_AND: u8 = 27;
+const BIT_OR: u8 = 28;
+const BIT_XOR: u8 = 29;
+const SHL: u8 = 30;
+const SHR: u8 = 31;
+const EQ: u8 = 32;
+const NE: u8 = 33;
+const NEG: u8 = 34;
+const NOT: u8 = 35;
+const MAKE_CLOSURE: u8 = 36;
+
+// the kinds of each value
+const INT: u8 = 0;
+const FLOAT: u8 = 1;
+const STRING: u8 = 2;
+const BOOL: u8 = 3;
+const LIST_VALUE: u8 = 4;
+const RECORD: u8 = 5;
+const VARIANT: u8 = 6;
+const FUNCTION: u8 = 7;
+const UNIT_VALUE: u8 = 8;
+const CHAR: u8 = 9;
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // lengths are limited to u32 by the format
+    fn length(&mut self, length: usize) {
+        self.u32(length as u32);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.length(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn value(&mut self, value: &Value) -> Result<(), BytecodeError> {
+        match value {
+            Value::Int(int) => {
+                self.u8(INT);
+                let bytes = int.to_signed_bytes_le();
+                self.length(bytes.len());
+                self.bytes.extend_from_slice(&bytes);
+            }
+            Value::Float(float) => {
+                self.u8(FLOAT);
+                self.bytes.extend_from_slice(&float.to_bits().to_le_bytes());
+            }
+            Value::String(string) => {
+                self.u8(STRING);
+                self.string(string);
+            }
+            Value::Char(c) => {
+                self.u8(CHAR);
+                self.u32(*c as u32);
+            }
+            Value::Bool(b) => {
+                self.u8(BOOL);
+                self.u8(*b as u8);
+            }
+            Value::List(items) => {
+                self.u8(LIST_VALUE);
+                self.length(items.len());
+                for item in items {
+                    self.value(item)?;
+                }
+            }
+            Value::Record(fields) => {
+                self.u8(RECORD);
+                self.length(fields.len());
+                for (name, value) in fields {
+                    self.string(name);
+                    self.value(value)?;
+                }
+            }
+            Value::Variant { name, fields } => {
+                self.u8(VARIANT);
+                self.string(name);
+                self.length(fields.len());
+                for field in fields {
+                    self.value(field)?;
+                }
+            }
+            Value::Function(name) => {
+                self.u8(FUNCTION);
+                self.string(name);
+            }
+            // sections and closures are never constants of compiled code
+            Value::Section { .. } | Value::Closure { .. } | Value::Partial { .. } => {
+                return Err(BytecodeError::Unencodable(format!("the value {:?}", value)))
+            }
+            Value::Unit => self.u8(UNIT_VALUE),
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, instruction: Instruction) -> Result<(), BytecodeError> {
+        match instruction {
+            Instruction::Constant(constant) => {
+                self.u8(CONSTANT);
+                self.u32(constant);
+            }
+            Instruction::Unit => self.u8(UNIT),
+            Instruction::Load(slot) => {
+                self.u8(LOAD);
+                self.u16(slot);
+            }
+            Instruction::Store(slot) => {
+                self.u8(STORE);
+                self.u16(slot);
+            }
+            Instruction::Pop => self.u8(POP),
+            Instruction::Add => self.u8(ADD),
+            Instruction::Sub => self.u8(SUB),
+            Instruction::Mul => self.u8(MUL),
+            Instruction::Div => self.u8(DIV),
+            Instruction::Lt => self.u8(LT),
+            Instruction::Gt => self.u8(GT),
+            Instruction::Rem => self.u8(REM),
+            Instruction::Le => self.u8(LE),
+            Instruction::Ge => self.u8(GE),
+            Instruction::BitAnd => self.u8(BIT_AND),
+            Instruction::BitOr => self.u8(BIT_OR),
+            Instruction::BitXor => self.u8(BIT_XOR),
+            Instruction::Shl => self.u8(SHL),
+            Instruction::Shr => self.u8(SHR),
+            Instruction::Eq => self.u8(EQ),
+            Instruction::Ne => self.u8(NE),
+            Instruction::Neg => self.u8(NEG),
+            Instruction::Not => self.u8(NOT),
+            Instruction::List(length) => {
+                self.u8(LIST);
+                self.u16(length);
+            }
+            Instruction::Call { function, argc } => {
+                self.u8(CALL);
+                self.u32(function);
+                self.u8(argc);
+            }
+            Instruction::CallValue(argc) => {
+                self.u8(CALL_VALUE);
+                self.u8(argc);
+            }
+            Instruction::MakeClosure { function, captures } => {
+                self.u8(MAKE_CLOSURE);
+                self.u32(function);
+                self.u8(captures);
+            }
+            Instruction::Construct { name, argc } => {
+                self.u8(CONSTRUCT);
+                self.u32(name);
+                self.u8(argc);
+            }
+            Instruction::Builtin { builtin, argc } => {
+                self.u8(BUILTIN);
+                match Builtin::ALL.iter().position(|b| *b == builtin) {
+                    Some(index) => self.u8(index as u8),
+                    None => {
+                        return Err(BytecodeError::Unencodable(format!(
+                            "the builtin {:?}",
+                            builtin
+                        )))
+                    }
+                }
+                self.u8(argc);
+            }
+            Instruction::Field(field) => {
+                self.u8(FIELD);
+                self.u8(field);
+            }
+            Instruction::Jump(target) => {
+                self.u8(JUMP);
+                self.u32(target);
+            }
+            Instruction::JumpIfNotEqual { constant, target } => {
+                self.u8(JUMP_IF_NOT_EQUAL);
+                self.u32(constant);
+                self.u32(target);
+            }
+            Instruction::JumpIfNotVariant { name, target } => {
+                self.u8(JUMP_IF_NOT_VARIANT);
+                self.u32(name);
+                self.u32(target);
+            }
+            Instruction::JumpIfLess { constant, target } => {
+                self.u8(JUMP_IF_LESS);
+                self.u32(constant);
+                self.u32(target);
+            }
+            Instruction::JumpIfNotLess { constant, target } => {
+                self.u8(JUMP_IF_NOT_LESS);
+                self.u32(constant);
+                self.u32(target);
+            }
+            Instruction::NoMatch => self.u8(NO_MATCH),
+            Instruction::Return => self.u8(RETURN),
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    at: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], BytecodeError> {
+        let end = self
+            .at
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(BytecodeError::UnexpectedEnd)?;
+        let bytes = &self.bytes[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn length(&mut self) -> Result<usize, BytecodeError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let length = self.length()?;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| BytecodeError::InvalidUtf8)
+    }
+
+    fn value(&mut self) -> Result<Value, BytecodeError> {
+        Ok(match self.u8()? {
+            INT => {
+                let length = self.length()?;
+                Value::Int(BigInt::from_signed_bytes_le(self.take(length)?))
+            }
+            FLOAT => Value::Float(f64::from_bits(self.u64()?)),
+            STRING => Value::String(self.string()?),
+            CHAR => {
+                let c = self.u32()?;
+                Value::Char(std::char::from_u32(c).ok_or(BytecodeError::InvalidChar(c))?)
+            }
+            BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                b => return Err(BytecodeError::InvalidEncoding("bool", b)),
+            },
+            LIST_VALUE => {
+                let length = self.length()?;
+                let mut items = Vec::new();
+                for _ in 0..length {
+                    items.push(self.value()?);
+                }
+                Value::List(items)
+            }
+            RECORD => {
+                let length = self.length()?;
+                let mut fields = Vec::new();
+                for _ in 0..length {
+                    fields.push((self.string()?, self.value()?));
+                }
+                Value::Record(fields)
+            }
+            VARIANT => {
+                let name = self.string()?;
+                let length = self.length()?;
+                let mut fields = Vec::new();
+                for _ in 0..length {
+                    fields.push(self.value()?);
+                }
+                Value::Variant { name, fields }
+            }
+            FUNCTION => Value::Function(self.string()?),
+            UNIT_VALUE => Value::Unit,
+            kind => return Err(BytecodeError::InvalidEncoding("value", kind)),
+        })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, BytecodeError> {
+        Ok(match self.u8()? {
+            CONSTANT => Instruction::Constant(self.u32()?),
+            UNIT => Instruction::Unit,
+            LOAD => Instruction::Load(self.u16()?),
+            STORE => Instruction::Store(self.u16()?),
+            POP => Instruction::Pop,
+            ADD => Instruction::Add,
+            SUB => Instruction::Sub,
+            MUL => Instruction::Mul,
+            DIV => Instruction::Div,
+            LT => Instruction::Lt,
+            GT => Instruction::Gt,
+            REM => Instruction::Rem,
+            LE => Instruction::Le,
+            GE => Instruction::Ge,
+            BIT_AND => Instruction::BitAnd,
+            BIT_OR => Instruction::BitOr,
+            BIT_XOR => Instruction::BitXor,
+            SHL => Instruction::Shl,
+            SHR => Instruction::Shr,
+            EQ => Instruction::Eq,
+            NE => Instruction::Ne,
+            NEG => Instruction::Neg,
+            NOT => Instruction::Not,
+            LIST => Instruction::List(self.u16()?),
+            CALL => Instruction::Call {
+                function: self.u32()?,
+                argc: self.u8()?,
+            },
+            CALL_VALUE => Instruction::CallValue(self.u8()?),
+            MAKE_CLOSURE => Instruction::MakeClosure {
+                function: self.u32()?,
+                captures: self.u8()?,
+            },
+            CONSTRUCT => Instruction::Construct {
+                name: self.u32()?,
+                argc: self.u8()?,
+            },
+            BUILTIN => {
+                let index = self.u8()?;
+                Instruction::Builtin {
+                    builtin: *Builtin::ALL
+                        .get(index as usize)
+                        .ok_or(BytecodeError::InvalidEncoding("builtin", index))?,
+                    argc: self.u8()?,
+                }
+            }
+            FIELD => Instruction::Field(self.u8()?),
+            JUMP => Instruction::Jump(self.u32()?),
+            JUMP_IF_NOT_EQUAL => Instruction::JumpIfNotEqual {
+                constant: self.u32()?,
+                target: self.u32()?,
+            },
+            JUMP_IF_NOT_VARIANT => Instruction::JumpIfNotVariant {
+                name: self.u32()?,
+                target: self.u32()?,
+            },
+            JUMP_IF_LESS => Instruction::JumpIfLess {
+                constant: self.u32()?,
+                target: self.u32()?,
+            },
+            JUMP_IF_NOT_LESS => Instruction::JumpIfNotLess {
+                constant: self.u32()?,
+                target: self.u32()?,
+            },
+            NO_MATCH => Instruction::NoMatch,
+            RETURN => Instruction::Return,
+            opcode => return Err(BytecodeError::InvalidEncoding("opcode", opcode)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser::ast::OpSymbol;
+
+    fn module() -> Module {
+        Module {
+            program: Program {
+                constants: vec![
+                    Value::Int(BigInt::from(-300)),
+                    Value::from("ok"),
+                    Value::from('λ'),
+                    Value::Float(0.5),
+                    Value::List(vec![Value::Unit, true.into()]),
+                    Value::Variant {
+                        name: "some".into(),
+                        fields: vec![Value::Function("f".into())],
+                    },
+                    Value::Record(vec![("x".into(), Value::Int(BigInt::from(1)))]),
+                ],
+                functions: vec![Function {
+                    name: "f".into(),
+                    arity: 1,
+                    locals: 2,
+                    code: vec![
+                        Instruction::Load(0),
+                        Instruction::Not,
+                        Instruction::MakeClosure {
+                            function: 0,
+                            captures: 1,
+                        },
+                        Instruction::JumpIfNotEqual {
+                            constant: 0,
+                            target: 5,
+                        },
+                        Instruction::Builtin {
+                            builtin: Builtin::Fold,
+                            argc: 3,
+                        },
+                        Instruction::Return,
+                        Instruction::NoMatch,
+                    ],
+                }],
+            },
+            bindings: vec![Binding {
+                name: "f".into(),
+                tags: vec![
+                    TagName::Primary(Cow::Borrowed("test")),
+                    TagName::Secondary(Cow::Borrowed("nothing")),
+                ],
+                item: Item::Function(0),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let module = module();
+        assert_eq!(decode(&encode(&module).unwrap()), Ok(module));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bytes = encode(&module()).unwrap();
+
+        assert_eq!(decode(b"ELF\x7f\x01\x00"), Err(BytecodeError::BadMagic));
+        assert_eq!(
+            decode(b"SNFB\x02\x00"),
+            Err(BytecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEnd)
+        );
+
+        // a surrogate in place of the char constant
+        let mut surrogate = bytes.clone();
+        let at = bytes
+            .windows(5)
+            .position(|window| window == [CHAR, 0xbb, 0x03, 0x00, 0x00])
+            .unwrap();
+        surrogate[at + 1..at + 5].copy_from_slice(&0xd800u32.to_le_bytes());
+        assert_eq!(decode(&surrogate), Err(BytecodeError::InvalidChar(0xd800)));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode(&trailing), Err(BytecodeError::TrailingBytes));
+
+        // the opcode of the final instruction
+        let mut invalid = bytes;
+        *invalid.last_mut().unwrap() = 0xff;
+        assert_eq!(
+            decode(&invalid),
+            Err(BytecodeError::InvalidEncoding("opcode", 0xff))
+        );
+    }
+
+    #[test]
+    fn test_unencodable() {
+        let mut module = module();
+        module.program.constants.push(Value::Section {
+            op: OpSymbol::Star,
+            l: None,
+            r: Some(Box::new(Value::Int(BigInt::from(2)))),
+        });
+        assert!(matches!(
+            encode(&module),
+            Err(BytecodeError::Unencodable(_))
+        ));
+    }
+}
@@ -0,0 +1,314 @@
+// a compact instruction set for fractal programs, along with a compiler to it and a stack-based
+// virtual machine that executes it
+//
+// each function is compiled to a flat list of instructions operating on a value stack and a fixed
+// number of local slots, the first of which hold the function's arguments
+//
+// compiled programs may be stored as modules alongside the tags of their bindings, allowing them
+// to be loaded without the original source
+
+use crate::{FractalError, UniverseItem, Value};
+use std::{borrow::Cow, collections::HashMap};
+use tag::{TagName, Universe, UniverseEntry, UniverseError};
+use thiserror::Error;
+
+mod compiler;
+mod disasm;
+mod format;
+mod verify;
+mod vm;
+
+pub use disasm::disassemble;
+pub use format::VERSION;
+pub use verify::verify;
+pub use vm::Vm;
+
+/// a compiled program along with the bindings of the universe it was compiled from
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub program: Program,
+    pub bindings: Vec<Binding>,
+}
+
+/// a binding in a module, with the tags it had in its universe
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub tags: Vec<TagName<'static>>,
+    pub item: Item,
+}
+
+/// the value of a binding in a module
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum Item {
+    /// a function, referred to by its index in the program
+    Function(u32),
+    Constructor {
+        ty: String,
+        arity: u32,
+    },
+    Type,
+    /// a user-defined operator, referring to the binding of the function it calls
+    Operator {
+        function: String,
+    },
+
+    // dummy variant used for implementing Default
+    #[default]
+    None,
+}
+
+impl Module {
+    /// encodes the module in the on-disk format
+    pub fn encode(&self) -> Result<Vec<u8>, BytecodeError> {
+        format::encode(self)
+    }
+
+    /// decodes a module from the on-disk format without verifying it. see [`load`]
+    pub fn decode(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        format::decode(bytes)
+    }
+
+    /// reconstructs the universe the module was compiled from
+    pub fn universe(
+        &self,
+    ) -> Result<(Universe<'static, Item>, Vec<UniverseEntry<'static, Item>>), UniverseError> {
+        let mut universe = Universe::default();
+        let mut entries = Vec::with_capacity(self.bindings.len());
+        for binding in &self.bindings {
+            entries.push(universe.insert(|b| {
+                b.set_name(Cow::Owned(binding.name.clone()))
+                    .set_value(binding.item.clone());
+                for tag in &binding.tags {
+                    b.add_tag(tag.clone());
+                }
+                b
+            })?);
+        }
+        Ok((universe, entries))
+    }
+}
+
+/// decodes and verifies a module
+pub fn load(bytes: &[u8]) -> Result<Module, BytecodeError> {
+    let module = Module::decode(bytes)?;
+    verify(&module)?;
+    Ok(module)
+}
+
+/// converts a tag name into one that owns its contents
+pub(crate) fn owned_tag_name(tag: &TagName<'_>) -> TagName<'static> {
+    match tag {
+        TagName::Primary(name) => TagName::Primary(Cow::Owned(name.to_string())),
+        TagName::Secondary(name) => TagName::Secondary(Cow::Owned(name.to_string())),
+    }
+}
+
+#[non_exhaustive]
+#[derive(Error, Debug, PartialEq)]
+pub enum BytecodeError {
+    #[error("The file is not a snowflake bytecode module")]
+    BadMagic,
+
+    #[error("Unsupported bytecode version {0} (expected {})", VERSION)]
+    UnsupportedVersion(u16),
+
+    #[error("Unexpected end of the module")]
+    UnexpectedEnd,
+
+    #[error("Unexpected data after the end of the module")]
+    TrailingBytes,
+
+    #[error("Invalid {0} encoding `{1}`")]
+    InvalidEncoding(&'static str, u8),
+
+    #[error("Invalid char encoding `{0:#x}`")]
+    InvalidChar(u32),
+
+    #[error("Invalid UTF-8 in a string")]
+    InvalidUtf8,
+
+    #[error("Invalid code in `{function}` at {at}: {reason}")]
+    InvalidCode {
+        function: String,
+        at: usize,
+        reason: &'static str,
+    },
+
+    #[error("Invalid binding `{name}`: {reason}")]
+    InvalidBinding { name: String, reason: &'static str },
+
+    #[error("Unable to encode {0} in a module")]
+    Unencodable(String),
+}
+
+/// a compiled program
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    /// the constants referred to by instructions, shared across every function
+    pub constants: Vec<Value>,
+    pub functions: Vec<Function>,
+}
+
+/// a compiled function
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+
+    /// the number of local slots used, including the arguments
+    pub locals: usize,
+    pub code: Vec<Instruction>,
+}
+
+/// a single instruction. jump targets are indices into the code of the current function
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    /// pushes a constant
+    Constant(u32),
+    /// pushes the unit value
+    Unit,
+    /// pushes the value of a local slot
+    Load(u16),
+    /// pops a value into a local slot
+    Store(u16),
+    /// discards the value on top of the stack
+    Pop,
+
+    // integer operators, popping the right operand and then the left one
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    // compares any two values, popping the right one and then the left one
+    Eq,
+    Ne,
+    /// negates the integer on top of the stack
+    Neg,
+    /// negates the bool or inverts the bits of the integer on top of the stack
+    Not,
+
+    /// pops the given number of values into a list
+    List(u16),
+    /// calls a function in the program with the arguments on top of the stack
+    Call {
+        function: u32,
+        argc: u8,
+    },
+    /// calls the function reference below the arguments on top of the stack
+    CallValue(u8),
+    /// pops the given number of values, pushing a reference to a function in the program that
+    /// passes them before the arguments it is called with
+    MakeClosure {
+        function: u32,
+        captures: u8,
+    },
+    /// builds a variant value using the constructor named by a constant
+    Construct {
+        name: u32,
+        argc: u8,
+    },
+    /// calls a builtin function
+    Builtin {
+        builtin: Builtin,
+        argc: u8,
+    },
+    /// replaces the variant value on top of the stack with one of its fields
+    Field(u8),
+
+    Jump(u32),
+    /// pops a value, jumping if it isn't equal to the constant
+    JumpIfNotEqual {
+        constant: u32,
+        target: u32,
+    },
+    /// pops a value, jumping if it wasn't built by the constructor named by the constant
+    JumpIfNotVariant {
+        name: u32,
+        target: u32,
+    },
+    /// pops an integer, jumping if it is less than the constant
+    JumpIfLess {
+        constant: u32,
+        target: u32,
+    },
+    /// pops an integer, jumping if it is greater than or equal to the constant
+    JumpIfNotLess {
+        constant: u32,
+        target: u32,
+    },
+
+    /// reports that no arm of a match expression matched its value
+    NoMatch,
+    /// returns the value on top of the stack
+    Return,
+}
+
+/// the functions built into the language
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Builtin {
+    Println,
+    Len,
+    Get,
+    Push,
+    Concat,
+    Map,
+    Fold,
+}
+
+impl Builtin {
+    pub const ALL: &'static [Builtin] = &[
+        Builtin::Println,
+        Builtin::Len,
+        Builtin::Get,
+        Builtin::Push,
+        Builtin::Concat,
+        Builtin::Map,
+        Builtin::Fold,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Println => "println",
+            Builtin::Len => "len",
+            Builtin::Get => "get",
+            Builtin::Push => "push",
+            Builtin::Concat => "concat",
+            Builtin::Map => "map",
+            Builtin::Fold => "fold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    /// checks whether the builtin can be called with the given number of arguments
+    pub fn accepts(self, argc: u8) -> bool {
+        match self {
+            Builtin::Println => argc <= 1,
+            Builtin::Len => argc == 1,
+            Builtin::Get | Builtin::Push | Builtin::Concat | Builtin::Map => argc == 2,
+            Builtin::Fold => argc == 3,
+        }
+    }
+}
+
+/// compiles every function in a universe, in order of their names, followed by the lambdas and
+/// sections lifted out of them
+pub fn compile(items: &HashMap<&str, &UniverseItem>) -> Result<Program, FractalError> {
+    compiler::compile(items)
+}
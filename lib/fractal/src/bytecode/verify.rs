@@ -0,0 +1,379 @@
+// verification of loaded modules
+//
+// a module read from disk may have been produced by anything, so before it is run every operand is
+// checked to be in bounds and the depth of the stack is checked to be the same along every path to
+// each instruction, allowing the virtual machine to trust the shape of the code it executes. the
+// kinds of values are not checked, so the virtual machine reports instructions applied to values
+// they can't operate on as errors instead
+
+use super::{BytecodeError, Function, Instruction, Item, Module, Program};
+use crate::Value;
+
+/// checks that a module is well-formed
+pub fn verify(module: &Module) -> Result<(), BytecodeError> {
+    for function in &module.program.functions {
+        verify_function(&module.program, function)?;
+    }
+
+    for binding in &module.bindings {
+        let invalid = |reason| BytecodeError::InvalidBinding {
+            name: binding.name.clone(),
+            reason,
+        };
+        match &binding.item {
+            Item::Function(index) => match module.program.functions.get(*index as usize) {
+                Some(function) if function.name == binding.name => (),
+                Some(_) => return Err(invalid("the function has a different name")),
+                None => return Err(invalid("the function does not exist")),
+            },
+            Item::Operator { function }
+                if !module.bindings.iter().any(|other| {
+                    other.name == *function
+                        && matches!(other.item, Item::Function(_) | Item::Constructor { .. })
+                }) =>
+            {
+                return Err(invalid("the operator's function does not exist"))
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_function(program: &Program, function: &Function) -> Result<(), BytecodeError> {
+    let invalid = |at, reason| BytecodeError::InvalidCode {
+        function: function.name.clone(),
+        at,
+        reason,
+    };
+
+    if function.arity > function.locals {
+        return Err(invalid(0, "there are fewer local slots than arguments"));
+    }
+
+    // check the operands of each instruction
+    for (at, instruction) in function.code.iter().enumerate() {
+        let constant = |constant: u32| {
+            program
+                .constants
+                .get(constant as usize)
+                .ok_or_else(|| invalid(at, "the constant does not exist"))
+        };
+        let target = |target: u32| {
+            if (target as usize) < function.code.len() {
+                Ok(())
+            } else {
+                Err(invalid(at, "the jump target is out of bounds"))
+            }
+        };
+        let name = |name: u32| match constant(name)? {
+            Value::String(_) => Ok(()),
+            _ => Err(invalid(at, "the constant is not a name")),
+        };
+        let bound = |bound: u32| match constant(bound)? {
+            Value::Int(_) | Value::Char(_) => Ok(()),
+            _ => Err(invalid(at, "the constant is not an integer or char")),
+        };
+
+        match *instruction {
+            Instruction::Constant(c) => {
+                constant(c)?;
+            }
+            Instruction::Load(slot) | Instruction::Store(slot)
+                if slot as usize >= function.locals =>
+            {
+                return Err(invalid(at, "the local slot is out of bounds"));
+            }
+            Instruction::Call { function, argc } => {
+                match program.functions.get(function as usize) {
+                    Some(callee) if callee.arity == argc as usize => (),
+                    Some(_) => return Err(invalid(at, "the wrong number of arguments are passed")),
+                    None => return Err(invalid(at, "the function does not exist")),
+                }
+            }
+            Instruction::MakeClosure { function, captures } => {
+                match program.functions.get(function as usize) {
+                    Some(callee) if callee.arity >= captures as usize => (),
+                    Some(_) => return Err(invalid(at, "more values are captured than are taken")),
+                    None => return Err(invalid(at, "the function does not exist")),
+                }
+            }
+            Instruction::Construct { name: n, .. } => name(n)?,
+            Instruction::Builtin { builtin, argc } if !builtin.accepts(argc) => {
+                return Err(invalid(at, "the wrong number of arguments are passed"));
+            }
+            Instruction::Jump(t) => target(t)?,
+            Instruction::JumpIfNotEqual {
+                constant: c,
+                target: t,
+            } => {
+                constant(c)?;
+                target(t)?;
+            }
+            Instruction::JumpIfNotVariant { name: n, target: t } => {
+                name(n)?;
+                target(t)?;
+            }
+            Instruction::JumpIfLess {
+                constant: c,
+                target: t,
+            }
+            | Instruction::JumpIfNotLess {
+                constant: c,
+                target: t,
+            } => {
+                bound(c)?;
+                target(t)?;
+            }
+            _ => (),
+        }
+    }
+
+    // follow every path through the code, recording the depth of the stack before each
+    // instruction
+    let mut depths: Vec<Option<usize>> = vec![None; function.code.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((at, depth)) = pending.pop() {
+        let instruction = match function.code.get(at) {
+            Some(instruction) => *instruction,
+            None => return Err(invalid(at, "execution runs past the end of the code")),
+        };
+        match depths[at] {
+            Some(known) if known == depth => continue,
+            Some(_) => return Err(invalid(at, "the stack depth differs between paths")),
+            None => depths[at] = Some(depth),
+        }
+
+        let (pops, pushes) = effect(instruction);
+        let depth = depth
+            .checked_sub(pops)
+            .ok_or_else(|| invalid(at, "the stack underflows"))?
+            + pushes;
+
+        match instruction {
+            Instruction::Return if depth != 0 => {
+                return Err(invalid(at, "values are left on the stack when returning"))
+            }
+            Instruction::Return | Instruction::NoMatch => (),
+            Instruction::Jump(target) => pending.push((target as usize, depth)),
+            Instruction::JumpIfNotEqual { target, .. }
+            | Instruction::JumpIfNotVariant { target, .. }
+            | Instruction::JumpIfLess { target, .. }
+            | Instruction::JumpIfNotLess { target, .. } => {
+                pending.push((target as usize, depth));
+                pending.push((at + 1, depth));
+            }
+            _ => pending.push((at + 1, depth)),
+        }
+    }
+
+    Ok(())
+}
+
+/// the number of values an instruction pops and then pushes
+fn effect(instruction: Instruction) -> (usize, usize) {
+    match instruction {
+        Instruction::Constant(_) | Instruction::Unit | Instruction::Load(_) => (0, 1),
+        Instruction::Store(_) | Instruction::Pop => (1, 0),
+        Instruction::Add
+        | Instruction::Sub
+        | Instruction::Mul
+        | Instruction::Div
+        | Instruction::Rem
+        | Instruction::Lt
+        | Instruction::Gt
+        | Instruction::Le
+        | Instruction::Ge
+        | Instruction::BitAnd
+        | Instruction::BitOr
+        | Instruction::BitXor
+        | Instruction::Shl
+        | Instruction::Shr
+        | Instruction::Eq
+        | Instruction::Ne => (2, 1),
+        Instruction::Neg | Instruction::Not => (1, 1),
+        Instruction::List(length) => (length as usize, 1),
+        Instruction::Call { argc, .. }
+        | Instruction::Construct { argc, .. }
+        | Instruction::Builtin { argc, .. } => (argc as usize, 1),
+        Instruction::CallValue(argc) => (argc as usize + 1, 1),
+        Instruction::MakeClosure { captures, .. } => (captures as usize, 1),
+        Instruction::Field(_) => (1, 1),
+        Instruction::Jump(_) | Instruction::NoMatch => (0, 0),
+        Instruction::JumpIfNotEqual { .. }
+        | Instruction::JumpIfNotVariant { .. }
+        | Instruction::JumpIfLess { .. }
+        | Instruction::JumpIfNotLess { .. } => (1, 0),
+        Instruction::Return => (1, 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bytecode::Binding;
+
+    fn module(code: Vec<Instruction>) -> Module {
+        Module {
+            program: Program {
+                constants: vec![Value::from("some"), Value::Int(1.into())],
+                functions: vec![Function {
+                    name: "f".into(),
+                    arity: 1,
+                    locals: 1,
+                    code,
+                }],
+            },
+            bindings: vec![Binding {
+                name: "f".into(),
+                tags: Vec::new(),
+                item: Item::Function(0),
+            }],
+        }
+    }
+
+    fn reason(code: Vec<Instruction>) -> Option<&'static str> {
+        match verify(&module(code)) {
+            Ok(()) => None,
+            Err(BytecodeError::InvalidCode { reason, .. }) => Some(reason),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        use Instruction::*;
+
+        assert_eq!(
+            reason(vec![
+                Load(0),
+                JumpIfLess {
+                    constant: 1,
+                    target: 4
+                },
+                Constant(0),
+                Return,
+                NoMatch,
+            ]),
+            None
+        );
+
+        assert_eq!(reason(vec![Add, Return]), Some("the stack underflows"));
+        assert_eq!(
+            reason(vec![Unit, Unit, Return]),
+            Some("values are left on the stack when returning")
+        );
+        assert_eq!(
+            reason(vec![Unit]),
+            Some("execution runs past the end of the code")
+        );
+        assert_eq!(
+            reason(vec![Load(1), Return]),
+            Some("the local slot is out of bounds")
+        );
+        assert_eq!(
+            reason(vec![Constant(2), Return]),
+            Some("the constant does not exist")
+        );
+        assert_eq!(
+            reason(vec![Jump(7)]),
+            Some("the jump target is out of bounds")
+        );
+        assert_eq!(
+            reason(vec![
+                Load(0),
+                JumpIfLess {
+                    constant: 0,
+                    target: 0
+                }
+            ]),
+            Some("the constant is not an integer or char")
+        );
+        assert_eq!(
+            reason(vec![
+                Call {
+                    function: 0,
+                    argc: 2
+                },
+                Return
+            ]),
+            Some("the wrong number of arguments are passed")
+        );
+        assert_eq!(
+            reason(vec![
+                Load(0),
+                Load(0),
+                MakeClosure {
+                    function: 0,
+                    captures: 2
+                },
+                Return
+            ]),
+            Some("more values are captured than are taken")
+        );
+
+        // the loop pushes another value on every iteration
+        assert_eq!(
+            reason(vec![Unit, Jump(0)]),
+            Some("the stack depth differs between paths")
+        );
+
+        let mut dangling = module(vec![Unit, Return]);
+        dangling.bindings.push(Binding {
+            name: "<+>".into(),
+            tags: Vec::new(),
+            item: Item::Operator {
+                function: "g".into(),
+            },
+        });
+        assert_eq!(
+            verify(&dangling),
+            Err(BytecodeError::InvalidBinding {
+                name: "<+>".into(),
+                reason: "the operator's function does not exist"
+            })
+        );
+
+        let mut renamed = module(vec![Unit, Return]);
+        renamed.bindings[0].name = "g".into();
+        assert_eq!(
+            verify(&renamed),
+            Err(BytecodeError::InvalidBinding {
+                name: "g".into(),
+                reason: "the function has a different name"
+            })
+        );
+    }
+
+    #[test]
+    fn test_unchecked_values() {
+        use crate::{bytecode::Vm, FractalError};
+        use Instruction::*;
+
+        // well-formed code may still apply instructions to values they can't operate on, which
+        // the virtual machine reports rather than crashing on
+        for code in [
+            vec![Constant(1), Constant(1), CallValue(1), Return],
+            vec![Constant(1), Field(0), Return],
+            vec![Constant(0), Neg, Return],
+            vec![Constant(1), Constant(0), Add, Return],
+            vec![
+                Constant(0),
+                JumpIfLess {
+                    constant: 1,
+                    target: 2,
+                },
+                Unit,
+                Return,
+            ],
+        ] {
+            let module = module(code);
+            assert_eq!(verify(&module), Ok(()));
+            assert!(matches!(
+                Vm::new(&module.program).call("f", vec![Value::Unit]),
+                Err(FractalError::InvalidValues(_))
+            ));
+        }
+    }
+}
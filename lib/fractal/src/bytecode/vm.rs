@@ -0,0 +1,256 @@
+// a stack-based virtual machine executing compiled programs
+
+use super::{Builtin, Instruction, Program};
+use crate::{builtins, operators, FractalError, Value};
+use parser::ast::{OpSymbol, UnaryOpSymbol};
+use std::collections::HashMap;
+
+/// a virtual machine executing the functions of a program
+pub struct Vm<'p> {
+    program: &'p Program,
+    functions: HashMap<&'p str, usize>,
+}
+
+impl<'p> Vm<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            functions: program
+                .functions
+                .iter()
+                .enumerate()
+                .map(|(i, function)| (function.name.as_str(), i))
+                .collect(),
+        }
+    }
+
+    /// calls a function by its name
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, FractalError> {
+        match self.functions.get(name) {
+            Some(index) => self.run(*index, args),
+            None => Err(FractalError::UnboundName(String::from(name))),
+        }
+    }
+
+    /// calls a function reference. as references are only made to functions and constructors,
+    /// any name that isn't a function is a constructor
+    fn call_value(&self, callee: &Value, args: Vec<Value>) -> Result<Value, FractalError> {
+        match callee {
+            Value::Function(name) => match self.functions.get(name.as_str()) {
+                Some(index) => self.run(*index, args),
+                None => Ok(Value::Variant {
+                    name: name.clone(),
+                    fields: args,
+                }),
+            },
+            Value::Partial {
+                function,
+                args: captured,
+            } => match self.functions.get(function.as_str()) {
+                Some(index) => {
+                    let mut all = captured.clone();
+                    all.extend(args);
+                    self.run(*index, all)
+                }
+                None => Err(FractalError::UnboundName(function.clone())),
+            },
+            value => Err(FractalError::InvalidValues(format!(
+                "called a non-function: {:?}",
+                value
+            ))),
+        }
+    }
+
+    fn run(&self, index: usize, args: Vec<Value>) -> Result<Value, FractalError> {
+        let function = &self.program.functions[index];
+        let mut locals = args;
+        locals.resize(function.locals, Value::Unit);
+        let mut stack: Vec<Value> = Vec::new();
+
+        let mut pc = 0;
+        loop {
+            let instruction = function.code[pc];
+            pc += 1;
+
+            match instruction {
+                Instruction::Constant(constant) => {
+                    stack.push(self.program.constants[constant as usize].clone())
+                }
+                Instruction::Unit => stack.push(Value::Unit),
+                Instruction::Load(slot) => stack.push(locals[slot as usize].clone()),
+                Instruction::Store(slot) => locals[slot as usize] = pop(&mut stack),
+                Instruction::Pop => {
+                    pop(&mut stack);
+                }
+                Instruction::Add
+                | Instruction::Sub
+                | Instruction::Mul
+                | Instruction::Div
+                | Instruction::Rem
+                | Instruction::Lt
+                | Instruction::Gt
+                | Instruction::Le
+                | Instruction::Ge
+                | Instruction::BitAnd
+                | Instruction::BitOr
+                | Instruction::BitXor
+                | Instruction::Shl
+                | Instruction::Shr
+                | Instruction::Eq
+                | Instruction::Ne => {
+                    let r = pop(&mut stack);
+                    let l = pop(&mut stack);
+                    stack.push(operators::eval_binary(&operator(instruction), l, r)?);
+                }
+                Instruction::Neg => {
+                    let arg = pop(&mut stack);
+                    stack.push(operators::eval_unary(&UnaryOpSymbol::Minus, arg)?);
+                }
+                Instruction::Not => {
+                    let arg = pop(&mut stack);
+                    stack.push(operators::eval_unary(&UnaryOpSymbol::Bang, arg)?);
+                }
+                Instruction::List(length) => {
+                    let items = pop_n(&mut stack, length as usize);
+                    stack.push(Value::List(items));
+                }
+                Instruction::Call { function, argc } => {
+                    let args = pop_n(&mut stack, argc as usize);
+                    stack.push(self.run(function as usize, args)?);
+                }
+                Instruction::CallValue(argc) => {
+                    let args = pop_n(&mut stack, argc as usize);
+                    let callee = pop(&mut stack);
+                    stack.push(self.call_value(&callee, args)?);
+                }
+                Instruction::MakeClosure { function, captures } => {
+                    let args = pop_n(&mut stack, captures as usize);
+                    stack.push(Value::Partial {
+                        function: self.program.functions[function as usize].name.clone(),
+                        args,
+                    });
+                }
+                Instruction::Construct { name, argc } => {
+                    let fields = pop_n(&mut stack, argc as usize);
+                    stack.push(Value::Variant {
+                        name: self.constant_name(name)?,
+                        fields,
+                    });
+                }
+                Instruction::Builtin { builtin, argc } => {
+                    let args = pop_n(&mut stack, argc as usize);
+                    stack.push(match builtin {
+                        Builtin::Println => {
+                            match args.first() {
+                                Some(value) => println!("{}", value),
+                                None => println!(),
+                            }
+                            Value::Unit
+                        }
+                        _ => builtins::eval_list_builtin(builtin.name(), args, |f, args| {
+                            self.call_value(f, args)
+                        })?,
+                    });
+                }
+                Instruction::Field(field) => match pop(&mut stack) {
+                    Value::Variant { mut fields, .. } if (field as usize) < fields.len() => {
+                        stack.push(fields.swap_remove(field as usize))
+                    }
+                    value => {
+                        return Err(FractalError::InvalidValues(format!(
+                            "accessed field {} of {:?}",
+                            field, value
+                        )))
+                    }
+                },
+                Instruction::Jump(target) => pc = target as usize,
+                Instruction::JumpIfNotEqual { constant, target } => {
+                    if pop(&mut stack) != self.program.constants[constant as usize] {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::JumpIfNotVariant { name, target } => {
+                    let matches = match pop(&mut stack) {
+                        Value::Variant { name: ctor, .. } => {
+                            self.program.constants[name as usize] == Value::String(ctor)
+                        }
+                        _ => false,
+                    };
+                    if !matches {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::JumpIfLess { constant, target } => {
+                    if less(&pop(&mut stack), &self.program.constants[constant as usize])? {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::JumpIfNotLess { constant, target } => {
+                    if !less(&pop(&mut stack), &self.program.constants[constant as usize])? {
+                        pc = target as usize;
+                    }
+                }
+                Instruction::NoMatch => return Err(FractalError::NoMatchingPattern),
+                Instruction::Return => return Ok(pop(&mut stack)),
+            }
+        }
+    }
+
+    fn constant_name(&self, constant: u32) -> Result<String, FractalError> {
+        match &self.program.constants[constant as usize] {
+            Value::String(name) => Ok(name.clone()),
+            value => Err(FractalError::InvalidValues(format!(
+                "expected a name constant, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
+// TODO(superwhiskers): remove expect
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("stack underflow")
+}
+
+/// the operator applied by an instruction taking two operands
+fn operator(instruction: Instruction) -> OpSymbol {
+    match instruction {
+        Instruction::Add => OpSymbol::Plus,
+        Instruction::Sub => OpSymbol::Minus,
+        Instruction::Mul => OpSymbol::Star,
+        Instruction::Div => OpSymbol::ForwardSlash,
+        Instruction::Rem => OpSymbol::Percent,
+        Instruction::Lt => OpSymbol::LAngleBracket,
+        Instruction::Gt => OpSymbol::RAngleBracket,
+        Instruction::Le => OpSymbol::LAngleBracketEqual,
+        Instruction::Ge => OpSymbol::RAngleBracketEqual,
+        Instruction::BitAnd => OpSymbol::Ampersand,
+        Instruction::BitOr => OpSymbol::Pipe,
+        Instruction::BitXor => OpSymbol::Xor,
+        Instruction::Shl => OpSymbol::DoubleLAngleBracket,
+        Instruction::Shr => OpSymbol::DoubleRAngleBracket,
+        Instruction::Eq => OpSymbol::DoubleEqual,
+        Instruction::Ne => OpSymbol::BangEqual,
+        // TODO(superwhiskers): remove panic
+        _ => panic!("{:?} is not an operator", instruction),
+    }
+}
+
+/// compares two integers or two chars, as range patterns do
+fn less(l: &Value, r: &Value) -> Result<bool, FractalError> {
+    match (l, r) {
+        (Value::Int(l), Value::Int(r)) => Ok(l < r),
+        (Value::Char(l), Value::Char(r)) => Ok(l < r),
+        _ => Err(FractalError::InvalidValues(format!(
+            "compared {:?} with {:?}",
+            l, r
+        ))),
+    }
+}
+
+/// pops the given number of values, keeping them in the order they were pushed in
+fn pop_n(stack: &mut Vec<Value>, n: usize) -> Vec<Value> {
+    // TODO(superwhiskers): remove expect
+    let at = stack.len().checked_sub(n).expect("stack underflow");
+    stack.split_off(at)
+}
@@ -0,0 +1,300 @@
+// exhaustiveness checking for `match`, loosely following the usefulness algorithm from
+// "warnings for pattern matching" (maranget, 2007)
+
+use crate::FractalError;
+use parser::ast::Pattern;
+use std::{collections::HashSet, iter};
+
+/// a pattern with its identifiers resolved to either constructors or bindings
+#[derive(Debug, Clone)]
+enum Pat {
+    Wildcard,
+    Constructor(String, Vec<Pat>),
+
+    // integer, string, char and range patterns. these never cover their type on their own
+    Literal,
+}
+
+/// returns the cases not covered by the provided match arms as patterns, or an empty vector if
+/// the arms are exhaustive
+///
+/// `siblings` maps a constructor name to every constructor of its type along with their arities
+pub fn missing_patterns<F>(arms: &[&Pattern], siblings: F) -> Result<Vec<Pattern>, FractalError>
+where
+    F: Fn(&str) -> Option<Vec<(String, usize)>>,
+{
+    let mut rows = Vec::with_capacity(arms.len());
+    for arm in arms {
+        rows.push(vec![lower(arm, &siblings)?]);
+    }
+
+    Ok(missing(&rows, 1, &siblings)
+        .into_iter()
+        .map(|mut witness| raise(witness.remove(0)))
+        .collect())
+}
+
+/// resolves the identifiers in a pattern and validates the arity of its constructors
+fn lower<F>(pat: &Pattern, siblings: &F) -> Result<Pat, FractalError>
+where
+    F: Fn(&str) -> Option<Vec<(String, usize)>>,
+{
+    Ok(match pat {
+        Pattern::Wildcard => Pat::Wildcard,
+        Pattern::Identifier(name) => match arity(name, siblings) {
+            Some(0) => Pat::Constructor(name.clone(), vec![]),
+            Some(expected) => {
+                return Err(FractalError::ConstructorArity {
+                    name: name.clone(),
+                    expected,
+                    found: 0,
+                })
+            }
+            None => Pat::Wildcard,
+        },
+        Pattern::Constructor { name, args } => match arity(name, siblings) {
+            Some(expected) if expected == args.len() => {
+                let mut lowered = Vec::with_capacity(args.len());
+                for arg in args {
+                    lowered.push(lower(arg, siblings)?);
+                }
+                Pat::Constructor(name.clone(), lowered)
+            }
+            Some(expected) => {
+                return Err(FractalError::ConstructorArity {
+                    name: name.clone(),
+                    expected,
+                    found: args.len(),
+                })
+            }
+            None => return Err(FractalError::UnknownConstructor(name.clone())),
+        },
+        Pattern::Integer(_)
+        | Pattern::StringLiteral(_)
+        | Pattern::Char(_)
+        | Pattern::Range { .. } => Pat::Literal,
+    })
+}
+
+/// converts a witness back into an ast pattern so that it can be reported
+fn raise(pat: Pat) -> Pattern {
+    match pat {
+        Pat::Wildcard | Pat::Literal => Pattern::Wildcard,
+        Pat::Constructor(name, args) => Pattern::Constructor {
+            name,
+            args: args.into_iter().map(raise).collect(),
+        },
+    }
+}
+
+fn arity<F>(name: &str, siblings: &F) -> Option<usize>
+where
+    F: Fn(&str) -> Option<Vec<(String, usize)>>,
+{
+    siblings(name)?
+        .into_iter()
+        .find(|(sibling, _)| sibling == name)
+        .map(|(_, arity)| arity)
+}
+
+/// computes every vector of `width` values not matched by any of the rows
+fn missing<F>(rows: &[Vec<Pat>], width: usize, siblings: &F) -> Vec<Vec<Pat>>
+where
+    F: Fn(&str) -> Option<Vec<(String, usize)>>,
+{
+    if width == 0 {
+        return if rows.is_empty() {
+            vec![vec![]]
+        } else {
+            vec![]
+        };
+    }
+
+    // the first column's type is only known if a constructor appears in it
+    let signature = rows.iter().find_map(|row| match &row[0] {
+        Pat::Constructor(name, _) => siblings(name),
+        _ => None,
+    });
+
+    let signature = match signature {
+        Some(signature) => signature,
+        None => {
+            return missing(&default(rows), width - 1, siblings)
+                .into_iter()
+                .map(|witness| iter::once(Pat::Wildcard).chain(witness).collect())
+                .collect()
+        }
+    };
+
+    let present: HashSet<&str> = rows
+        .iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Constructor(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut witnesses = Vec::new();
+    if signature
+        .iter()
+        .all(|(name, _)| present.contains(name.as_str()))
+    {
+        // every constructor is covered at the top level, so the missing cases (if any) are
+        // inside of their fields or in the following columns
+        for (name, arity) in &signature {
+            for mut witness in missing(&specialize(rows, name, *arity), arity + width - 1, siblings)
+            {
+                let rest = witness.split_off(*arity);
+                witnesses.push(
+                    iter::once(Pat::Constructor(name.clone(), witness))
+                        .chain(rest)
+                        .collect(),
+                );
+            }
+        }
+    } else {
+        for witness in missing(&default(rows), width - 1, siblings) {
+            for (name, arity) in signature
+                .iter()
+                .filter(|(name, _)| !present.contains(name.as_str()))
+            {
+                witnesses.push(
+                    iter::once(Pat::Constructor(name.clone(), vec![Pat::Wildcard; *arity]))
+                        .chain(witness.iter().cloned())
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    witnesses
+}
+
+/// keeps the rows that can match the given constructor, replacing their first column with the
+/// constructor's fields
+fn specialize(rows: &[Vec<Pat>], name: &str, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let fields = match &row[0] {
+                Pat::Constructor(other, fields) if other == name => fields.clone(),
+                Pat::Wildcard => vec![Pat::Wildcard; arity],
+                _ => return None,
+            };
+            Some(fields.into_iter().chain(row[1..].iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// keeps the rows that match anything in their first column, dropping that column
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter(|row| matches!(row[0], Pat::Wildcard))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn siblings(name: &str) -> Option<Vec<(String, usize)>> {
+        let shape = vec![
+            (String::from("circle"), 1),
+            (String::from("rect"), 2),
+            (String::from("empty"), 0),
+        ];
+        let option = vec![(String::from("some"), 1), (String::from("none"), 0)];
+        match name {
+            "circle" | "rect" | "empty" => Some(shape),
+            "some" | "none" => Some(option),
+            _ => None,
+        }
+    }
+
+    fn ctor(name: &str, args: Vec<Pattern>) -> Pattern {
+        Pattern::Constructor {
+            name: name.into(),
+            args,
+        }
+    }
+
+    fn ident(name: &str) -> Pattern {
+        Pattern::Identifier(name.into())
+    }
+
+    fn check(arms: Vec<Pattern>) -> Vec<String> {
+        let arms: Vec<&Pattern> = arms.iter().collect();
+        missing_patterns(&arms, siblings)
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_exhaustive() {
+        assert!(check(vec![
+            ctor("circle", vec![ident("r")]),
+            ctor("rect", vec![Pattern::Wildcard, Pattern::Wildcard]),
+            ident("empty"),
+        ])
+        .is_empty());
+        assert!(check(vec![ctor("circle", vec![ident("r")]), ident("other")]).is_empty());
+        assert!(check(vec![Pattern::Integer(0.into()), Pattern::Wildcard]).is_empty());
+    }
+
+    #[test]
+    fn test_missing_constructors() {
+        assert_eq!(
+            check(vec![ctor("circle", vec![ident("r")])]),
+            vec!["rect _ _", "empty"]
+        );
+        assert_eq!(check(vec![ident("none")]), vec!["some _"]);
+        assert_eq!(check(vec![Pattern::Integer(0.into())]), vec!["_"]);
+    }
+
+    #[test]
+    fn test_missing_nested() {
+        assert_eq!(
+            check(vec![
+                ident("none"),
+                ctor("some", vec![ctor("circle", vec![Pattern::Wildcard])]),
+                ctor("some", vec![ident("empty")]),
+            ]),
+            vec!["some (rect _ _)"]
+        );
+        assert_eq!(
+            check(vec![
+                ident("none"),
+                ctor(
+                    "some",
+                    vec![ctor("rect", vec![Pattern::Integer(0.into()), ident("h")])]
+                ),
+                ctor("some", vec![ctor("circle", vec![Pattern::Wildcard])]),
+                ctor("some", vec![ident("empty")]),
+            ]),
+            vec!["some (rect _ _)"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_constructors() {
+        let arms = [ctor("square", vec![Pattern::Wildcard])];
+        let arms: Vec<&Pattern> = arms.iter().collect();
+        assert!(matches!(
+            missing_patterns(&arms, siblings),
+            Err(FractalError::UnknownConstructor(name)) if name == "square"
+        ));
+
+        let arms = [ctor("circle", vec![])];
+        let arms: Vec<&Pattern> = arms.iter().collect();
+        assert!(matches!(
+            missing_patterns(&arms, siblings),
+            Err(FractalError::ConstructorArity {
+                expected: 1,
+                found: 0,
+                ..
+            })
+        ));
+    }
+}
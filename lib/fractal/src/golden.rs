@@ -0,0 +1,33 @@
+// helpers shared by the golden tests of fractal and its backends, which keep the expected output
+// for each program in a directory in a file alongside it
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// the programs in a directory, in order of their names
+pub fn programs(dir: &Path) -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sf"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// checks output against the contents of the file expected to hold it. setting `BLESS` updates
+/// the file instead
+pub fn check(expected: &Path, output: &str) {
+    if env::var_os("BLESS").is_some() {
+        fs::write(expected, output).unwrap();
+        return;
+    }
+    assert_eq!(
+        output,
+        fs::read_to_string(expected).unwrap(),
+        "{}",
+        expected.display()
+    );
+}
@@ -0,0 +1,151 @@
+// the textual format of the intermediate representation, used for debugging and in tests
+
+use super::{
+    Block, BlockId, Constant, Function, Global, Instruction, Operand, Program, Rvalue, Terminator,
+    Var,
+};
+use std::fmt;
+use tag::TagName;
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|tag| match tag {
+                TagName::Primary(name) => format!("*{}", name),
+                TagName::Secondary(name) => name.to_string(),
+            })
+            .collect();
+        writeln!(
+            f,
+            "fn {}({}) [{}] {{",
+            self.name,
+            list(&self.params),
+            tags.join(", ")
+        )?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            write_block(f, BlockId(i as u32), block)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, id: BlockId, block: &Block) -> fmt::Result {
+    if block.params.is_empty() {
+        writeln!(f, "{}:", id)?;
+    } else {
+        writeln!(f, "{}({}):", id, list(&block.params))?;
+    }
+    for instruction in &block.instructions {
+        writeln!(f, "  {}", instruction)?;
+    }
+    writeln!(f, "  {}", block.terminator)
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.name)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(int) => write!(f, "{}", int),
+            Constant::String(string) => write!(f, "{:?}", string),
+            Constant::Char(c) => write!(f, "{:?}", c),
+            Constant::Bool(bool) => write!(f, "{}", bool),
+            Constant::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Var(var) => write!(f, "{}", var),
+            Operand::Constant(constant) => write!(f, "{}", constant),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.var, self.value)
+    }
+}
+
+impl fmt::Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Binary { op, l, r } => write!(f, "{} {}, {}", op.name(), l, r),
+            Rvalue::Unary { op, arg } => write!(f, "{} {}", op.name(), arg),
+            Rvalue::Call { callee, args } => write!(f, "call {}({})", callee, list(args)),
+            Rvalue::CallIndirect { callee, args } => {
+                write!(f, "call {}({})", callee, list(args))
+            }
+            Rvalue::Builtin { builtin, args } => {
+                write!(f, "builtin {}({})", builtin.name(), list(args))
+            }
+            Rvalue::Construct { constructor, args } => {
+                write!(f, "construct {}({})", constructor, list(args))
+            }
+            Rvalue::FunctionRef(global) => write!(f, "ref {}", global),
+            Rvalue::List(items) => write!(f, "list [{}]", list(items)),
+            Rvalue::IsVariant { value, constructor } => {
+                write!(f, "is-variant {}, {}", value, constructor)
+            }
+            Rvalue::Field { value, index } => write!(f, "field {}, {}", value, index),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Return(value) => write!(f, "return {}", value),
+            Terminator::Jump { target, args } if args.is_empty() => write!(f, "jump {}", target),
+            Terminator::Jump { target, args } => write!(f, "jump {}({})", target, list(args)),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => write!(f, "branch {}, {}, {}", condition, then, otherwise),
+            Terminator::NoMatch => write!(f, "no-match"),
+        }
+    }
+}
+
+/// joins displayable items with commas
+fn list<T: fmt::Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
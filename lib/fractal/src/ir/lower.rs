@@ -0,0 +1,582 @@
+// lowering of the functions of a universe from their syntax trees
+
+use super::{
+    BinaryOp, Block, BlockId, Constant, Function, Global, Instruction, Operand, Program, Rvalue,
+    Terminator, UnaryOp, Var,
+};
+use crate::{bytecode::Builtin, unquote, FractalError, UniverseItem};
+use parser::ast::{Expression, OpSymbol, Pattern, UnaryOpSymbol};
+use std::collections::HashMap;
+use tag::TagName;
+
+/// the operands holding the values of the local bindings in scope
+type Scope = HashMap<String, Operand>;
+
+/// lowers every function in a universe, given its bindings in the order of its entries
+pub fn lower(bindings: &[(&str, &[TagName], &UniverseItem)]) -> Result<Program, FractalError> {
+    let mut functions = Vec::new();
+    for (index, (name, tags, item)) in bindings.iter().enumerate() {
+        if let UniverseItem::FnDecl { args, body, .. } = item {
+            let lowering = Lowering {
+                bindings,
+                blocks: Vec::new(),
+                current: BlockId(0),
+                next_var: 0,
+            };
+            functions.push(lowering.function(name, index, tags, args, body)?);
+        }
+    }
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Program { functions })
+}
+
+/// a block that may not have been terminated yet
+struct PartialBlock {
+    params: Vec<Var>,
+    instructions: Vec<Instruction>,
+    terminator: Option<Terminator>,
+}
+
+struct Lowering<'l> {
+    bindings: &'l [(&'l str, &'l [TagName<'l>], &'l UniverseItem)],
+    blocks: Vec<PartialBlock>,
+
+    /// the block instructions are added to
+    current: BlockId,
+    next_var: u32,
+}
+
+impl<'l> Lowering<'l> {
+    fn function(
+        mut self,
+        name: &str,
+        binding: usize,
+        tags: &[TagName],
+        args: &[String],
+        body: &[Box<Expression>],
+    ) -> Result<Function, FractalError> {
+        self.current = self.block();
+
+        let mut scope = Scope::new();
+        let mut params = Vec::with_capacity(args.len());
+        for arg in args {
+            let var = self.var();
+            params.push(var);
+            scope.insert(arg.clone(), var.into());
+        }
+
+        let value = self.body(&mut scope, body)?;
+        self.terminate(Terminator::Return(value));
+
+        let blocks = self
+            .blocks
+            .into_iter()
+            .map(|block| Block {
+                params: block.params,
+                instructions: block.instructions,
+                // TODO(superwhiskers): remove expect
+                terminator: block.terminator.expect("a block was left unterminated"),
+            })
+            .collect();
+        Ok(Function {
+            name: String::from(name),
+            binding,
+            tags: tags.iter().map(crate::bytecode::owned_tag_name).collect(),
+            params,
+            blocks,
+        })
+    }
+
+    /// lowers a block of expressions, returning the value of the last one
+    fn body(
+        &mut self,
+        scope: &mut Scope,
+        body: &[Box<Expression>],
+    ) -> Result<Operand, FractalError> {
+        let mut value = Constant::Unit.into();
+        for expr in body {
+            value = self.expression(scope, expr)?;
+        }
+        Ok(value)
+    }
+
+    fn expression(
+        &mut self,
+        scope: &mut Scope,
+        expr: &Expression,
+    ) -> Result<Operand, FractalError> {
+        Ok(match expr {
+            Expression::Integer(int) => Constant::Int(int.clone()).into(),
+            Expression::StringLiteral(string) => {
+                Constant::String(String::from(unquote(string))).into()
+            }
+            Expression::Char(c) => Constant::Char(*c).into(),
+            // functions and constructors that take arguments evaluate to a reference to themselves
+            Expression::Identifier(name) => match scope.get(name) {
+                Some(value) => value.clone(),
+                None => {
+                    let (global, item) = self.resolve(name)?;
+                    match item {
+                        UniverseItem::Constructor { fields, .. } if fields.is_empty() => {
+                            self.emit(Rvalue::Construct {
+                                constructor: global,
+                                args: vec![],
+                            })
+                        }
+                        UniverseItem::FnDecl { args, .. } if args.is_empty() => {
+                            self.emit(Rvalue::Call {
+                                callee: global,
+                                args: vec![],
+                            })
+                        }
+                        UniverseItem::Constructor { .. } | UniverseItem::FnDecl { .. } => {
+                            self.emit(Rvalue::FunctionRef(global))
+                        }
+                        _ => return Err(FractalError::UnboundName(name.clone())),
+                    }
+                }
+            },
+            Expression::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.expression(scope, item)?);
+                }
+                self.emit(Rvalue::List(values))
+            }
+            // user-defined operators are calls to the function they're bound to
+            Expression::OpCall {
+                op: op @ OpSymbol::Custom(_),
+                args,
+            } => match self.resolve(&op.to_string())? {
+                (_, UniverseItem::Operator { function }) => {
+                    let args: Vec<Expression> =
+                        args.iter().map(|arg| arg.as_ref().clone()).collect();
+                    self.call(scope, function, &args)?
+                }
+                _ => return Err(FractalError::UnboundName(op.to_string())),
+            },
+            Expression::OpCall {
+                op: op @ OpSymbol::DoubleAmpersand,
+                args,
+            }
+            | Expression::OpCall {
+                op: op @ OpSymbol::DoublePipe,
+                args,
+            } => match args.as_slice() {
+                [l, r] => self.short_circuit(scope, *op == OpSymbol::DoubleAmpersand, l, r)?,
+                // TODO(superwhiskers): remove panic
+                _ => panic!("operator `{}` applied to {} operands", op, args.len()),
+            },
+            Expression::OpCall { op, args } => match args.as_slice() {
+                [l, r] => {
+                    let l = self.expression(scope, l)?;
+                    let r = self.expression(scope, r)?;
+                    let op = match op {
+                        OpSymbol::Plus => BinaryOp::Add,
+                        OpSymbol::Minus => BinaryOp::Sub,
+                        OpSymbol::Star => BinaryOp::Mul,
+                        OpSymbol::ForwardSlash => BinaryOp::Div,
+                        OpSymbol::Percent => BinaryOp::Rem,
+                        OpSymbol::LAngleBracket => BinaryOp::Lt,
+                        OpSymbol::RAngleBracket => BinaryOp::Gt,
+                        OpSymbol::LAngleBracketEqual => BinaryOp::Le,
+                        OpSymbol::RAngleBracketEqual => BinaryOp::Ge,
+                        OpSymbol::DoubleEqual => BinaryOp::Eq,
+                        OpSymbol::BangEqual => BinaryOp::Ne,
+                        OpSymbol::Ampersand => BinaryOp::BitAnd,
+                        OpSymbol::Pipe => BinaryOp::BitOr,
+                        OpSymbol::Xor => BinaryOp::BitXor,
+                        OpSymbol::DoubleLAngleBracket => BinaryOp::Shl,
+                        OpSymbol::DoubleRAngleBracket => BinaryOp::Shr,
+                        _ => {
+                            return Err(FractalError::Unsupported(format!(
+                                "the `{}` operator at runtime",
+                                op
+                            )))
+                        }
+                    };
+                    self.emit(Rvalue::Binary { op, l, r })
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("operator `{}` applied to {} operands", op, args.len()),
+            },
+            Expression::Section { .. } => {
+                return Err(FractalError::Unsupported(String::from(
+                    "operator sections at runtime",
+                )))
+            }
+            Expression::Lambda { .. } => {
+                return Err(FractalError::Unsupported(String::from(
+                    "closures at runtime",
+                )))
+            }
+            Expression::Float(_) => {
+                return Err(FractalError::Unsupported(String::from("floats at runtime")))
+            }
+            Expression::UnaryOpCall { op, arg } => {
+                let arg = self.expression(scope, arg)?;
+                let op = match op {
+                    UnaryOpSymbol::Minus => UnaryOp::Neg,
+                    UnaryOpSymbol::Bang => UnaryOp::Not,
+                };
+                self.emit(Rvalue::Unary { op, arg })
+            }
+            Expression::FnCall { name, args } => self.call(scope, name, args)?,
+            Expression::Apply { callee, args } => {
+                let callee = self.expression(scope, callee)?;
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.expression(scope, arg)?);
+                }
+                self.emit(Rvalue::CallIndirect {
+                    callee,
+                    args: values,
+                })
+            }
+            Expression::Match { expr, args } => self.match_expression(scope, expr, args)?,
+            Expression::ValueDecl { assigns, body } => match body {
+                Some(body) => {
+                    let mut scope = scope.clone();
+                    for assign in assigns {
+                        self.expression(&mut scope, assign)?;
+                    }
+                    self.body(&mut scope, body)?
+                }
+                // without a body, the bindings are added to the enclosing block
+                None => {
+                    for assign in assigns {
+                        self.expression(scope, assign)?;
+                    }
+                    Constant::Unit.into()
+                }
+            },
+            Expression::ValueAssign { pat, expr } => {
+                let value = self.expression(scope, expr)?;
+                match pat {
+                    Pattern::Wildcard => (),
+                    Pattern::Identifier(name) if !self.is_constructor(name) => {
+                        scope.insert(name.clone(), value);
+                    }
+                    // patterns that may not match fail at runtime instead
+                    _ => {
+                        let fail = self.block();
+                        self.within(fail, |lowering| lowering.terminate(Terminator::NoMatch));
+                        self.pattern(scope, pat, &value, fail)?;
+                    }
+                }
+                Constant::Unit.into()
+            }
+            Expression::TypeDecl { expr, .. } => self.expression(scope, expr)?,
+            Expression::TagAssign { .. } => {
+                return Err(FractalError::Unsupported(String::from(
+                    "tag assignments in function bodies",
+                )))
+            }
+            // TODO(superwhiskers): remove panic
+            Expression::Destructure { .. } => panic!("destructure outside of a match expression"),
+        })
+    }
+
+    fn call(
+        &mut self,
+        scope: &mut Scope,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Operand, FractalError> {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.expression(scope, arg)?);
+        }
+
+        // builtins take precedence, as they do in the interpreter
+        if let Some(builtin) = Builtin::from_name(name) {
+            return Ok(self.emit(Rvalue::Builtin {
+                builtin,
+                args: values,
+            }));
+        }
+        if let Some(callee) = scope.get(name) {
+            return Ok(self.emit(Rvalue::CallIndirect {
+                callee: callee.clone(),
+                args: values,
+            }));
+        }
+
+        let (global, item) = self.resolve(name)?;
+        Ok(match item {
+            UniverseItem::Constructor { .. } => self.emit(Rvalue::Construct {
+                constructor: global,
+                args: values,
+            }),
+            UniverseItem::FnDecl { .. } => self.emit(Rvalue::Call {
+                callee: global,
+                args: values,
+            }),
+            _ => return Err(FractalError::NotAFunction(String::from(name))),
+        })
+    }
+
+    /// lowers a match expression, testing each arm in turn and passing the value of the one that
+    /// matched to the block after them
+    fn match_expression(
+        &mut self,
+        scope: &mut Scope,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Result<Operand, FractalError> {
+        let scrutinee = self.expression(scope, expr)?;
+        let end = self.block();
+        let value = self.var();
+        self.blocks[end.0 as usize].params.push(value);
+
+        for arm in arms {
+            match arm {
+                Expression::Destructure { pat, body } => {
+                    // a failed test moves on to the next arm
+                    let next = self.block();
+
+                    // arms bind into their own scope, which is discarded afterwards
+                    let mut arm_scope = scope.clone();
+                    self.pattern(&mut arm_scope, pat, &scrutinee, next)?;
+                    let arm_value = self.body(&mut arm_scope, body)?;
+                    self.terminate(Terminator::Jump {
+                        target: end,
+                        args: vec![arm_value],
+                    });
+
+                    self.current = next;
+                }
+                // TODO(superwhiskers): remove panic
+                _ => panic!("unexpected match arm: {:?}", arm),
+            }
+        }
+        self.terminate(Terminator::NoMatch);
+
+        self.current = end;
+        Ok(value.into())
+    }
+
+    /// lowers `&&` and `||`, which only evaluate their right side when the left side doesn't
+    /// decide the result on its own
+    fn short_circuit(
+        &mut self,
+        scope: &mut Scope,
+        and: bool,
+        l: &Expression,
+        r: &Expression,
+    ) -> Result<Operand, FractalError> {
+        let condition = self.expression(scope, l)?;
+        let end = self.block();
+        let value = self.var();
+        self.blocks[end.0 as usize].params.push(value);
+
+        // branches can't pass arguments, so the decided result is passed on by a block of its own
+        let decided = self.block();
+        self.within(decided, |lowering| {
+            lowering.terminate(Terminator::Jump {
+                target: end,
+                args: vec![Constant::Bool(!and).into()],
+            })
+        });
+
+        let right = self.block();
+        let (then, otherwise) = if and {
+            (right, decided)
+        } else {
+            (decided, right)
+        };
+        self.terminate(Terminator::Branch {
+            condition,
+            then,
+            otherwise,
+        });
+
+        self.current = right;
+        let r = self.expression(scope, r)?;
+        self.terminate(Terminator::Jump {
+            target: end,
+            args: vec![r],
+        });
+
+        self.current = end;
+        Ok(value.into())
+    }
+
+    /// lowers the tests of a pattern against a value, binding any identifiers in it. failed tests
+    /// move on to the `fail` block
+    fn pattern(
+        &mut self,
+        scope: &mut Scope,
+        pat: &Pattern,
+        value: &Operand,
+        fail: BlockId,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) if self.is_constructor(name) => {
+                self.pattern(
+                    scope,
+                    &Pattern::Constructor {
+                        name: name.clone(),
+                        args: vec![],
+                    },
+                    value,
+                    fail,
+                )?;
+            }
+            Pattern::Identifier(name) => {
+                scope.insert(name.clone(), value.clone());
+            }
+            Pattern::Integer(int) => {
+                let condition = self.emit(Rvalue::Binary {
+                    op: BinaryOp::Eq,
+                    l: value.clone(),
+                    r: Constant::Int(int.clone()).into(),
+                });
+                self.test(condition, fail);
+            }
+            Pattern::StringLiteral(string) => {
+                let condition = self.emit(Rvalue::Binary {
+                    op: BinaryOp::Eq,
+                    l: value.clone(),
+                    r: Constant::String(String::from(unquote(string))).into(),
+                });
+                self.test(condition, fail);
+            }
+            Pattern::Char(c) => {
+                let condition = self.emit(Rvalue::Binary {
+                    op: BinaryOp::Eq,
+                    l: value.clone(),
+                    r: Constant::Char(*c).into(),
+                });
+                self.test(condition, fail);
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let condition = self.emit(Rvalue::Binary {
+                        op: BinaryOp::Ge,
+                        l: value.clone(),
+                        r: range_bound(start)?,
+                    });
+                    self.test(condition, fail);
+                }
+                if let Some(end) = end {
+                    let condition = self.emit(Rvalue::Binary {
+                        op: BinaryOp::Lt,
+                        l: value.clone(),
+                        r: range_bound(end)?,
+                    });
+                    self.test(condition, fail);
+                }
+            }
+            Pattern::Constructor { name, args } => {
+                let (constructor, _) = self.resolve(name)?;
+                let condition = self.emit(Rvalue::IsVariant {
+                    value: value.clone(),
+                    constructor,
+                });
+                self.test(condition, fail);
+
+                for (index, arg) in args.iter().enumerate() {
+                    if let Pattern::Wildcard = arg {
+                        continue;
+                    }
+
+                    let field = self.emit(Rvalue::Field {
+                        value: value.clone(),
+                        index,
+                    });
+                    self.pattern(scope, arg, &field, fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// continues in a new block if a condition holds, moving on to `fail` otherwise
+    fn test(&mut self, condition: Operand, fail: BlockId) {
+        let pass = self.block();
+        self.terminate(Terminator::Branch {
+            condition,
+            then: pass,
+            otherwise: fail,
+        });
+        self.current = pass;
+    }
+
+    /// looks up a binding by its name, returning the first one in the universe with it
+    ///
+    /// TODO(superwhiskers): resolve names using the tags of the current binding, as the evaluator
+    ///                      should
+    fn resolve(&self, name: &str) -> Result<(Global, &'l UniverseItem), FractalError> {
+        self.bindings
+            .iter()
+            .position(|(binding, _, _)| *binding == name)
+            .map(|binding| {
+                (
+                    Global {
+                        name: String::from(name),
+                        binding,
+                    },
+                    self.bindings[binding].2,
+                )
+            })
+            .ok_or_else(|| FractalError::UnboundName(String::from(name)))
+    }
+
+    fn is_constructor(&self, name: &str) -> bool {
+        matches!(
+            self.resolve(name),
+            Ok((_, UniverseItem::Constructor { .. }))
+        )
+    }
+
+    /// adds an instruction to the current block, returning the variable holding its result
+    fn emit(&mut self, value: Rvalue) -> Operand {
+        let var = self.var();
+        self.blocks[self.current.0 as usize]
+            .instructions
+            .push(Instruction { var, value });
+        var.into()
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current.0 as usize].terminator = Some(terminator);
+    }
+
+    /// runs a function with another block as the current one
+    fn within<F: FnOnce(&mut Self)>(&mut self, block: BlockId, f: F) {
+        let current = self.current;
+        self.current = block;
+        f(self);
+        self.current = current;
+    }
+
+    fn block(&mut self) -> BlockId {
+        self.blocks.push(PartialBlock {
+            params: Vec::new(),
+            instructions: Vec::new(),
+            terminator: None,
+        });
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn var(&mut self) -> Var {
+        let var = Var(self.next_var);
+        self.next_var += 1;
+        var
+    }
+}
+
+fn range_bound(bound: &Pattern) -> Result<Operand, FractalError> {
+    match bound {
+        Pattern::Integer(int) => Ok(Constant::Int(int.clone()).into()),
+        Pattern::Char(c) => Ok(Constant::Char(*c).into()),
+        _ => Err(FractalError::Unsupported(format!(
+            "the range bound `{}`",
+            bound
+        ))),
+    }
+}
@@ -0,0 +1,333 @@
+// an intermediate representation between the syntax tree and the backends
+//
+// functions are lowered to blocks of instructions in administrative normal form: every operand is
+// either a constant or a variable, and every variable is assigned exactly once, either by an
+// instruction or as a parameter of a block. control flow is explicit, with each block ending in a
+// terminator that returns or moves on to other blocks, so `let` bindings and match expressions
+// are gone by the time a backend sees a function
+//
+// references to other bindings are resolved while lowering, and refer to the binding by its index
+// in the entries of the universe
+
+use crate::bytecode::Builtin;
+use num_bigint::BigInt;
+use tag::TagName;
+
+mod dump;
+mod lower;
+pub mod opt;
+
+pub use lower::lower;
+pub use opt::Passes;
+
+/// every function in a universe, in order of their names
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    /// the index of the function's binding in the universe
+    pub binding: usize,
+    pub tags: Vec<TagName<'static>>,
+    pub params: Vec<Var>,
+    /// the blocks of the function, the first of which is entered when it is called
+    pub blocks: Vec<Block>,
+}
+
+/// a variable, which is assigned once
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Var(pub u32);
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct BlockId(pub u32);
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    /// the variables assigned by the jumps to this block
+    pub params: Vec<Var>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// assigns the result of an operation to a variable
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub var: Var,
+    pub value: Rvalue,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand {
+    Var(Var),
+    Constant(Constant),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Constant {
+    Int(BigInt),
+    String(String),
+    Char(char),
+    Bool(bool),
+    Unit,
+}
+
+/// a reference to a binding in the universe
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Global {
+    pub name: String,
+    /// the index of the binding in the universe
+    pub binding: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// the remainder of a division, which takes the sign of the left side
+    Rem,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    /// compares two integers, two strings or two chars
+    Eq,
+    Ne,
+    /// bitwise operators, which treat integers as if they were in two's complement
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    /// negates a boolean, or inverts the bits of an integer
+    Not,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Rvalue {
+    Binary {
+        op: BinaryOp,
+        l: Operand,
+        r: Operand,
+    },
+    Unary {
+        op: UnaryOp,
+        arg: Operand,
+    },
+    Call {
+        callee: Global,
+        args: Vec<Operand>,
+    },
+    /// calls a function reference
+    CallIndirect {
+        callee: Operand,
+        args: Vec<Operand>,
+    },
+    Builtin {
+        builtin: Builtin,
+        args: Vec<Operand>,
+    },
+    Construct {
+        constructor: Global,
+        args: Vec<Operand>,
+    },
+    /// a function or constructor used as a value
+    FunctionRef(Global),
+    List(Vec<Operand>),
+    /// checks if a variant was built by a constructor
+    IsVariant {
+        value: Operand,
+        constructor: Global,
+    },
+    /// retrieves a field of a variant
+    Field {
+        value: Operand,
+        index: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Terminator {
+    Return(Operand),
+    Jump {
+        target: BlockId,
+        args: Vec<Operand>,
+    },
+    Branch {
+        condition: Operand,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// fails, as no pattern matched the value
+    NoMatch,
+}
+
+impl From<Var> for Operand {
+    fn from(var: Var) -> Self {
+        Operand::Var(var)
+    }
+}
+
+impl From<Constant> for Operand {
+    fn from(constant: Constant) -> Self {
+        Operand::Constant(constant)
+    }
+}
+
+impl BinaryOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Sub => "sub",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Rem => "rem",
+            BinaryOp::Lt => "lt",
+            BinaryOp::Gt => "gt",
+            BinaryOp::Le => "le",
+            BinaryOp::Ge => "ge",
+            BinaryOp::Eq => "eq",
+            BinaryOp::Ne => "ne",
+            BinaryOp::BitAnd => "and",
+            BinaryOp::BitOr => "or",
+            BinaryOp::BitXor => "xor",
+            BinaryOp::Shl => "shl",
+            BinaryOp::Shr => "shr",
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "neg",
+            UnaryOp::Not => "not",
+        }
+    }
+}
+
+impl Function {
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+}
+
+impl Terminator {
+    /// the blocks control may move on to
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Return(_) | Terminator::NoMatch => vec![],
+            Terminator::Jump { target, .. } => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::{golden, Evaluator, EvaluatorConfig};
+    use parser::lexer;
+    use std::{
+        borrow::Cow,
+        collections::{HashMap, HashSet},
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    pub(crate) fn populate(source: &str) -> Evaluator<'static> {
+        let mut file_tags = HashMap::new();
+        file_tags.insert(String::from("test.sf"), Vec::new());
+
+        let mut evaluator = Evaluator::new(EvaluatorConfig {
+            project_tag: TagName::Primary(Cow::Borrowed("test")),
+            file_tags,
+        });
+
+        let mut files = HashMap::new();
+        files.insert(
+            String::from("test.sf"),
+            parser::parse(lexer::lex(source)).unwrap(),
+        );
+        evaluator.populate(&files).unwrap();
+        evaluator
+    }
+
+    /// the programs in the golden directory, each of which has its dump alongside it in a `.ir`
+    /// file
+    fn golden() -> Vec<PathBuf> {
+        golden::programs(&Path::new(env!("CARGO_MANIFEST_DIR")).join("golden/ir"))
+    }
+
+    /// checks that every variable is assigned once, and that every block referred to exists
+    fn check_ssa(function: &Function) {
+        let mut assigned = HashSet::new();
+        let vars = function
+            .params
+            .iter()
+            .chain(function.blocks.iter().flat_map(|block| {
+                block.params.iter().chain(
+                    block
+                        .instructions
+                        .iter()
+                        .map(|instruction| &instruction.var),
+                )
+            }));
+        for var in vars {
+            assert!(assigned.insert(*var), "{:?} is assigned twice", var);
+        }
+
+        for block in &function.blocks {
+            for successor in block.terminator.successors() {
+                assert!((successor.0 as usize) < function.blocks.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_golden() {
+        for program in golden() {
+            let evaluator = populate(&fs::read_to_string(&program).unwrap());
+            let lowered = evaluator.lower().unwrap();
+            for function in &lowered.functions {
+                check_ssa(function);
+            }
+
+            golden::check(&program.with_extension("ir"), &lowered.to_string());
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let evaluator = populate(
+            "type shape = circle ilarge | empty
+size :: tag nothing
+size :: ilarge -> shape
+size n => circle n
+
+
+",
+        );
+        let lowered = evaluator.lower().unwrap();
+        let function = &lowered.functions[0];
+        let bindings = evaluator.bindings();
+        assert_eq!(bindings[function.binding].0, "size");
+
+        let constructor = match &function.blocks[0].instructions[0].value {
+            Rvalue::Construct { constructor, .. } => constructor,
+            value => panic!("unexpected value: {:?}", value),
+        };
+        assert_eq!(bindings[constructor.binding].0, "circle");
+    }
+}
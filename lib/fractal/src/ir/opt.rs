@@ -0,0 +1,675 @@
+// optimisation passes over the intermediate representation
+
+use super::{
+    BinaryOp, Block, BlockId, Constant, Function, Global, Instruction, Operand, Program, Rvalue,
+    Terminator, UnaryOp, Var,
+};
+use num_bigint::BigInt;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+    iter,
+};
+
+/// the passes to run over a program, along with their settings
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Passes {
+    /// replaces operations on constants with their results
+    pub fold_constants: bool,
+
+    /// replaces calls to small functions with their bodies
+    pub inline: bool,
+
+    /// the most instructions a function may have for calls to it to be inlined
+    pub inline_threshold: usize,
+
+    /// removes the functions that can't be reached from the entry point
+    pub eliminate_dead_bindings: bool,
+
+    /// the name of the function every other function must be reachable from to be kept
+    pub entry: String,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Self {
+            fold_constants: true,
+            inline: true,
+            inline_threshold: 8,
+            eliminate_dead_bindings: true,
+            entry: String::from("main"),
+        }
+    }
+}
+
+impl Passes {
+    /// disables every pass
+    pub fn none() -> Self {
+        Self {
+            fold_constants: false,
+            inline: false,
+            eliminate_dead_bindings: false,
+            ..Self::default()
+        }
+    }
+
+    /// runs the enabled passes over a program. inlining runs first, so that the constants passed
+    /// to inlined functions may be folded, and removing dead bindings runs last, as inlining may
+    /// leave functions unused
+    pub fn run(&self, program: &mut Program) {
+        if self.inline {
+            inline(program, self.inline_threshold);
+        }
+        if self.fold_constants {
+            for function in &mut program.functions {
+                fold_constants(function);
+            }
+        }
+        if self.eliminate_dead_bindings {
+            eliminate_dead_bindings(program, &self.entry);
+        }
+    }
+}
+
+/// folds binary operations on constants, and branches on constant conditions, removing the
+/// blocks that can no longer be reached afterwards
+///
+/// divisions by zero and shifts by negative amounts are left alone, so that they still fail at
+/// runtime
+pub fn fold_constants(function: &mut Function) {
+    let mut constants = HashMap::new();
+
+    // blocks may use the variables of blocks after them, so this repeats until nothing changes
+    loop {
+        let mut changed = false;
+        for block in &mut function.blocks {
+            let mut instructions = Vec::with_capacity(block.instructions.len());
+            for mut instruction in block.instructions.drain(..) {
+                for operand in rvalue_operands(&mut instruction.value) {
+                    resolve(operand, &constants);
+                }
+                match fold(&instruction.value) {
+                    Some(constant) => {
+                        constants.insert(instruction.var, constant.into());
+                        changed = true;
+                    }
+                    None => instructions.push(instruction),
+                }
+            }
+            block.instructions = instructions;
+
+            for operand in terminator_operands(&mut block.terminator) {
+                resolve(operand, &constants);
+            }
+            let target = match &block.terminator {
+                Terminator::Branch {
+                    condition: Operand::Constant(Constant::Bool(condition)),
+                    then,
+                    otherwise,
+                } => Some(if *condition { *then } else { *otherwise }),
+                _ => None,
+            };
+            if let Some(target) = target {
+                block.terminator = Terminator::Jump {
+                    target,
+                    args: vec![],
+                };
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    remove_unreachable_blocks(function);
+}
+
+fn fold(value: &Rvalue) -> Option<Constant> {
+    let (op, l, r) = match value {
+        Rvalue::Binary {
+            op,
+            l: Operand::Constant(l),
+            r: Operand::Constant(r),
+        } => (op, l, r),
+        Rvalue::Unary {
+            op,
+            arg: Operand::Constant(arg),
+        } => return fold_unary(*op, arg),
+        _ => return None,
+    };
+
+    Some(match (op, l, r) {
+        (BinaryOp::Add, Constant::Int(l), Constant::Int(r)) => Constant::Int(l + r),
+        (BinaryOp::Sub, Constant::Int(l), Constant::Int(r)) => Constant::Int(l - r),
+        (BinaryOp::Mul, Constant::Int(l), Constant::Int(r)) => Constant::Int(l * r),
+        (BinaryOp::Div, Constant::Int(l), Constant::Int(r)) if *r != BigInt::from(0) => {
+            Constant::Int(l / r)
+        }
+        (BinaryOp::Rem, Constant::Int(l), Constant::Int(r)) if *r != BigInt::from(0) => {
+            Constant::Int(l % r)
+        }
+        (BinaryOp::BitAnd, Constant::Int(l), Constant::Int(r)) => Constant::Int(l & r),
+        (BinaryOp::BitOr, Constant::Int(l), Constant::Int(r)) => Constant::Int(l | r),
+        (BinaryOp::BitXor, Constant::Int(l), Constant::Int(r)) => Constant::Int(l ^ r),
+        (BinaryOp::Shl, Constant::Int(l), Constant::Int(r)) => {
+            Constant::Int(l << usize::try_from(r).ok()?)
+        }
+        (BinaryOp::Shr, Constant::Int(l), Constant::Int(r)) => {
+            Constant::Int(l >> usize::try_from(r).ok()?)
+        }
+        (BinaryOp::Lt, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l < r),
+        (BinaryOp::Gt, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l > r),
+        (BinaryOp::Le, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l <= r),
+        (BinaryOp::Ge, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l >= r),
+        (BinaryOp::Eq, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l == r),
+        (BinaryOp::Ne, Constant::Int(l), Constant::Int(r)) => Constant::Bool(l != r),
+        (BinaryOp::Eq, Constant::Bool(l), Constant::Bool(r)) => Constant::Bool(l == r),
+        (BinaryOp::Ne, Constant::Bool(l), Constant::Bool(r)) => Constant::Bool(l != r),
+        (BinaryOp::Eq, Constant::String(l), Constant::String(r)) => Constant::Bool(l == r),
+        (BinaryOp::Ne, Constant::String(l), Constant::String(r)) => Constant::Bool(l != r),
+        (BinaryOp::Lt, Constant::Char(l), Constant::Char(r)) => Constant::Bool(l < r),
+        (BinaryOp::Ge, Constant::Char(l), Constant::Char(r)) => Constant::Bool(l >= r),
+        (BinaryOp::Eq, Constant::Char(l), Constant::Char(r)) => Constant::Bool(l == r),
+        (BinaryOp::Ne, Constant::Char(l), Constant::Char(r)) => Constant::Bool(l != r),
+        _ => return None,
+    })
+}
+
+fn fold_unary(op: UnaryOp, arg: &Constant) -> Option<Constant> {
+    Some(match (op, arg) {
+        (UnaryOp::Neg, Constant::Int(int)) => Constant::Int(-int),
+        (UnaryOp::Not, Constant::Bool(b)) => Constant::Bool(!b),
+        // the two's complement of an integer with its bits inverted
+        (UnaryOp::Not, Constant::Int(int)) => Constant::Int(-int - 1),
+        _ => return None,
+    })
+}
+
+/// replaces calls to functions with a single block of at most `threshold` instructions with the
+/// instructions of that block
+///
+/// only the functions as they were before the pass are inlined, so calls within inlined functions
+/// are kept as they are
+pub fn inline(program: &mut Program, threshold: usize) {
+    let inlinable: HashMap<usize, Function> = program
+        .functions
+        .iter()
+        .filter(|function| is_inlinable(function, threshold))
+        .map(|function| (function.binding, function.clone()))
+        .collect();
+
+    for function in &mut program.functions {
+        let mut next_var = next_var(function);
+        let mut results = HashMap::new();
+
+        for block in &mut function.blocks {
+            let mut instructions = Vec::with_capacity(block.instructions.len());
+            for instruction in block.instructions.drain(..) {
+                let (callee, args) = match &instruction.value {
+                    Rvalue::Call { callee, args } if callee.binding != function.binding => {
+                        match inlinable.get(&callee.binding) {
+                            Some(callee) => (callee, args),
+                            None => {
+                                instructions.push(instruction);
+                                continue;
+                            }
+                        }
+                    }
+                    _ => {
+                        instructions.push(instruction);
+                        continue;
+                    }
+                };
+
+                // the parameters of the callee are replaced with the arguments, and its variables
+                // with new ones
+                let mut renamed: HashMap<Var, Operand> = callee
+                    .params
+                    .iter()
+                    .copied()
+                    .zip(args.iter().cloned())
+                    .collect();
+                let body = &callee.blocks[0];
+                for inlined in &body.instructions {
+                    let var = Var(next_var);
+                    next_var += 1;
+                    renamed.insert(inlined.var, var.into());
+
+                    let mut value = inlined.value.clone();
+                    for operand in rvalue_operands(&mut value) {
+                        rename(operand, &renamed);
+                    }
+                    instructions.push(Instruction { var, value });
+                }
+
+                if let Terminator::Return(result) = &body.terminator {
+                    let mut result = result.clone();
+                    rename(&mut result, &renamed);
+                    results.insert(instruction.var, result);
+                }
+            }
+            block.instructions = instructions;
+        }
+
+        substitute(function, &results);
+    }
+}
+
+fn is_inlinable(function: &Function, threshold: usize) -> bool {
+    match function.blocks.as_slice() {
+        [Block {
+            instructions,
+            terminator: Terminator::Return(_),
+            ..
+        }] => {
+            instructions.len() <= threshold
+                && !globals(function).any(|global| global.binding == function.binding)
+        }
+        _ => false,
+    }
+}
+
+/// removes every function that can't be reached from a function named `entry` through the
+/// references to other bindings in it. if there is no such function, every function is kept
+pub fn eliminate_dead_bindings(program: &mut Program, entry: &str) {
+    let functions: HashMap<usize, &Function> = program
+        .functions
+        .iter()
+        .map(|function| (function.binding, function))
+        .collect();
+
+    let mut pending: Vec<usize> = program
+        .functions
+        .iter()
+        .filter(|function| function.name == entry)
+        .map(|function| function.binding)
+        .collect();
+    if pending.is_empty() {
+        return;
+    }
+
+    let mut live = HashSet::new();
+    while let Some(binding) = pending.pop() {
+        if !live.insert(binding) {
+            continue;
+        }
+        if let Some(function) = functions.get(&binding) {
+            pending.extend(globals(function).map(|global| global.binding));
+        }
+    }
+
+    program
+        .functions
+        .retain(|function| live.contains(&function.binding));
+}
+
+/// every reference to another binding in a function
+fn globals(function: &Function) -> impl Iterator<Item = &Global> {
+    function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .filter_map(|instruction| match &instruction.value {
+            Rvalue::Call { callee, .. } => Some(callee),
+            Rvalue::Construct { constructor, .. } | Rvalue::IsVariant { constructor, .. } => {
+                Some(constructor)
+            }
+            Rvalue::FunctionRef(global) => Some(global),
+            _ => None,
+        })
+}
+
+fn remove_unreachable_blocks(function: &mut Function) {
+    let mut reachable = HashSet::new();
+    let mut pending = vec![BlockId(0)];
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            pending.extend(function.block(id).terminator.successors());
+        }
+    }
+    if reachable.len() == function.blocks.len() {
+        return;
+    }
+
+    // the remaining blocks keep their order
+    let mut renumbered = HashMap::new();
+    let blocks = function
+        .blocks
+        .drain(..)
+        .enumerate()
+        .filter(|(id, _)| reachable.contains(&BlockId(*id as u32)))
+        .map(|(id, block)| {
+            renumbered.insert(BlockId(id as u32), BlockId(renumbered.len() as u32));
+            block
+        })
+        .collect();
+    function.blocks = blocks;
+
+    for block in &mut function.blocks {
+        match &mut block.terminator {
+            Terminator::Jump { target, .. } => *target = renumbered[target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => {
+                *then = renumbered[then];
+                *otherwise = renumbered[otherwise];
+            }
+            Terminator::Return(_) | Terminator::NoMatch => (),
+        }
+    }
+}
+
+/// replaces the uses of variables throughout a function
+fn substitute(function: &mut Function, substitutions: &HashMap<Var, Operand>) {
+    if substitutions.is_empty() {
+        return;
+    }
+
+    for block in &mut function.blocks {
+        for instruction in &mut block.instructions {
+            for operand in rvalue_operands(&mut instruction.value) {
+                resolve(operand, substitutions);
+            }
+        }
+        for operand in terminator_operands(&mut block.terminator) {
+            resolve(operand, substitutions);
+        }
+    }
+}
+
+/// replaces a variable with what it is substituted by, following substitutions of substitutions
+fn resolve(operand: &mut Operand, substitutions: &HashMap<Var, Operand>) {
+    while let Operand::Var(var) = operand {
+        match substitutions.get(var) {
+            Some(substitution) => *operand = substitution.clone(),
+            None => break,
+        }
+    }
+}
+
+/// replaces a variable of an inlined function with what it is renamed to. unlike `resolve`, this
+/// doesn't follow the result, as it belongs to another function
+fn rename(operand: &mut Operand, renamed: &HashMap<Var, Operand>) {
+    if let Operand::Var(var) = operand {
+        if let Some(replacement) = renamed.get(var) {
+            *operand = replacement.clone();
+        }
+    }
+}
+
+/// the first variable not used by a function
+fn next_var(function: &Function) -> u32 {
+    function
+        .params
+        .iter()
+        .chain(function.blocks.iter().flat_map(|block| {
+            block.params.iter().chain(
+                block
+                    .instructions
+                    .iter()
+                    .map(|instruction| &instruction.var),
+            )
+        }))
+        .map(|var| var.0 + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+fn rvalue_operands(value: &mut Rvalue) -> Vec<&mut Operand> {
+    match value {
+        Rvalue::Binary { l, r, .. } => vec![l, r],
+        Rvalue::Unary { arg, .. } => vec![arg],
+        Rvalue::Call { args, .. }
+        | Rvalue::Builtin { args, .. }
+        | Rvalue::Construct { args, .. }
+        | Rvalue::List(args) => args.iter_mut().collect(),
+        Rvalue::CallIndirect { callee, args } => iter::once(callee).chain(args).collect(),
+        Rvalue::FunctionRef(_) => vec![],
+        Rvalue::IsVariant { value, .. } | Rvalue::Field { value, .. } => vec![value],
+    }
+}
+
+fn terminator_operands(terminator: &mut Terminator) -> Vec<&mut Operand> {
+    match terminator {
+        Terminator::Return(value) => vec![value],
+        Terminator::Jump { args, .. } => args.iter_mut().collect(),
+        Terminator::Branch { condition, .. } => vec![condition],
+        Terminator::NoMatch => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::test::populate;
+    use indoc::indoc;
+
+    fn optimize(source: &str, passes: Passes) -> String {
+        let mut program = populate(source).lower().unwrap();
+        passes.run(&mut program);
+        program.to_string()
+    }
+
+    const ARITHMETIC: &str = indoc! {"
+        triple :: tag nothing
+        triple :: ilarge -> ilarge
+        triple n => (1 + 2) * n
+
+
+        pick :: tag nothing
+        pick :: ilarge -> string
+        pick n => match 2 + 3 =>
+          0 => \"zero\"
+          5 => \"five\"
+          _ => \"other\"
+
+
+        fail :: tag nothing
+        fail :: ilarge -> ilarge
+        fail n => 1 / 0
+    "};
+
+    #[test]
+    fn test_fold_constants() {
+        let passes = Passes {
+            fold_constants: true,
+            ..Passes::none()
+        };
+        assert_eq!(
+            optimize(ARITHMETIC, passes),
+            indoc! {"
+                fn fail(%0) [*test, nothing] {
+                bb0:
+                  %1 = div 1, 0
+                  return %1
+                }
+
+                fn pick(%0) [*test, nothing] {
+                bb0:
+                  jump bb2
+                bb1(%2):
+                  return %2
+                bb2:
+                  jump bb3
+                bb3:
+                  jump bb1(\"five\")
+                }
+
+                fn triple(%0) [*test, nothing] {
+                bb0:
+                  %2 = mul 3, %0
+                  return %2
+                }
+            "}
+        );
+    }
+
+    #[test]
+    fn test_fold_operators() {
+        let source = indoc! {"
+            parity :: tag nothing
+            parity :: ilarge -> bool
+            parity n => 7 % 2 != -1 && !(1 << 3 <= (12 ^ 10)) || n % 2 == 0
+
+
+            fail :: tag nothing
+            fail :: ilarge -> ilarge
+            fail n => (1 % 0) + (1 >> -1)
+        "};
+        let passes = Passes {
+            fold_constants: true,
+            ..Passes::none()
+        };
+        assert_eq!(
+            optimize(source, passes),
+            indoc! {"
+                fn fail(%0) [*test, nothing] {
+                bb0:
+                  %1 = rem 1, 0
+                  %3 = shr 1, -1
+                  %4 = add %1, %3
+                  return %4
+                }
+
+                fn parity(%0) [*test, nothing] {
+                bb0:
+                  jump bb2
+                bb1(%4):
+                  branch %4, bb4, bb5
+                bb2:
+                  jump bb1(true)
+                bb3(%9):
+                  return %9
+                bb4:
+                  jump bb3(true)
+                bb5:
+                  %10 = rem %0, 2
+                  %11 = eq %10, 0
+                  jump bb3(%11)
+                }
+            "}
+        );
+    }
+
+    const CALLS: &str = indoc! {"
+        double :: tag nothing
+        double :: ilarge -> ilarge
+        double n => n * 2
+
+
+        fib :: tag nothing
+        fib :: ilarge -> ilarge
+        fib n => match n =>
+          0..2 => n
+          _ => (fib (n - 1)) + (fib (n - 2))
+
+
+        unused :: tag nothing
+        unused :: ilarge -> ilarge
+        unused n => fib n
+
+
+        apply :: tag nothing
+        apply :: (ilarge -> ilarge) ilarge -> ilarge
+        apply f n => f n
+
+
+        main :: tag nothing
+        main :: ilarge -> ilarge
+        main a => apply double (double (double 5))
+    "};
+
+    #[test]
+    fn test_inline() {
+        let passes = Passes {
+            inline: true,
+            ..Passes::none()
+        };
+        let optimized = optimize(CALLS, passes);
+        assert!(optimized.contains(indoc! {"
+            fn main(%0) [*test, nothing] {
+            bb0:
+              %1 = ref @double
+              %5 = mul 5, 2
+              %6 = mul %5, 2
+              %7 = call %1(%6)
+              return %7
+            }
+        "}));
+
+        // functions with more than one block, and those above the threshold, are kept as calls
+        assert!(optimized.contains(indoc! {"
+            fn unused(%0) [*test, nothing] {
+            bb0:
+              %1 = call @fib(%0)
+              return %1
+            }
+        "}));
+        let passes = Passes {
+            inline: true,
+            inline_threshold: 0,
+            ..Passes::none()
+        };
+        assert!(optimize(CALLS, passes).contains("%2 = call @double(5)"));
+    }
+
+    #[test]
+    fn test_eliminate_dead_bindings() {
+        let passes = Passes {
+            eliminate_dead_bindings: true,
+            ..Passes::none()
+        };
+        let optimized = optimize(CALLS, passes);
+        let functions: Vec<&str> = optimized
+            .lines()
+            .filter(|line| line.starts_with("fn "))
+            .collect();
+        // `double` is kept, as it is referenced by `main` even though it isn't called there
+        assert_eq!(
+            functions,
+            vec![
+                "fn apply(%0, %1) [*test, nothing] {",
+                "fn double(%0) [*test, nothing] {",
+                "fn main(%0) [*test, nothing] {"
+            ]
+        );
+
+        // without an entry point, nothing is removed
+        let passes = Passes {
+            eliminate_dead_bindings: true,
+            entry: String::from("start"),
+            ..Passes::none()
+        };
+        assert_eq!(optimize(CALLS, passes), optimize(CALLS, Passes::none()));
+    }
+
+    #[test]
+    fn test_passes() {
+        let unoptimized = populate(CALLS).lower().unwrap();
+        let mut program = unoptimized.clone();
+        Passes::none().run(&mut program);
+        assert_eq!(program, unoptimized);
+
+        // inlining leaves `apply` unused and the arguments of `double` to be folded, while `double`
+        // is kept as it is still referenced
+        assert_eq!(
+            optimize(CALLS, Passes::default()),
+            indoc! {"
+                fn double(%0) [*test, nothing] {
+                bb0:
+                  %1 = mul %0, 2
+                  return %1
+                }
+
+                fn main(%0) [*test, nothing] {
+                bb0:
+                  %1 = ref @double
+                  %7 = call %1(20)
+                  return %7
+                }
+            "}
+        );
+    }
+}
@@ -0,0 +1,628 @@
+// a just-in-time compiler running integer functions natively
+//
+// functions whose arguments and result are all integers are compiled with cranelift once they have
+// been called enough times, along with every function they call. integers are lowered to 64-bit
+// machine integers, so an operation which would overflow, divide by zero or fail to match stops
+// the native code and the call is retried in the interpreter, where the integer grows or the error
+// is reported as usual. functions using anything else are left to the interpreter
+
+use crate::{builtins, typeck, UniverseItem, Value};
+use cranelift_codegen::{
+    ir::{
+        condcodes::IntCC, types, AbiParam, Block, FuncRef, Function, InstBuilder, MemFlags,
+        Signature, UserFuncName,
+    },
+    settings::{self, Configurable},
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use parser::ast::{Expression, OpSymbol, Pattern, Type};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// the number of times a function is called before it is compiled
+const HOT_CALLS: usize = 8;
+
+/// the entry point of a compiled function, which is passed its arguments and a flag that is set if
+/// it has to be retried in the interpreter
+type EntryPoint = unsafe extern "C" fn(*const i64, *mut u8) -> i64;
+
+/// the native values holding the local bindings in scope
+type Locals = HashMap<String, cranelift_codegen::ir::Value>;
+
+pub struct Jit {
+    module: JITModule,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+
+    /// the number of times each function that hasn't been considered for compilation was called
+    calls: HashMap<String, usize>,
+
+    /// the functions that have been considered for compilation, which are `None` if they couldn't
+    /// be compiled
+    functions: HashMap<String, Option<Compiled>>,
+    compiled: usize,
+}
+
+struct Compiled {
+    id: FuncId,
+    arity: usize,
+    entry_point: EntryPoint,
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        let mut flags = settings::builder();
+        // TODO(superwhiskers): remove expect
+        flags
+            .set("use_colocated_libcalls", "false")
+            .expect("unable to configure cranelift");
+        flags
+            .set("is_pic", "false")
+            .expect("unable to configure cranelift");
+        flags
+            .set("opt_level", "speed")
+            .expect("unable to configure cranelift");
+
+        // TODO(superwhiskers): remove expect
+        let isa = cranelift_native::builder()
+            .expect("the host machine is not supported by cranelift")
+            .finish(settings::Flags::new(flags))
+            .expect("unable to configure cranelift");
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+        Self {
+            context: module.make_context(),
+            module,
+            builder_context: FunctionBuilderContext::new(),
+            calls: HashMap::new(),
+            functions: HashMap::new(),
+            compiled: 0,
+        }
+    }
+
+    /// the number of functions that have been compiled
+    pub fn compiled(&self) -> usize {
+        self.compiled
+    }
+
+    /// checks if a function has been compiled
+    pub fn is_compiled(&self, name: &str) -> bool {
+        matches!(self.functions.get(name), Some(Some(_)))
+    }
+
+    /// calls a function natively, compiling it if it has become hot. `None` is returned if the
+    /// call has to be made in the interpreter instead. the items of the universe are only retrieved
+    /// if the function is compiled
+    pub fn call<'i>(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        items: impl FnOnce() -> HashMap<&'i str, &'i UniverseItem>,
+    ) -> Option<Value> {
+        if !self.functions.contains_key(name) {
+            let calls = self.calls.entry(String::from(name)).or_insert(0);
+            *calls += 1;
+            if *calls < HOT_CALLS {
+                return None;
+            }
+
+            self.calls.remove(name);
+            if !self.compile(name, &items()) {
+                self.functions.insert(String::from(name), None);
+            }
+        }
+
+        let compiled = self.functions.get(name)?.as_ref()?;
+        if args.len() != compiled.arity {
+            return None;
+        }
+
+        let mut ints = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                Value::Int(int) => ints.push(i64::try_from(int).ok()?),
+                _ => return None,
+            }
+        }
+
+        let mut failed = 0;
+        // safety: the entry point reads exactly as many arguments as the function takes
+        let value = unsafe { (compiled.entry_point)(ints.as_ptr(), &mut failed) };
+        if failed == 0 {
+            Some(Value::Int(value.into()))
+        } else {
+            None
+        }
+    }
+
+    /// compiles a function along with every function it calls, returning whether it could be
+    fn compile(&mut self, name: &str, items: &HashMap<&str, &UniverseItem>) -> bool {
+        let mut batch = Batch {
+            module: &mut self.module,
+            functions: &self.functions,
+            declared: Vec::new(),
+        };
+        if batch.declare(name, items).is_none() {
+            return false;
+        }
+
+        // the functions called are declared while lowering, so they are lowered in turn until
+        // none are left
+        let mut lowered = Vec::new();
+        let mut next = 0;
+        while let Some((name, id, arity)) = batch.declared.get(next).cloned() {
+            next += 1;
+            match lower(
+                &mut batch,
+                &mut self.builder_context,
+                items,
+                &name,
+                id,
+                arity,
+            ) {
+                Some(function) => lowered.push((name, id, arity, function)),
+                None => return false,
+            }
+        }
+
+        let mut defined = Vec::with_capacity(lowered.len());
+        for (name, id, arity, function) in lowered {
+            self.context.func = function;
+            // TODO(superwhiskers): remove expect
+            self.module
+                .define_function(id, &mut self.context)
+                .expect("unable to define a compiled function");
+            self.module.clear_context(&mut self.context);
+
+            let entry_point = self.entry_point(&name, id, arity);
+            defined.push((name, id, arity, entry_point));
+        }
+
+        // TODO(superwhiskers): remove expect
+        self.module
+            .finalize_definitions()
+            .expect("unable to finalize compiled functions");
+        for (name, id, arity, entry_point) in defined {
+            let entry_point = self.module.get_finalized_function(entry_point);
+            self.functions.insert(
+                name,
+                Some(Compiled {
+                    id,
+                    arity,
+                    // safety: entry points are defined with this signature
+                    entry_point: unsafe {
+                        std::mem::transmute::<*const u8, EntryPoint>(entry_point)
+                    },
+                }),
+            );
+            self.compiled += 1;
+        }
+        true
+    }
+
+    /// defines the entry point of a function, which loads its arguments from memory, returning
+    /// its id
+    fn entry_point(&mut self, name: &str, id: FuncId, arity: usize) -> FuncId {
+        let pointer = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::I64));
+
+        // TODO(superwhiskers): remove expect
+        let entry_point = self
+            .module
+            .declare_function(&format!("sf.{}.entry", name), Linkage::Local, &signature)
+            .expect("unable to declare an entry point");
+
+        self.context.func =
+            Function::with_name_signature(UserFuncName::user(0, entry_point.as_u32()), signature);
+        {
+            let mut builder =
+                FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+            let block = builder.create_block();
+            builder.append_block_params_for_function_params(block);
+            builder.switch_to_block(block);
+            let (args, failed) = (
+                builder.block_params(block)[0],
+                builder.block_params(block)[1],
+            );
+
+            let mut values = vec![failed];
+            for i in 0..arity {
+                values.push(builder.ins().load(
+                    types::I64,
+                    MemFlags::trusted(),
+                    args,
+                    8 * i as i32,
+                ));
+            }
+            let callee = self.module.declare_func_in_func(id, builder.func);
+            let call = builder.ins().call(callee, &values);
+            let value = builder.inst_results(call)[0];
+            builder.ins().return_(&[value]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        // TODO(superwhiskers): remove expect
+        self.module
+            .define_function(entry_point, &mut self.context)
+            .expect("unable to define an entry point");
+        self.module.clear_context(&mut self.context);
+        entry_point
+    }
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// the functions being compiled together
+struct Batch<'j> {
+    module: &'j mut JITModule,
+    functions: &'j HashMap<String, Option<Compiled>>,
+
+    /// the functions declared so far, along with their arities
+    declared: Vec<(String, FuncId, usize)>,
+}
+
+impl<'j> Batch<'j> {
+    /// declares a function, returning `None` if it can't be compiled
+    fn declare(&mut self, name: &str, items: &HashMap<&str, &UniverseItem>) -> Option<FuncId> {
+        match self.functions.get(name) {
+            Some(Some(compiled)) => return Some(compiled.id),
+            Some(None) => return None,
+            None => (),
+        }
+        if let Some((_, id, _)) = self.declared.iter().find(|(n, _, _)| n == name) {
+            return Some(*id);
+        }
+
+        let arity = integer_arity(items.get(name)?)?;
+        let id = self
+            .module
+            .declare_function(
+                &format!("sf.{}", name),
+                Linkage::Local,
+                &signature(self.module, arity),
+            )
+            .ok()?;
+        self.declared.push((String::from(name), id, arity));
+        Some(id)
+    }
+}
+
+/// lowers a function to cranelift's intermediate representation, returning `None` if it uses
+/// anything that can't be compiled
+fn lower(
+    batch: &mut Batch,
+    builder_context: &mut FunctionBuilderContext,
+    items: &HashMap<&str, &UniverseItem>,
+    name: &str,
+    id: FuncId,
+    arity: usize,
+) -> Option<Function> {
+    let (args, body) = match items.get(name) {
+        Some(UniverseItem::FnDecl { args, body, .. }) => (args, body),
+        _ => return None,
+    };
+
+    let mut function = Function::with_name_signature(
+        UserFuncName::user(0, id.as_u32()),
+        signature(batch.module, arity),
+    );
+    let lowered = {
+        let mut builder = FunctionBuilder::new(&mut function, builder_context);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        let params = builder.block_params(block).to_vec();
+        let fail = builder.create_block();
+
+        let mut lowering = Lowering {
+            builder,
+            batch,
+            items,
+            callees: HashMap::new(),
+            failed: params[0],
+            fail,
+        };
+        let locals: Locals = args.iter().cloned().zip(params[1..].to_vec()).collect();
+        let value = lowering.block(&locals, body);
+
+        // the function is finished even if it can't be compiled, leaving the builder context ready
+        // to be used again
+        let builder = &mut lowering.builder;
+        match value {
+            Some(value) => builder.ins().return_(&[value]),
+            None => builder.ins().jump(fail, &[]),
+        };
+
+        // failures set the flag and return immediately
+        builder.switch_to_block(fail);
+        let one = builder.ins().iconst(types::I8, 1);
+        builder
+            .ins()
+            .store(MemFlags::trusted(), one, lowering.failed, 0);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.ins().return_(&[zero]);
+
+        builder.seal_all_blocks();
+        lowering.builder.finalize();
+        value.is_some()
+    };
+
+    if lowered {
+        Some(function)
+    } else {
+        None
+    }
+}
+
+struct Lowering<'b, 'l, 'j> {
+    builder: FunctionBuilder<'b>,
+    batch: &'l mut Batch<'j>,
+    items: &'l HashMap<&'l str, &'l UniverseItem>,
+
+    /// the functions referenced so far
+    callees: HashMap<String, FuncRef>,
+
+    /// the address of the flag set on failure
+    failed: cranelift_codegen::ir::Value,
+    /// the block setting the flag and returning
+    fail: Block,
+}
+
+impl<'b, 'l, 'j> Lowering<'b, 'l, 'j> {
+    /// lowers a block, returning the value of its last expression
+    fn block(
+        &mut self,
+        locals: &Locals,
+        body: &[Box<Expression>],
+    ) -> Option<cranelift_codegen::ir::Value> {
+        let mut value = None;
+        for expr in body {
+            value = Some(self.expression(locals, expr)?);
+        }
+        value
+    }
+
+    fn expression(
+        &mut self,
+        locals: &Locals,
+        expr: &Expression,
+    ) -> Option<cranelift_codegen::ir::Value> {
+        Some(match expr {
+            Expression::Integer(int) => {
+                let int = i64::try_from(int).ok()?;
+                self.builder.ins().iconst(types::I64, int)
+            }
+            Expression::TypeDecl { expr, .. } => self.expression(locals, expr)?,
+            Expression::Identifier(name) => match locals.get(name) {
+                Some(value) => *value,
+                None => self.call(locals, name, &[])?,
+            },
+            Expression::OpCall { op, args } => match args.as_slice() {
+                [l, r] => {
+                    let l = self.expression(locals, l)?;
+                    let r = self.expression(locals, r)?;
+                    self.arithmetic(op, l, r)?
+                }
+                _ => return None,
+            },
+            Expression::FnCall { name, args } => self.call(locals, name, args)?,
+            Expression::Match { expr, args } => self.match_expression(locals, expr, args)?,
+            _ => return None,
+        })
+    }
+
+    fn arithmetic(
+        &mut self,
+        op: &OpSymbol,
+        l: cranelift_codegen::ir::Value,
+        r: cranelift_codegen::ir::Value,
+    ) -> Option<cranelift_codegen::ir::Value> {
+        let (value, overflow) = match op {
+            OpSymbol::Plus => self.builder.ins().sadd_overflow(l, r),
+            OpSymbol::Minus => self.builder.ins().ssub_overflow(l, r),
+            OpSymbol::Star => self.builder.ins().smul_overflow(l, r),
+            OpSymbol::ForwardSlash => {
+                // division traps in both of these cases, so they are left to the interpreter
+                let zero = self.builder.ins().icmp_imm(IntCC::Equal, r, 0);
+                self.fail_if(zero);
+                let min = self.builder.ins().icmp_imm(IntCC::Equal, l, i64::MIN);
+                let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, r, -1);
+                let overflow = self.builder.ins().band(min, minus_one);
+                self.fail_if(overflow);
+                return Some(self.builder.ins().sdiv(l, r));
+            }
+            _ => return None,
+        };
+        self.fail_if(overflow);
+        Some(value)
+    }
+
+    fn call(
+        &mut self,
+        locals: &Locals,
+        name: &str,
+        args: &[Expression],
+    ) -> Option<cranelift_codegen::ir::Value> {
+        // builtins take precedence, as they do in the interpreter, and function references are
+        // never integers
+        if name == "println" || builtins::LIST_BUILTINS.contains(&name) || locals.contains_key(name)
+        {
+            return None;
+        }
+        if integer_arity(self.items.get(name)?)? != args.len() {
+            return None;
+        }
+
+        let mut values = vec![self.failed];
+        for arg in args {
+            values.push(self.expression(locals, arg)?);
+        }
+
+        let callee = match self.callees.get(name) {
+            Some(callee) => *callee,
+            None => {
+                let id = self.batch.declare(name, self.items)?;
+                let callee = self
+                    .batch
+                    .module
+                    .declare_func_in_func(id, self.builder.func);
+                self.callees.insert(String::from(name), callee);
+                callee
+            }
+        };
+        let call = self.builder.ins().call(callee, &values);
+        let value = self.builder.inst_results(call)[0];
+
+        // a failure in the callee fails the caller too
+        let failed = self
+            .builder
+            .ins()
+            .load(types::I8, MemFlags::trusted(), self.failed, 0);
+        self.fail_if(failed);
+        Some(value)
+    }
+
+    /// lowers a match expression, testing each arm in turn and passing the value of the one that
+    /// matched to the block after them
+    fn match_expression(
+        &mut self,
+        locals: &Locals,
+        expr: &Expression,
+        arms: &[Expression],
+    ) -> Option<cranelift_codegen::ir::Value> {
+        let scrutinee = self.expression(locals, expr)?;
+        let end = self.builder.create_block();
+        let value = self.builder.append_block_param(end, types::I64);
+
+        for arm in arms {
+            let (pat, body) = match arm {
+                Expression::Destructure { pat, body } => (pat, body),
+                _ => return None,
+            };
+
+            // a failed test moves on to the next arm
+            let next = self.builder.create_block();
+            let mut arm_locals = locals.clone();
+            self.pattern(&mut arm_locals, pat, scrutinee, next)?;
+            let arm_value = self.block(&arm_locals, body)?;
+            self.builder.ins().jump(end, &[arm_value]);
+            self.builder.switch_to_block(next);
+        }
+
+        let fail = self.fail;
+        self.builder.ins().jump(fail, &[]);
+        self.builder.switch_to_block(end);
+        Some(value)
+    }
+
+    /// lowers the tests of a pattern against an integer, binding any identifiers in it. failed
+    /// tests jump to the `next` block
+    fn pattern(
+        &mut self,
+        locals: &mut Locals,
+        pat: &Pattern,
+        value: cranelift_codegen::ir::Value,
+        next: Block,
+    ) -> Option<()> {
+        match pat {
+            Pattern::Wildcard => (),
+            Pattern::Identifier(name) => match self.items.get(name.as_str()) {
+                Some(UniverseItem::Constructor { .. }) => return None,
+                _ => {
+                    locals.insert(name.clone(), value);
+                }
+            },
+            Pattern::Integer(int) => {
+                let int = i64::try_from(int).ok()?;
+                let different = self.builder.ins().icmp_imm(IntCC::NotEqual, value, int);
+                self.branch(different, next);
+            }
+            Pattern::Range { start, end } => {
+                // ranges include their start and exclude their end
+                if let Some(start) = start {
+                    let start = range_bound(start)?;
+                    let before = self
+                        .builder
+                        .ins()
+                        .icmp_imm(IntCC::SignedLessThan, value, start);
+                    self.branch(before, next);
+                }
+                if let Some(end) = end {
+                    let end = range_bound(end)?;
+                    let after =
+                        self.builder
+                            .ins()
+                            .icmp_imm(IntCC::SignedGreaterThanOrEqual, value, end);
+                    self.branch(after, next);
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn fail_if(&mut self, condition: cranelift_codegen::ir::Value) {
+        let fail = self.fail;
+        self.branch(condition, fail);
+    }
+
+    /// jumps to a block if a condition holds, continuing in a new block otherwise
+    fn branch(&mut self, condition: cranelift_codegen::ir::Value, target: Block) {
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, target, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+}
+
+/// the signature of a compiled function, which is passed the address of the flag set on failure
+/// followed by its arguments
+fn signature(module: &JITModule, arity: usize) -> Signature {
+    let mut signature = module.make_signature();
+    signature
+        .params
+        .push(AbiParam::new(module.target_config().pointer_type()));
+    for _ in 0..arity {
+        signature.params.push(AbiParam::new(types::I64));
+    }
+    signature.returns.push(AbiParam::new(types::I64));
+    signature
+}
+
+/// the number of arguments a function takes, if its arguments and result are all integers
+fn integer_arity(item: &UniverseItem) -> Option<usize> {
+    let (sig, args) = match item {
+        UniverseItem::FnDecl { sig, args, .. } => (sig, args),
+        _ => return None,
+    };
+    let integers = match sig {
+        Type::FnSig { args: types, ret } => {
+            types.len() == args.len()
+                && types.iter().all(|ty| typeck::is_integer(ty))
+                && typeck::is_integer(ret)
+        }
+        ty => args.is_empty() && typeck::is_integer(ty),
+    };
+    if integers {
+        Some(args.len())
+    } else {
+        None
+    }
+}
+
+fn range_bound(bound: &Pattern) -> Option<i64> {
+    match bound {
+        Pattern::Integer(int) => i64::try_from(int).ok(),
+        _ => None,
+    }
+}
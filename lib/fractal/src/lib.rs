@@ -1,9 +1,23 @@
 use num_bigint::BigInt;
-use parser::ast::{Expression, OpSymbol, Statement, Tag, Type};
+use parser::ast::{Expression, OpSymbol, Pattern, Statement, Tag, Type, Variant};
 use std::{borrow::Cow, collections::HashMap};
 use tag::{TagName, Universe, UniverseEntry, UniverseError};
 use thiserror::Error;
 
+pub mod builtins;
+pub mod bytecode;
+pub mod exhaustiveness;
+#[doc(hidden)]
+pub mod golden;
+pub mod ir;
+#[cfg(feature = "jit")]
+pub mod jit;
+mod operators;
+pub mod typeck;
+pub mod value;
+
+pub use value::Value;
+
 // this is a hack, remove it
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct EvaluatorConfig<'a> {
@@ -15,11 +29,10 @@ pub struct Evaluator<'a> {
     universe: Universe<'a, UniverseItem>,
     config: EvaluatorConfig<'a>,
     pub entries: Vec<UniverseEntry<'a, UniverseItem>>,
+    #[cfg(feature = "jit")]
+    jit: std::cell::RefCell<jit::Jit>,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub struct TypedExpression(pub Type, pub Expression);
-
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum UniverseItem {
     FnDecl {
@@ -27,6 +40,18 @@ pub enum UniverseItem {
         args: Vec<String>,
         body: Vec<Box<Expression>>,
     },
+    VariantDecl {
+        variants: Vec<Variant>,
+    },
+    // a constructor of the VariantDecl named by `ty`
+    Constructor {
+        ty: String,
+        fields: Vec<Type>,
+    },
+    // a user-defined operator, calling the function it is bound to with its operands
+    Operator {
+        function: String,
+    },
 
     // dummy variant used for implementing Default
     None,
@@ -44,6 +69,8 @@ impl<'a> Evaluator<'a> {
             universe: Universe::default(),
             config,
             entries: Vec::new(),
+            #[cfg(feature = "jit")]
+            jit: std::cell::RefCell::new(jit::Jit::new()),
         }
     }
 
@@ -75,6 +102,9 @@ impl<'a> Evaluator<'a> {
             for (file_path, contents) in files {
                 // now, iterate over the contents of the file, sifting the bindings
                 for stmt in contents {
+                    // doc comments aren't kept in the universe
+                    let stmt = stmt.undocumented();
+
                     // match against the statement, checking to see if it fits a set of accepted
                     // bindings
                     match stmt {
@@ -92,7 +122,6 @@ impl<'a> Evaluator<'a> {
                             };
 
                             match body {
-                                Type::FnSig { .. } => cache_entry.1 = Some(body.clone()),
                                 Type::Tag(tag) => {
                                     // TODO(superwhiskers): remove expect
                                     let mut tags = self
@@ -112,7 +141,7 @@ impl<'a> Evaluator<'a> {
                                     // shove it into the cache entry
                                     cache_entry.0 = Some(tags);
                                 }
-                                _ => panic!("unexpected Type kind in type/tag signature"),
+                                _ => cache_entry.1 = Some(body.clone()),
                             }
                         }
                         Statement::FnDecl { name, .. } => {
@@ -129,6 +158,63 @@ impl<'a> Evaluator<'a> {
 
                             cache_entry.2 = Some(stmt.clone());
                         }
+                        Statement::VariantDecl { name, .. } => {
+                            let cache_entry = if let Some(k) = cache.get_mut(name) {
+                                k
+                            } else {
+                                cache.insert(name.clone(), (None, None, None));
+
+                                // TODO(superwhiskers): remove expect
+                                cache.get_mut(name).expect(
+                                    "unable to get a value that was just inserted into a map",
+                                )
+                            };
+
+                            // types don't require a tag signature, so they default to the tags of
+                            // the file they were declared in
+                            if cache_entry.0.is_none() {
+                                // TODO(superwhiskers): remove expect
+                                let mut tags = self
+                                    .config
+                                    .file_tags
+                                    .get(file_path)
+                                    .expect("unable to locate the current file in the file tag map")
+                                    .clone();
+                                tags.push(self.config.project_tag.clone());
+                                cache_entry.0 = Some(tags);
+                            }
+
+                            cache_entry.2 = Some(stmt.clone());
+                        }
+                        // operators are bound under their symbols, taking on the tags of the file
+                        // they were declared in
+                        Statement::OperatorDecl { op, .. } => {
+                            let name = op.to_string();
+                            let cache_entry = if let Some(k) = cache.get_mut(&name) {
+                                k
+                            } else {
+                                cache.insert(name.clone(), (None, None, None));
+
+                                // TODO(superwhiskers): remove expect
+                                cache.get_mut(&name).expect(
+                                    "unable to get a value that was just inserted into a map",
+                                )
+                            };
+
+                            if cache_entry.0.is_none() {
+                                // TODO(superwhiskers): remove expect
+                                let mut tags = self
+                                    .config
+                                    .file_tags
+                                    .get(file_path)
+                                    .expect("unable to locate the current file in the file tag map")
+                                    .clone();
+                                tags.push(self.config.project_tag.clone());
+                                cache_entry.0 = Some(tags);
+                            }
+
+                            cache_entry.2 = Some(stmt.clone());
+                        }
                         _ => panic!("unexpected Statement kind at top level"),
                     }
                 }
@@ -144,11 +230,14 @@ impl<'a> Evaluator<'a> {
                 // match over the Statement kind of it, as that's what the UniverseItem bases the
                 // variant off of
                 println!("binding: {:?}", binding_value);
-                let (universe_item, tags) = match binding_value.2 {
+                let (universe_items, tags) = match binding_value.2 {
                     Some(Statement::FnDecl { args, body, .. }) => {
                         if let Some(sig) = binding_value.1 {
                             if let Some(tags) = binding_value.0 {
-                                (UniverseItem::FnDecl { sig, args, body }, tags)
+                                (
+                                    vec![(binding_name, UniverseItem::FnDecl { sig, args, body })],
+                                    tags,
+                                )
                             } else {
                                 panic!(
                                     "incomplete binding (missing tags) (this should never happen)"
@@ -158,95 +247,629 @@ impl<'a> Evaluator<'a> {
                             panic!("incomplete binding (missing type)");
                         }
                     }
+                    Some(Statement::VariantDecl { variants, .. }) => {
+                        // each constructor is bound alongside the type, sharing its tags
+                        let mut items = Vec::with_capacity(variants.len() + 1);
+                        for variant in &variants {
+                            items.push((
+                                variant.name.clone(),
+                                UniverseItem::Constructor {
+                                    ty: binding_name.clone(),
+                                    fields: variant.fields.clone(),
+                                },
+                            ));
+                        }
+                        items.push((binding_name, UniverseItem::VariantDecl { variants }));
+
+                        // TODO(superwhiskers): remove expect
+                        (
+                            items,
+                            binding_value
+                                .0
+                                .expect("incomplete binding (missing tags) (this should never happen)"),
+                        )
+                    }
+                    Some(Statement::OperatorDecl { function, .. }) => (
+                        vec![(binding_name, UniverseItem::Operator { function })],
+                        // TODO(superwhiskers): remove expect
+                        binding_value
+                            .0
+                            .expect("incomplete binding (missing tags) (this should never happen)"),
+                    ),
                     // TODO(superwhiskers): actually handle this properly
                     s => panic!("incomplete binding (missing valid statement, got {:?})", s),
                 };
 
-                self.entries.push(self.universe.insert(|b| {
-                    b.set_name(Cow::Owned(binding_name))
-                        .set_value(universe_item);
-                    for tag in tags {
-                        b.add_tag(tag);
-                    }
-                    b
-                })?);
+                for (name, universe_item) in universe_items {
+                    self.entries.push(self.universe.insert(|b| {
+                        b.set_name(Cow::Owned(name)).set_value(universe_item);
+                        for tag in &tags {
+                            b.add_tag(tag.clone());
+                        }
+                        b
+                    })?);
+                }
             }
         }
 
+        // now that every binding is known, ensure that each match expression is exhaustive and
+        // that every binding is well-typed
+        let checker = typeck::TypeChecker::new(self.items());
+        for entry in &self.entries {
+            // TODO(superwhiskers): remove expect
+            let (name, item) = self.universe.get(entry.binding).expect("no binding found");
+            if let UniverseItem::FnDecl { body, .. } = item {
+                for expr in body {
+                    self.check_matches(expr)?;
+                }
+            }
+            checker.check_item(name, item)?;
+        }
+
         Ok(())
     }
 
-    // evaluate an expression and return the resulting expression
+    // evaluate an expression and return the resulting value
     pub fn eval_expression(
         &mut self,
-        local_bindings: &mut HashMap<String, TypedExpression>,
+        local_bindings: &mut HashMap<String, Value>,
         expr: &Box<Expression>,
-    ) -> Result<Option<TypedExpression>, FractalError> {
-        Ok(Some(match expr.as_ref() {
-            Expression::Integer(int) => {
-                TypedExpression(Type::Identifier(String::from("ilarge")), Expression::Integer(int.clone()))
-            }
-            Expression::StringLiteral(string) => TypedExpression(
-                Type::Identifier(String::from("string")),
-                Expression::StringLiteral(string.clone()),
-            ),
+    ) -> Result<Value, FractalError> {
+        Ok(match expr.as_ref() {
+            Expression::Integer(int) => Value::Int(int.clone()),
+            Expression::Float(float) => Value::Float(float.0),
+            Expression::StringLiteral(string) => Value::String(String::from(unquote(string))),
+            Expression::Char(c) => Value::Char(*c),
+            // ascriptions, such as those of suffixed integers, only matter to the type checker
+            Expression::TypeDecl { expr, .. } => self.eval_expression(local_bindings, expr)?,
             // if an identifier is passed all the way down, it is retrieved from the local bindings
-            // hashamp
+            // hashamp, falling back to the bindings in the universe
+            //
+            // functions and constructors that take arguments evaluate to a reference to themselves
             //
             // TODO(superwhiskers): remove expect
-            Expression::Identifier(ident) => local_bindings
-                .get(ident)
-                .expect("unable to retrieve the binding from locals")
-                .clone(),
-            Expression::FnCall {
-                name,
-                args,
-            } => {
-                match name.as_str() {
-                    "println" => {
-                        let boxed_arg = Box::new(args.get(0)
-                                    .expect("unable to get the first argument to println").clone());
-                        println!("{}", if args.len() == 0 {
-                            String::from("")
-                        } else {
-                            // TODO(superwhiskers): remove expect
-                            if let TypedExpression(
-                                Type::Identifier(typen),
-                                Expression::StringLiteral(string),
-                            ) = self.eval_expression(
-                                local_bindings,
-                                &boxed_arg,
-                            )?.expect("unable to evaluate to get a string") {
-                                if typen != "string" {
-                                    // TODO(superwhiskers): remove panic
-                                    panic!("expression didn't return string to println");
+            Expression::Identifier(ident) => match local_bindings.get(ident) {
+                Some(value) => value.clone(),
+                None => match self.lookup(ident).cloned() {
+                    Some(UniverseItem::Constructor { fields, .. }) if fields.is_empty() => {
+                        return self.apply(ident, vec![])
+                    }
+                    Some(UniverseItem::FnDecl { args, .. }) if args.is_empty() => {
+                        return self.apply(ident, vec![])
+                    }
+                    Some(UniverseItem::Constructor { .. }) | Some(UniverseItem::FnDecl { .. }) => {
+                        Value::Function(ident.clone())
+                    }
+                    _ => panic!("unable to retrieve the binding from locals"),
+                },
+            },
+            Expression::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.eval_expression(local_bindings, item)?);
+                }
+                Value::List(values)
+            }
+            Expression::OpCall { op, args } => {
+                let (l, r) = match args.as_slice() {
+                    [l, r] => (l, r),
+                    // TODO(superwhiskers): remove panic
+                    _ => panic!("operator calls must have two arguments"),
+                };
+                let l = self.eval_expression(local_bindings, l)?;
+
+                // the right side of `&&` and `||` is only evaluated if it decides the result
+                match (op, l) {
+                    (OpSymbol::DoubleAmpersand, Value::Bool(false)) => Value::Bool(false),
+                    (OpSymbol::DoublePipe, Value::Bool(true)) => Value::Bool(true),
+                    (OpSymbol::DoubleAmpersand, _) | (OpSymbol::DoublePipe, _) => {
+                        self.eval_expression(local_bindings, r)?
+                    }
+                    (op, l) => {
+                        let r = self.eval_expression(local_bindings, r)?;
+                        return self.eval_binary(op, l, r);
+                    }
+                }
+            }
+            Expression::Section { op, l, r } => {
+                let mut operand = |operand: &Option<Box<Expression>>| match operand {
+                    Some(expr) => self
+                        .eval_expression(local_bindings, expr)
+                        .map(|value| Some(Box::new(value))),
+                    None => Ok(None),
+                };
+                Value::Section {
+                    op: op.clone(),
+                    l: operand(l)?,
+                    r: operand(r)?,
+                }
+            }
+            Expression::UnaryOpCall { op, arg } => {
+                let arg = self.eval_expression(local_bindings, arg)?;
+                operators::eval_unary(op, arg)?
+            }
+            Expression::FnCall { name, args } => match name.as_str() {
+                "println" => {
+                    match args.first() {
+                        Some(arg) => {
+                            let value = self.eval_expression(local_bindings, &Box::new(arg.clone()))?;
+                            println!("{}", value);
+                        }
+                        None => println!(),
+                    }
+                    Value::Unit
+                }
+                _ if builtins::LIST_BUILTINS.contains(&name.as_str()) => {
+                    let args = self.eval_args(local_bindings, args)?;
+                    return builtins::eval_list_builtin(name, args, |f, args| {
+                        self.call_value(f, args)
+                    });
+                }
+                _ => {
+                    // locals holding a function reference, a section or a closure are called
+                    // through
+                    let callee = match local_bindings.get(name) {
+                        Some(callee @ Value::Function(_))
+                        | Some(callee @ Value::Section { .. })
+                        | Some(callee @ Value::Closure { .. }) => callee.clone(),
+                        _ => Value::Function(name.clone()),
+                    };
+                    let args = self.eval_args(local_bindings, args)?;
+                    return self.call_value(&callee, args);
+                }
+            },
+            Expression::Apply { callee, args } => {
+                let callee = self.eval_expression(local_bindings, callee)?;
+                let args = self.eval_args(local_bindings, args)?;
+                return self.call_value(&callee, args);
+            }
+            Expression::Match { expr, args } => {
+                let value = self.eval_expression(local_bindings, expr)?;
+
+                for arm in args {
+                    match arm {
+                        Expression::Destructure { pat, body } => {
+                            // arms bind into their own scope, which is discarded afterwards
+                            let mut bindings = local_bindings.clone();
+                            if self.match_pattern(&mut bindings, pat, &value) {
+                                let mut last = Value::Unit;
+                                for expr in body {
+                                    last = self.eval_expression(&mut bindings, expr)?;
                                 }
-                                string
-                            } else {
-                                // TODO(superwhiskers): remove panic
-                                panic!("expression didn't return stringliteral to println");
+                                return Ok(last);
                             }
-                        });
-                        return Ok(None);
+                        }
+                        // TODO(superwhiskers): remove panic
+                        _ => panic!("unexpected match arm: {:?}", arm),
+                    }
+                }
+
+                return Err(FractalError::NoMatchingPattern);
+            }
+            Expression::Lambda { args, body } => Value::Closure {
+                args: args.clone(),
+                body: body.clone(),
+                captures: local_bindings.clone(),
+            },
+            Expression::ValueDecl { assigns, body } => match body {
+                Some(body) => {
+                    let mut bindings = local_bindings.clone();
+                    for assign in assigns {
+                        self.eval_expression(&mut bindings, assign)?;
                     }
-                    _ => panic!("unknown function: {}", name),
+                    return self.eval_block(&mut bindings, body);
                 }
+                // without a body, the bindings are added to the enclosing block
+                None => {
+                    for assign in assigns {
+                        self.eval_expression(local_bindings, assign)?;
+                    }
+                    Value::Unit
+                }
+            },
+            Expression::ValueAssign { pat, expr } => {
+                let value = self.eval_expression(local_bindings, expr)?;
+                if !self.match_pattern(local_bindings, pat, &value) {
+                    return Err(FractalError::NoMatchingPattern);
+                }
+                Value::Unit
             }
             _ => panic!("invalid expression: {:?}", expr),
-        }))
+        })
+    }
+
+    // evaluate the expressions of a block in order, resulting in the value of the last one
+    fn eval_block(
+        &mut self,
+        local_bindings: &mut HashMap<String, Value>,
+        body: &[Box<Expression>],
+    ) -> Result<Value, FractalError> {
+        let mut last = Value::Unit;
+        for expr in body {
+            last = self.eval_expression(local_bindings, expr)?;
+        }
+        Ok(last)
+    }
+
+    // evaluate each argument to a call, in order
+    fn eval_args(
+        &mut self,
+        local_bindings: &mut HashMap<String, Value>,
+        args: &[Expression],
+    ) -> Result<Vec<Value>, FractalError> {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval_expression(local_bindings, &Box::new(arg.clone()))?);
+        }
+        Ok(values)
+    }
+
+    // call a function or constructor by name with already evaluated arguments
+    pub(crate) fn apply(&mut self, name: &str, args: Vec<Value>) -> Result<Value, FractalError> {
+        match self.lookup(name).cloned() {
+            Some(UniverseItem::Constructor { fields, .. }) => {
+                if fields.len() != args.len() {
+                    return Err(FractalError::ConstructorArity {
+                        name: String::from(name),
+                        expected: fields.len(),
+                        found: args.len(),
+                    });
+                }
+
+                Ok(Value::Variant {
+                    name: String::from(name),
+                    fields: args,
+                })
+            }
+            Some(item @ UniverseItem::FnDecl { .. }) => self.call_fn(name, item, args),
+            // TODO(superwhiskers): remove panic
+            _ => panic!("unknown function: {}", name),
+        }
+    }
+
+    // call a function reference, a section or a closure with already evaluated arguments
+    fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, FractalError> {
+        match callee {
+            Value::Function(name) => self.apply(name, args),
+            // the body of a closure sees the locals it captured, along with its arguments
+            Value::Closure {
+                args: arg_names,
+                body,
+                captures,
+            } => {
+                if arg_names.len() != args.len() {
+                    return Err(FractalError::ArgumentCount {
+                        name: callee.to_string(),
+                        expected: arg_names.len(),
+                        found: args.len(),
+                    });
+                }
+
+                let mut bindings = captures.clone();
+                bindings.extend(arg_names.iter().cloned().zip(args));
+                self.eval_block(&mut bindings, body)
+            }
+            // the arguments fill in the missing operands from left to right
+            Value::Section { op, l, r } => {
+                let mut args = args.into_iter();
+                let mut operand = |operand: &Option<Box<Value>>| match operand {
+                    Some(value) => Some(value.as_ref().clone()),
+                    None => args.next(),
+                };
+                match (operand(l), operand(r)) {
+                    (Some(l), Some(r)) => self.eval_binary(op, l, r),
+                    // TODO(superwhiskers): remove panic
+                    _ => panic!("section `{}` called with too few arguments", callee),
+                }
+            }
+            // TODO(superwhiskers): remove panic
+            value => panic!("called a non-function: {:?}", value),
+        }
+    }
+
+    // apply an infix operator to its already evaluated operands, calling the function bound to it
+    // if it is user-defined
+    fn eval_binary(&mut self, op: &OpSymbol, l: Value, r: Value) -> Result<Value, FractalError> {
+        match (op, l, r) {
+            (OpSymbol::Custom(_), l, r) => {
+                let function = self.operator_function(op)?;
+                self.apply(&function, vec![l, r])
+            }
+            // both sides of `&&` and `||` have been evaluated by now, as when called as a section
+            (OpSymbol::DoubleAmpersand, Value::Bool(l), Value::Bool(r)) => Ok(Value::Bool(l && r)),
+            (OpSymbol::DoublePipe, Value::Bool(l), Value::Bool(r)) => Ok(Value::Bool(l || r)),
+            (op, l, r) => operators::eval_binary(op, l, r),
+        }
+    }
+
+    // retrieve the name of the function a user-defined operator is bound to
+    fn operator_function(&self, op: &OpSymbol) -> Result<String, FractalError> {
+        match self.lookup(&op.to_string()) {
+            Some(UniverseItem::Operator { function }) => Ok(function.clone()),
+            _ => Err(FractalError::UnboundName(op.to_string())),
+        }
+    }
+
+    // call a function, running it natively instead if it has been compiled
+    #[cfg_attr(not(feature = "jit"), allow(unused_variables))]
+    fn call_fn(
+        &mut self,
+        name: &str,
+        item: UniverseItem,
+        args: Vec<Value>,
+    ) -> Result<Value, FractalError> {
+        #[cfg(feature = "jit")]
+        {
+            let value = self.jit.borrow_mut().call(name, &args, || self.items());
+            if let Some(value) = value {
+                return Ok(value);
+            }
+        }
+
+        self.eval_fn(item, args)
+    }
+
+    /// the just-in-time compiler used for calls
+    #[cfg(feature = "jit")]
+    pub fn jit(&self) -> std::cell::Ref<'_, jit::Jit> {
+        self.jit.borrow()
+    }
+
+    // check a value against a pattern, adding any bindings it introduces to the provided map
+    fn match_pattern(
+        &self,
+        bindings: &mut HashMap<String, Value>,
+        pat: &Pattern,
+        value: &Value,
+    ) -> bool {
+        match (pat, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Integer(pat), Value::Int(int)) => pat == int,
+            (Pattern::StringLiteral(pat), Value::String(string)) => unquote(pat) == string,
+            (Pattern::Char(pat), Value::Char(c)) => pat == c,
+            // ranges include their start and exclude their end
+            (Pattern::Range { start, end }, Value::Int(int)) => {
+                let after_start = match start.as_deref() {
+                    Some(Pattern::Integer(start)) => int >= start,
+                    None => true,
+                    // TODO(superwhiskers): remove panic
+                    Some(pat) => panic!("unsupported range bound: {:?}", pat),
+                };
+                let before_end = match end.as_deref() {
+                    Some(Pattern::Integer(end)) => int < end,
+                    None => true,
+                    // TODO(superwhiskers): remove panic
+                    Some(pat) => panic!("unsupported range bound: {:?}", pat),
+                };
+                after_start && before_end
+            }
+            (Pattern::Range { start, end }, Value::Char(c)) => {
+                let after_start = match start.as_deref() {
+                    Some(Pattern::Char(start)) => c >= start,
+                    None => true,
+                    // TODO(superwhiskers): remove panic
+                    Some(pat) => panic!("unsupported range bound: {:?}", pat),
+                };
+                let before_end = match end.as_deref() {
+                    Some(Pattern::Char(end)) => c < end,
+                    None => true,
+                    // TODO(superwhiskers): remove panic
+                    Some(pat) => panic!("unsupported range bound: {:?}", pat),
+                };
+                after_start && before_end
+            }
+            (Pattern::Identifier(name), _) => match self.lookup(name) {
+                Some(UniverseItem::Constructor { .. }) => {
+                    matches!(value, Value::Variant { name: ctor, .. } if ctor == name)
+                }
+                _ => {
+                    bindings.insert(name.clone(), value.clone());
+                    true
+                }
+            },
+            (
+                Pattern::Constructor { name, args },
+                Value::Variant {
+                    name: ctor,
+                    fields,
+                },
+            ) if name == ctor => args
+                .iter()
+                .zip(fields)
+                .all(|(pat, value)| self.match_pattern(bindings, pat, value)),
+            _ => false,
+        }
+    }
+
+    // retrieve the value of a binding by its name
+    //
+    // TODO(superwhiskers): resolve names using the tags of the current binding instead of a
+    //                      linear search over every binding
+    fn lookup(&self, name: &str) -> Option<&UniverseItem> {
+        self.entries.iter().find_map(|entry| match self.universe.get(entry.binding) {
+            Some((binding_name, item)) if binding_name == name => Some(item),
+            _ => None,
+        })
+    }
+
+    /// lower every function in the universe to the intermediate representation
+    pub fn lower(&self) -> Result<ir::Program, FractalError> {
+        ir::lower(&self.bindings())
+    }
+
+    /// compile every function in the universe to bytecode
+    pub fn compile(&self) -> Result<bytecode::Program, FractalError> {
+        bytecode::compile(&self.items())
+    }
+
+    /// compile every function in the universe to bytecode, keeping every binding alongside its
+    /// tags so that the result may be stored and loaded later
+    pub fn module(&self) -> Result<bytecode::Module, FractalError> {
+        let program = self.compile()?;
+
+        let mut bindings: Vec<bytecode::Binding> = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let (name, item) = self.universe.get(entry.binding)?;
+                let item = match item {
+                    UniverseItem::FnDecl { .. } => bytecode::Item::Function(
+                        program
+                            .functions
+                            .iter()
+                            .position(|function| function.name == *name)?
+                            as u32,
+                    ),
+                    UniverseItem::VariantDecl { .. } => bytecode::Item::Type,
+                    UniverseItem::Constructor { ty, fields } => bytecode::Item::Constructor {
+                        ty: ty.clone(),
+                        arity: fields.len() as u32,
+                    },
+                    UniverseItem::Operator { function } => bytecode::Item::Operator {
+                        function: function.clone(),
+                    },
+                    UniverseItem::None => bytecode::Item::None,
+                };
+                Some(bytecode::Binding {
+                    name: name.to_string(),
+                    tags: entry.tags.iter().map(bytecode::owned_tag_name).collect(),
+                    item,
+                })
+            })
+            .collect();
+        bindings.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(bytecode::Module { program, bindings })
+    }
+
+    /// retrieve the universe entry of a binding by its name
+    pub fn entry(&self, name: &str) -> Option<&UniverseEntry<'a, UniverseItem>> {
+        self.entries.iter().find(|entry| {
+            matches!(self.universe.get(entry.binding), Some((binding_name, _)) if binding_name == name)
+        })
+    }
+
+    /// retrieve every binding in the universe along with its tags
+    pub fn bindings(&self) -> Vec<(&str, &[TagName<'a>], &UniverseItem)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| {
+                let (name, item) = self.universe.get(entry.binding)?;
+                Some((name.as_ref(), entry.tags.as_slice(), item))
+            })
+            .collect()
+    }
+
+    /// retrieve every binding in the universe by its name
+    pub fn items(&self) -> HashMap<&str, &UniverseItem> {
+        self.entries
+            .iter()
+            .filter_map(|entry| self.universe.get(entry.binding))
+            .map(|(name, item)| (name.as_ref(), item))
+            .collect()
+    }
+
+    // retrieve every constructor (and its arity) of the type the provided constructor belongs to
+    fn siblings(&self, name: &str) -> Option<Vec<(String, usize)>> {
+        match self.lookup(name)? {
+            UniverseItem::Constructor { ty, .. } => match self.lookup(ty)? {
+                UniverseItem::VariantDecl { variants } => Some(
+                    variants
+                        .iter()
+                        .map(|variant| (variant.name.clone(), variant.fields.len()))
+                        .collect(),
+                ),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // walk an expression, checking that every match expression inside of it is exhaustive
+    fn check_matches(&self, expr: &Expression) -> Result<(), FractalError> {
+        match expr {
+            Expression::Match { expr, args } => {
+                self.check_matches(expr)?;
+
+                let mut pats = Vec::with_capacity(args.len());
+                for arm in args {
+                    if let Expression::Destructure { pat, body } = arm {
+                        pats.push(pat);
+                        for expr in body {
+                            self.check_matches(expr)?;
+                        }
+                    }
+                }
+
+                let missing = exhaustiveness::missing_patterns(&pats, |name| self.siblings(name))?;
+                if !missing.is_empty() {
+                    return Err(FractalError::NonExhaustiveMatch(missing));
+                }
+            }
+            Expression::OpCall { args, .. } | Expression::List(args) => {
+                for arg in args {
+                    self.check_matches(arg)?;
+                }
+            }
+            Expression::FnCall { args, .. } => {
+                for arg in args {
+                    self.check_matches(arg)?;
+                }
+            }
+            Expression::Apply { callee, args } => {
+                self.check_matches(callee)?;
+                for arg in args {
+                    self.check_matches(arg)?;
+                }
+            }
+            Expression::Lambda { body, .. } => {
+                for expr in body {
+                    self.check_matches(expr)?;
+                }
+            }
+            Expression::Destructure { body, .. } => {
+                for expr in body {
+                    self.check_matches(expr)?;
+                }
+            }
+            Expression::ValueDecl { assigns, body } => {
+                for assign in assigns {
+                    self.check_matches(assign)?;
+                }
+                for expr in body.iter().flatten() {
+                    self.check_matches(expr)?;
+                }
+            }
+            Expression::Section { l, r, .. } => {
+                for operand in l.iter().chain(r) {
+                    self.check_matches(operand)?;
+                }
+            }
+            Expression::ValueAssign { expr, .. }
+            | Expression::TypeDecl { expr, .. }
+            | Expression::UnaryOpCall { arg: expr, .. } => self.check_matches(expr)?,
+            Expression::TagAssign { .. }
+            | Expression::Integer(_)
+            | Expression::Float(_)
+            | Expression::Identifier(_)
+            | Expression::StringLiteral(_)
+            | Expression::Char(_) => (),
+        }
+
+        Ok(())
     }
 
     // evaluate a UniverseItem::FnDecl and return the resulting expression
     pub fn eval_fn(
         &mut self,
         item: UniverseItem,
-        args: Vec<TypedExpression>,
-    ) -> Result<Option<TypedExpression>, FractalError> {
+        args: Vec<Value>,
+    ) -> Result<Value, FractalError> {
         match item {
             UniverseItem::FnDecl {
-                sig,
                 args: arg_names,
                 body,
+                ..
             } => {
                 // create a new binding set
                 let mut bindings = HashMap::new();
@@ -266,36 +889,42 @@ impl<'a> Evaluator<'a> {
                     );
                 }
 
-                let mut last = Ok(None);
+                let mut last = Value::Unit;
                 for expr in &body {
-                    last = self.eval_expression(&mut bindings, expr);
+                    last = self.eval_expression(&mut bindings, expr)?;
                 }
 
-                last
+                Ok(last)
             }
             // TODO(superwhiskers): remove panic
             _ => panic!("not a function: {:?}", item),
         }
     }
 
-    // evaluate a universe entry and return the resutling expression
+    // evaluate a universe entry and return the resutling value
     pub fn eval(
         &mut self,
         entry: &UniverseEntry<'a, UniverseItem>,
-        args: Vec<TypedExpression>,
-    ) -> Result<Option<TypedExpression>, FractalError> {
+        args: Vec<Value>,
+    ) -> Result<Value, FractalError> {
         // TODO(superwhiskers): remove expect
-        self.eval_fn(
-            self.universe
-                .get(entry.binding)
-                .expect("no binding found")
-                .1
-                .clone(),
-            args,
-        )
+        let (name, item) = self
+            .universe
+            .get(entry.binding)
+            .expect("no binding found");
+        let (name, item) = (name.to_string(), item.clone());
+        self.call_fn(&name, item, args)
     }
 }
 
+/// strips the quotes the lexer leaves around string literals
+pub fn unquote(string: &str) -> &str {
+    string
+        .strip_prefix('"')
+        .and_then(|string| string.strip_suffix('"'))
+        .unwrap_or(string)
+}
+
 /// helper recursive function used to flatten a tag OpCall into an array of TagNames
 pub fn flatten_tag_opcall_to_tagnames<'a>(names: &mut Vec<TagName<'a>>, tag: &Tag) {
     match tag {
@@ -320,4 +949,1061 @@ pub fn flatten_tag_opcall_to_tagnames<'a>(names: &mut Vec<TagName<'a>>, tag: &Ta
 pub enum FractalError {
     #[error("An error was encountered while using the tag library")]
     UniverseError(#[from] UniverseError),
+
+    #[error("Non-exhaustive match, missing cases: {}", display_patterns(.0))]
+    NonExhaustiveMatch(Vec<Pattern>),
+
+    #[error("No pattern matched the value")]
+    NoMatchingPattern,
+
+    #[error("The constructor `{0}` does not exist")]
+    UnknownConstructor(String),
+
+    #[error("The constructor `{name}` takes {expected} argument(s), but {found} were provided")]
+    ConstructorArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Expected a value of type `{expected}`, found one of type `{found}`")]
+    TypeMismatch { expected: Type, found: Type },
+
+    #[error("Expected a list of length {expected}, found one of length {found}")]
+    LengthMismatch { expected: BigInt, found: BigInt },
+
+    #[error("The integer {value} does not fit inside of `{ty}`")]
+    IntegerOutOfRange { value: BigInt, ty: Type },
+
+    #[error("The float {value} does not fit inside of `{ty}`")]
+    FloatOutOfRange { value: f64, ty: Type },
+
+    #[error("Invalid type `{0}`: {1}")]
+    InvalidType(Type, &'static str),
+
+    #[error("The type `{0}` does not exist")]
+    UnknownType(String),
+
+    #[error("No binding named `{0}` is in scope")]
+    UnboundName(String),
+
+    #[error("`{0}` is not a function")]
+    NotAFunction(String),
+
+    #[error("`{name}` takes {expected} argument(s), but {found} were provided")]
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("Index {index} is out of bounds for a list of length {length}")]
+    IndexOutOfBounds { index: BigInt, length: usize },
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Unable to shift by {0} bits")]
+    InvalidShift(BigInt),
+
+    // only reachable by running code that was never type checked, such as a crafted module
+    #[error("Invalid operation on the values of an unchecked program: {0}")]
+    InvalidValues(String),
+
+    #[error("An error was encountered while loading bytecode: {0}")]
+    Bytecode(#[from] bytecode::BytecodeError),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+}
+
+fn display_patterns(pats: &[Pattern]) -> String {
+    pats.iter()
+        .map(|pat| format!("`{}`", pat))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use indoc::indoc;
+    use parser::lexer;
+
+    fn populate(source: &str) -> Result<Evaluator<'static>, FractalError> {
+        let mut file_tags = HashMap::new();
+        file_tags.insert(String::from("test.sf"), Vec::new());
+
+        let mut evaluator = Evaluator::new(EvaluatorConfig {
+            project_tag: TagName::Primary(Cow::Borrowed("test")),
+            file_tags,
+        });
+
+        let mut files = HashMap::new();
+        files.insert(
+            String::from("test.sf"),
+            parser::parse(lexer::lex(source)).unwrap(),
+        );
+        evaluator.populate(&files)?;
+
+        Ok(evaluator)
+    }
+
+    fn call(
+        evaluator: &mut Evaluator,
+        name: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, FractalError> {
+        let item = evaluator.lookup(name).expect("no such binding").clone();
+        evaluator.eval_fn(item, args)
+    }
+
+    fn int(i: isize) -> Value {
+        Value::Int(BigInt::from(i))
+    }
+
+    #[test]
+    fn test_variant_match() {
+        let mut evaluator = populate(indoc! {"
+            type shape = circle ilarge | rect ilarge ilarge | empty
+            size :: tag nothing
+            size :: ilarge -> shape
+            size n => match n =>
+              0 => empty
+              1 => circle 1
+              _ => rect n 2
+
+
+            height :: tag nothing
+            height :: shape -> ilarge
+            height s => match s =>
+              circle r => r
+              rect _ h => h
+              empty => 0
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => height (size a)
+        "})
+        .unwrap();
+
+        assert_eq!(call(&mut evaluator, "main", vec![int(0)]).unwrap(), int(0));
+        assert_eq!(call(&mut evaluator, "main", vec![int(1)]).unwrap(), int(1));
+        assert_eq!(call(&mut evaluator, "main", vec![int(5)]).unwrap(), int(2));
+        assert_eq!(
+            call(&mut evaluator, "size", vec![int(5)]).unwrap(),
+            Value::Variant {
+                name: "rect".into(),
+                fields: vec![int(5), int(2)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_non_exhaustive_match() {
+        let result = populate(indoc! {"
+            type option = some ilarge | none
+            unwrap :: tag nothing
+            unwrap :: option -> ilarge
+            unwrap o => match o =>
+              some (x) => x
+        "});
+
+        match result {
+            Err(FractalError::NonExhaustiveMatch(missing)) => {
+                assert_eq!(missing, vec![Pattern::Constructor { name: "none".into(), args: vec![] }])
+            }
+            _ => panic!("expected a non-exhaustive match error"),
+        }
+    }
+
+    #[test]
+    fn test_nat_types() {
+        let evaluator = populate(indoc! {"
+            pair :: tag nothing
+            pair :: (int 8) -> list 2 (int 8)
+            pair n => [n, n + 1]
+
+
+            widen :: tag nothing
+            widen :: (list 2 (int 8)) -> list 2 (int 8)
+            widen xs => xs
+        "});
+        assert!(evaluator.is_ok());
+
+        let result = populate(indoc! {"
+            triple :: tag nothing
+            triple :: (int 8) -> list 3 (int 8)
+            triple n => [n, n]
+        "});
+        match result {
+            Err(FractalError::LengthMismatch { expected, found }) => {
+                assert_eq!(expected, BigInt::from(3));
+                assert_eq!(found, BigInt::from(2));
+            }
+            _ => panic!("expected a length mismatch"),
+        }
+
+        let result = populate(indoc! {"
+            big :: tag nothing
+            big :: int 8
+            big => 128
+        "});
+        assert!(matches!(result, Err(FractalError::IntegerOutOfRange { .. })));
+
+        let result = populate(indoc! {"
+            byte :: tag nothing
+            byte :: uint 8
+            byte => 0x100u8
+        "});
+        assert!(matches!(result, Err(FractalError::IntegerOutOfRange { .. })));
+
+        let result = populate(indoc! {"
+            byte :: tag nothing
+            byte :: uint 8
+            byte => 0xffi8
+        "});
+        assert!(matches!(result, Err(FractalError::IntegerOutOfRange { .. })));
+
+        let result = populate(indoc! {"
+            signed :: tag nothing
+            signed :: int 8
+            signed => 0x7fu8
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+
+        let result = populate(indoc! {"
+            narrow :: tag nothing
+            narrow :: (int 16) -> int 8
+            narrow n => n
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let mut evaluator = populate(indoc! {"
+            mask :: tag nothing
+            mask :: (uint 8) -> uint 8
+            mask b => b + 0x0fu8
+
+
+            next :: tag nothing
+            next :: (int 8) -> int 8
+            next n => n + 1
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => a + 0b1010 + 0o17 + 1_000
+        "})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        // widths are only checked against literals, so arithmetic may leave the range of a type
+        for (function, arg, expected) in &[
+            ("mask", 16, 31),
+            ("next", 127, 128),
+            ("main", 1, 1026),
+        ] {
+            assert_eq!(
+                call(&mut evaluator, function, vec![int(*arg)]).unwrap(),
+                int(*expected)
+            );
+            assert_eq!(vm.call(function, vec![int(*arg)]).unwrap(), int(*expected));
+        }
+    }
+
+    #[test]
+    fn test_float_literals() {
+        let mut evaluator = populate(indoc! {"
+            scale :: tag nothing
+            scale :: ilarge -> f32
+            scale n => match n =>
+              0 => 0.5
+              _ => 1.5e3f32
+
+
+            exact :: tag nothing
+            exact :: f64 -> bool
+            exact x => x == 0.25
+        "})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        for (function, arg, expected) in &[
+            ("scale", int(0), Value::Float(0.5)),
+            ("scale", int(1), Value::Float(1500.0)),
+            ("exact", Value::Float(0.25), true.into()),
+        ] {
+            assert_eq!(
+                &call(&mut evaluator, function, vec![arg.clone()]).unwrap(),
+                expected
+            );
+            assert_eq!(&vm.call(function, vec![arg.clone()]).unwrap(), expected);
+        }
+
+        let result = populate(indoc! {"
+            huge :: tag nothing
+            huge :: ilarge -> f32
+            huge n => 1e39
+        "});
+        assert!(matches!(result, Err(FractalError::FloatOutOfRange { .. })));
+
+        let result = populate(indoc! {"
+            mixed :: tag nothing
+            mixed :: ilarge -> f64
+            mixed n => 1.0f32
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_char_literals() {
+        let mut evaluator = populate(indoc! {r#"
+            classify :: tag nothing
+            classify :: char -> ilarge
+            classify c => match c =>
+              '\n' => 0
+              'a'..'z' => 1
+              'z' => 2
+              '0'..':' => 3
+              _ => 4
+
+
+            byte :: tag nothing
+            byte :: (uint 8) -> ilarge
+            byte b => match b =>
+              b'x' => 1
+              _ => 0
+
+
+            greeting :: tag nothing
+            greeting :: list 2 (uint 8)
+            greeting => b"hi"
+        "#})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        for (c, expected) in &[('\n', 0), ('a', 1), ('y', 1), ('z', 2), ('7', 3), ('λ', 4)] {
+            assert_eq!(
+                call(&mut evaluator, "classify", vec![Value::Char(*c)]).unwrap(),
+                int(*expected)
+            );
+            assert_eq!(
+                vm.call("classify", vec![Value::Char(*c)]).unwrap(),
+                int(*expected)
+            );
+        }
+        for (b, expected) in &[(b'x', 1), (b'y', 0)] {
+            let b = int(*b as isize);
+            assert_eq!(call(&mut evaluator, "byte", vec![b.clone()]).unwrap(), int(*expected));
+            assert_eq!(vm.call("byte", vec![b]).unwrap(), int(*expected));
+        }
+        assert_eq!(
+            call(&mut evaluator, "greeting", vec![]).unwrap(),
+            Value::List(vec![int(104), int(105)])
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut evaluator = populate(indoc! {r#"
+            rem :: tag nothing
+            rem :: ilarge -> ilarge
+            rem x => -12 % x
+
+
+            divides :: tag nothing
+            divides :: ilarge -> bool
+            divides x => x == 0 || 12 % x == 0 && x != 5
+
+
+            bits :: tag nothing
+            bits :: ilarge -> ilarge
+            bits x => (x << 2 | 1) ^ 3 & !0 >> 1
+
+
+            lowest :: tag nothing
+            lowest :: int 8
+            lowest => -128
+
+
+            between :: tag nothing
+            between :: ilarge -> bool
+            between x => !(x <= 0) && -x >= -9
+        "#})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        let cases: &[(&str, Vec<Value>, Value)] = &[
+            ("rem", vec![int(7)], int(-5)),
+            ("divides", vec![int(0)], true.into()),
+            ("divides", vec![int(4)], true.into()),
+            ("divides", vec![int(5)], false.into()),
+            ("divides", vec![int(7)], false.into()),
+            ("bits", vec![int(5)], int(22)),
+            ("lowest", vec![], int(-128)),
+            ("between", vec![int(9)], true.into()),
+            ("between", vec![int(0)], false.into()),
+            ("between", vec![int(10)], false.into()),
+        ];
+        for (name, args, expected) in cases {
+            assert_eq!(call(&mut evaluator, name, args.clone()).unwrap(), *expected);
+            assert_eq!(vm.call(name, args.clone()).unwrap(), *expected);
+        }
+
+        assert!(matches!(
+            call(&mut evaluator, "rem", vec![int(0)]),
+            Err(FractalError::DivisionByZero)
+        ));
+        assert!(matches!(
+            populate(indoc! {r#"
+                tooLow :: tag nothing
+                tooLow :: int 8
+                tooLow => -129
+            "#}),
+            Err(FractalError::IntegerOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_user_operators() {
+        let mut evaluator = populate(indoc! {r#"
+            mean :: tag nothing
+            mean :: ilarge -> ilarge
+            mean x => x <+> 10 <+> 4
+
+
+            infixl 6 <+> = average
+
+
+            average :: tag nothing
+            average :: ilarge ilarge -> ilarge
+            average a b => (a + b) / 2
+        "#})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+        assert_eq!(call(&mut evaluator, "mean", vec![int(6)]).unwrap(), int(6));
+        assert_eq!(vm.call("mean", vec![int(6)]).unwrap(), int(6));
+
+        assert!(matches!(
+            populate(indoc! {r#"
+                infixl 6 <+> = missing
+            "#}),
+            Err(FractalError::UnboundName(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn test_sections() {
+        let mut evaluator = populate(indoc! {r#"
+            scale :: tag nothing
+            scale :: ilarge -> list 3 ilarge
+            scale x => map [1, 2, 3] (* x)
+
+
+            small :: tag nothing
+            small :: (list 3 (int 8)) -> list 3 bool
+            small xs => map xs (< 2)
+
+
+            total :: tag nothing
+            total :: (list 3 ilarge) -> ilarge
+            total xs => fold xs 0 (+)
+
+
+            averages :: tag nothing
+            averages :: (list 2 ilarge) -> list 2 ilarge
+            averages xs => map xs (10 <+>)
+
+
+            infixl 6 <+> = average
+
+
+            average :: tag nothing
+            average :: ilarge ilarge -> ilarge
+            average a b => (a + b) / 2
+        "#})
+        .unwrap();
+
+        assert_eq!(
+            call(&mut evaluator, "scale", vec![int(3)]).unwrap(),
+            Value::List(vec![int(3), int(6), int(9)])
+        );
+        assert_eq!(
+            call(&mut evaluator, "small", vec![Value::List(vec![int(1), int(2), int(-4)])])
+                .unwrap(),
+            Value::List(vec![true.into(), false.into(), true.into()])
+        );
+        assert_eq!(
+            call(&mut evaluator, "total", vec![Value::List(vec![int(1), int(2), int(3)])])
+                .unwrap(),
+            int(6)
+        );
+        assert_eq!(
+            call(&mut evaluator, "averages", vec![Value::List(vec![int(2), int(20)])]).unwrap(),
+            Value::List(vec![int(6), int(15)])
+        );
+
+        // compiled sections are lifted into functions that are passed the operands they were
+        // given
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+        for (function, arg) in &[
+            ("scale", int(3)),
+            ("small", Value::List(vec![int(1), int(2), int(-4)])),
+            ("total", Value::List(vec![int(1), int(2), int(3)])),
+            ("averages", Value::List(vec![int(2), int(20)])),
+        ] {
+            assert_eq!(
+                vm.call(function, vec![arg.clone()]).unwrap(),
+                call(&mut evaluator, function, vec![arg.clone()]).unwrap(),
+                "{} {}",
+                function,
+                arg
+            );
+        }
+    }
+
+    #[test]
+    fn test_application() {
+        let mut evaluator = populate(indoc! {"
+            pick :: tag nothing
+            pick :: ilarge -> (ilarge -> ilarge)
+            pick n => match n =>
+              0 => double
+              _ => negate
+
+
+            double :: tag nothing
+            double :: ilarge -> ilarge
+            double x => x * 2
+
+
+            negate :: tag nothing
+            negate :: ilarge -> ilarge
+            negate x => -x
+
+
+            picked :: tag nothing
+            picked :: ilarge ilarge -> ilarge
+            picked n x => (pick n) x + 1
+
+
+            applied :: tag nothing
+            applied :: ilarge -> ilarge
+            applied x => negate $ double $ x - 1
+
+
+            piped :: tag nothing
+            piped :: ilarge -> ilarge
+            piped x => x - 1 |> double |> pick 1
+        "})
+        .unwrap();
+
+        assert_eq!(call(&mut evaluator, "picked", vec![int(0), int(4)]).unwrap(), int(9));
+        assert_eq!(call(&mut evaluator, "picked", vec![int(1), int(4)]).unwrap(), int(-3));
+        assert_eq!(call(&mut evaluator, "applied", vec![int(4)]).unwrap(), int(-6));
+        assert_eq!(call(&mut evaluator, "piped", vec![int(4)]).unwrap(), int(-6));
+
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+        assert_eq!(vm.call("picked", vec![int(0), int(4)]).unwrap(), int(9));
+        assert_eq!(vm.call("piped", vec![int(4)]).unwrap(), int(-6));
+
+        let mut evaluator = populate(indoc! {"
+            sectioned :: tag nothing
+            sectioned :: ilarge -> ilarge
+            sectioned x => x |> (* 3)
+        "})
+        .unwrap();
+        assert_eq!(call(&mut evaluator, "sectioned", vec![int(4)]).unwrap(), int(12));
+        let program = evaluator.compile().unwrap();
+        assert_eq!(
+            bytecode::Vm::new(&program).call("sectioned", vec![int(4)]).unwrap(),
+            int(12)
+        );
+
+        // applying anything but a function is caught before running
+        assert!(matches!(
+            populate(indoc! {"
+                main :: tag nothing
+                main :: ilarge -> ilarge
+                main x => (x + 1) x
+            "}),
+            Err(FractalError::NotAFunction(_))
+        ));
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut evaluator = populate(indoc! {r"
+            adder :: tag nothing
+            adder :: ilarge -> (ilarge -> ilarge)
+            adder n => \x => x + n
+
+
+            twice :: tag nothing
+            twice :: (ilarge -> ilarge) ilarge -> ilarge
+            twice f x => f (f x)
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => twice (adder a) 1 + twice (\x => x * 2) a
+
+
+            scaled :: tag nothing
+            scaled :: ilarge -> list 3 ilarge
+            scaled factor =>
+              let offset = factor + 1
+              map [1, 2, 3] (\x => x * factor + offset)
+
+
+            total :: tag nothing
+            total :: (list 3 ilarge) -> ilarge
+            total xs => fold xs 0 (\sum x => sum + x)
+
+
+            captured :: tag nothing
+            captured :: ilarge -> ilarge
+            captured x => let y = x * 2 in (\z => z + y) 1
+
+
+            nested :: tag nothing
+            nested :: ilarge -> ilarge
+            nested x => (\y => (\z => x * y + z) 1) 2
+        "})
+        .unwrap();
+
+        assert_eq!(call(&mut evaluator, "main", vec![int(3)]).unwrap(), int(19));
+        assert_eq!(
+            call(&mut evaluator, "scaled", vec![int(2)]).unwrap(),
+            Value::List(vec![int(5), int(7), int(9)])
+        );
+        assert_eq!(
+            call(&mut evaluator, "total", vec![Value::List(vec![int(1), int(2), int(3)])])
+                .unwrap(),
+            int(6)
+        );
+        assert_eq!(call(&mut evaluator, "captured", vec![int(5)]).unwrap(), int(11));
+
+        // compiled lambdas are lifted into functions that are passed the locals they capture
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+        for (function, arg) in &[
+            ("main", int(3)),
+            ("scaled", int(2)),
+            ("total", Value::List(vec![int(1), int(2), int(3)])),
+            ("captured", int(5)),
+            ("nested", int(5)),
+        ] {
+            assert_eq!(
+                vm.call(function, vec![arg.clone()]).unwrap(),
+                call(&mut evaluator, function, vec![arg.clone()]).unwrap(),
+                "{} {}",
+                function,
+                arg
+            );
+        }
+        assert_eq!(
+            vm.call("adder", vec![int(1)]).unwrap(),
+            Value::Partial {
+                function: "adder#0".into(),
+                args: vec![int(1)],
+            }
+        );
+
+        assert!(matches!(
+            populate(indoc! {r"
+                main :: tag nothing
+                main :: ilarge -> ilarge
+                main x => (\y => y + 1) x x
+            "}),
+            Err(FractalError::ArgumentCount { .. })
+        ));
+        assert!(matches!(
+            populate(indoc! {r#"
+                main :: tag nothing
+                main :: ilarge -> ilarge
+                main x => twice (\y => "y") x
+
+
+                twice :: tag nothing
+                twice :: (ilarge -> ilarge) ilarge -> ilarge
+                twice f x => f (f x)
+            "#}),
+            Err(FractalError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_generic_signatures() {
+        let mut evaluator = populate(indoc! {"
+            id :: tag nothing
+            id :: 'a -> 'a
+            id x => x
+
+
+            first :: tag nothing
+            first :: 'a 'b -> 'a
+            first x y => x
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => first (id a) (id \"unused\")
+        "})
+        .unwrap();
+
+        assert_eq!(call(&mut evaluator, "main", vec![int(4)]).unwrap(), int(4));
+
+        assert_eq!(
+            call(&mut evaluator, "id", vec![Value::from("hi")]).unwrap(),
+            Value::from("hi")
+        );
+
+        // type variables stand for any type inside of the function, so nothing specific can be
+        // done with them
+        let result = populate(indoc! {"
+            succ :: tag nothing
+            succ :: 'a -> 'a
+            succ x => x + 1
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+
+        let result = populate(indoc! {"
+            coerce :: tag nothing
+            coerce :: 'a -> 'b
+            coerce x => x
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+
+        let result = populate(indoc! {"
+            id :: tag nothing
+            id :: 'a -> 'a
+            id x => x
+
+
+            show :: tag nothing
+            show :: ilarge -> string
+            show n => id n
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_list_builtins() {
+        let mut evaluator = populate(indoc! {"
+            double :: tag nothing
+            double :: ilarge -> ilarge
+            double n => n * 2
+
+
+            add :: tag nothing
+            add :: ilarge ilarge -> ilarge
+            add a b => a + b
+
+
+            sum :: tag nothing
+            sum :: (list 'n ilarge) -> ilarge
+            sum xs => fold xs 0 add
+
+
+            pairs :: tag nothing
+            pairs :: ilarge -> list 4 ilarge
+            pairs n => concat (push [n] 1) [2, 3]
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => sum (map (pairs a) double)
+
+
+            second :: tag nothing
+            second :: (list 'n ilarge) -> ilarge
+            second xs => get xs 1
+
+
+            count :: tag nothing
+            count :: ilarge -> ilarge
+            count n => len (push [] n)
+        "})
+        .unwrap();
+
+        assert_eq!(call(&mut evaluator, "main", vec![int(4)]).unwrap(), int(20));
+        assert_eq!(call(&mut evaluator, "count", vec![int(4)]).unwrap(), int(1));
+
+        let list = Value::List(vec![int(7), int(8)]);
+        assert_eq!(
+            call(&mut evaluator, "second", vec![list]).unwrap(),
+            int(8)
+        );
+        let list = Value::List(vec![int(7)]);
+        assert!(matches!(
+            call(&mut evaluator, "second", vec![list]),
+            Err(FractalError::IndexOutOfBounds { length: 1, .. })
+        ));
+
+        // the lengths of pushed and concatenated lists are known statically
+        let result = populate(indoc! {"
+            pairs :: tag nothing
+            pairs :: ilarge -> list 3 ilarge
+            pairs n => concat (push [n] 1) [2, 3]
+        "});
+        assert!(matches!(result, Err(FractalError::LengthMismatch { .. })));
+
+        let result = populate(indoc! {"
+            shout :: tag nothing
+            shout :: string -> string
+            shout s => s
+
+
+            main :: tag nothing
+            main :: ilarge -> list 2 string
+            main a => map [1, 2] shout
+        "});
+        assert!(matches!(result, Err(FractalError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_bytecode_matches_tree_walker() {
+        let mut evaluator = populate(indoc! {"
+            type shape = circle ilarge | rect ilarge ilarge | empty
+            size :: tag nothing
+            size :: ilarge -> shape
+            size n => match n =>
+              0 => empty
+              1..10 => circle n
+              _ => rect n 2
+
+
+            area :: tag nothing
+            area :: shape -> ilarge
+            area s => match s =>
+              circle r => 3 * r * r
+              rect w h => w * h
+              empty => 0
+
+
+            double :: tag nothing
+            double :: ilarge -> ilarge
+            double n => n * 2
+
+
+            add :: tag nothing
+            add :: ilarge ilarge -> ilarge
+            add a b => a + b
+
+
+            shapes :: tag nothing
+            shapes :: ilarge -> list 3 shape
+            shapes n => map [n, n + 1, n + 20] size
+
+
+            main :: tag nothing
+            main :: ilarge -> ilarge
+            main a => fold (map (map (shapes a) area) double) (get [a, 1] 1) add
+
+
+            name :: tag nothing
+            name :: string -> ilarge
+            name s => match s =>
+              \"bar\" => 1
+              _ => 0
+        "})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        for a in &[0, 1, 5, 9, 10, 30] {
+            for (function, arg) in &[("main", int(*a)), ("size", int(*a)), ("shapes", int(*a))] {
+                assert_eq!(
+                    vm.call(function, vec![arg.clone()]).unwrap(),
+                    call(&mut evaluator, function, vec![arg.clone()]).unwrap(),
+                    "{} {}",
+                    function,
+                    arg
+                );
+            }
+        }
+        for s in &["bar", "baz"] {
+            assert_eq!(
+                vm.call("name", vec![Value::from(*s)]).unwrap(),
+                call(&mut evaluator, "name", vec![Value::from(*s)]).unwrap()
+            );
+        }
+
+        // compiled code passes verification and survives a trip through the on-disk format
+        let module = evaluator.module().unwrap();
+        let loaded = bytecode::load(&module.encode().unwrap()).unwrap();
+        assert_eq!(loaded, module);
+        assert_eq!(loaded.program, program);
+
+        let (_, entries) = loaded.universe().unwrap();
+        assert_eq!(entries.len(), evaluator.entries.len());
+        assert!(matches!(
+            loaded.bindings.iter().find(|binding| binding.name == "rect"),
+            Some(bytecode::Binding {
+                item: bytecode::Item::Constructor { arity: 2, .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_let_bindings() {
+        let mut evaluator = populate(indoc! {"
+            scaled :: tag nothing
+            scaled :: ilarge -> ilarge
+            scaled x =>
+              let y = x * 2
+              let z = y + 1
+              y * z
+
+
+            nested :: tag nothing
+            nested :: ilarge -> ilarge
+            nested x => let y = x + 1, z = y * y in z - (let y = 2 in y)
+
+
+            shadowed :: tag nothing
+            shadowed :: ilarge -> ilarge
+            shadowed x => (let x = 10 in x) + x
+
+
+            zero :: tag nothing
+            zero :: ilarge -> ilarge
+            zero n =>
+              let 0 = n
+              n + 1
+        "})
+        .unwrap();
+        let program = evaluator.compile().unwrap();
+        let vm = bytecode::Vm::new(&program);
+
+        for (function, arg) in &[
+            ("scaled", 3),
+            ("nested", 2),
+            ("shadowed", 1),
+            ("zero", 0),
+        ] {
+            assert_eq!(
+                vm.call(function, vec![int(*arg)]).unwrap(),
+                call(&mut evaluator, function, vec![int(*arg)]).unwrap(),
+                "{} {}",
+                function,
+                arg
+            );
+        }
+
+        // bindings whose pattern doesn't match fail on both engines
+        assert!(matches!(
+            call(&mut evaluator, "zero", vec![int(1)]),
+            Err(FractalError::NoMatchingPattern)
+        ));
+        assert!(matches!(
+            vm.call("zero", vec![int(1)]),
+            Err(FractalError::NoMatchingPattern)
+        ));
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn test_jit() {
+        let mut evaluator = populate(indoc! {"
+            fib :: tag nothing
+            fib :: ilarge -> ilarge
+            fib n => match n =>
+              0..2 => n
+              _ => (fib (n - 1)) + (fib (n - 2))
+
+
+            square :: tag nothing
+            square :: ilarge -> ilarge
+            square n => n * n
+
+
+            inverse :: tag nothing
+            inverse :: ilarge -> ilarge
+            inverse n => 100 / n
+
+
+            type shape = circle ilarge | empty
+            size :: tag nothing
+            size :: ilarge -> shape
+            size n => match n =>
+              0 => empty
+              _ => circle n
+
+
+            radius :: tag nothing
+            radius :: ilarge -> ilarge
+            radius n => match size n =>
+              circle r => r
+              empty => 0
+
+
+            count :: tag nothing
+            count :: ilarge -> ilarge
+            count n => match n =>
+              0 => 0
+              _ => len [n, n]
+        "})
+        .unwrap();
+        let big = |s: &str| Value::Int(s.parse().unwrap());
+
+        // fib calls itself often enough to be compiled while it is being interpreted
+        assert_eq!(evaluator.apply("fib", vec![int(25)]).unwrap(), int(75025));
+        assert!(evaluator.jit().is_compiled("fib"));
+        assert_eq!(evaluator.jit().compiled(), 1);
+        assert_eq!(evaluator.apply("fib", vec![int(30)]).unwrap(), int(832040));
+
+        for n in 0..10 {
+            assert_eq!(evaluator.apply("square", vec![int(n)]).unwrap(), int(n * n));
+            assert_eq!(
+                evaluator.apply("inverse", vec![int(n + 1)]).unwrap(),
+                int(100 / (n + 1))
+            );
+        }
+        assert!(evaluator.jit().is_compiled("square"));
+        assert!(evaluator.jit().is_compiled("inverse"));
+
+        // anything that doesn't fit inside of a machine integer is left to the interpreter, as
+        // are errors
+        assert_eq!(
+            evaluator.apply("square", vec![big("10000000000")]).unwrap(),
+            big("100000000000000000000")
+        );
+        assert_eq!(
+            evaluator
+                .apply("square", vec![big("100000000000000000000")])
+                .unwrap(),
+            big("10000000000000000000000000000000000000000")
+        );
+        assert!(matches!(
+            evaluator.apply("inverse", vec![int(0)]),
+            Err(FractalError::DivisionByZero)
+        ));
+
+        // functions using anything but integers are never compiled
+        for n in 0..10 {
+            assert_eq!(evaluator.apply("radius", vec![int(n)]).unwrap(), int(n));
+            assert_eq!(
+                evaluator.apply("count", vec![int(n)]).unwrap(),
+                int(if n == 0 { 0 } else { 2 })
+            );
+        }
+        assert!(!evaluator.jit().is_compiled("radius"));
+        assert!(!evaluator.jit().is_compiled("count"));
+        assert!(!evaluator.jit().is_compiled("size"));
+        assert_eq!(evaluator.jit().compiled(), 3);
+    }
 }
@@ -0,0 +1,137 @@
+// runtime implementations of the operators, shared by the tree walker and the bytecode vm
+//
+// `&&` and `||` aren't found here, as they only evaluate their right side when needed
+
+use crate::{FractalError, Value};
+use num_bigint::BigInt;
+use parser::ast::{OpSymbol, UnaryOpSymbol};
+use std::convert::TryFrom;
+
+/// applies an infix operator to its already evaluated operands
+pub(crate) fn eval_binary(op: &OpSymbol, l: Value, r: Value) -> Result<Value, FractalError> {
+    match op {
+        OpSymbol::DoubleEqual => return Ok(Value::Bool(l == r)),
+        OpSymbol::BangEqual => return Ok(Value::Bool(l != r)),
+        _ => (),
+    }
+
+    let (l, r) = match (l, r) {
+        (Value::Int(l), Value::Int(r)) => (l, r),
+        (l, r) => {
+            return Err(FractalError::InvalidValues(format!(
+                "`{}` applied to {:?} and {:?}",
+                op, l, r
+            )))
+        }
+    };
+    Ok(match op {
+        OpSymbol::Plus => Value::Int(l + r),
+        OpSymbol::Minus => Value::Int(l - r),
+        OpSymbol::Star => Value::Int(l * r),
+        OpSymbol::ForwardSlash | OpSymbol::Percent if r == BigInt::from(0) => {
+            return Err(FractalError::DivisionByZero)
+        }
+        OpSymbol::ForwardSlash => Value::Int(l / r),
+        // the remainder takes the sign of the left side
+        OpSymbol::Percent => Value::Int(l % r),
+        OpSymbol::LAngleBracket => Value::Bool(l < r),
+        OpSymbol::RAngleBracket => Value::Bool(l > r),
+        OpSymbol::LAngleBracketEqual => Value::Bool(l <= r),
+        OpSymbol::RAngleBracketEqual => Value::Bool(l >= r),
+        // bitwise operators treat integers as if they were in two's complement
+        OpSymbol::Ampersand => Value::Int(l & r),
+        OpSymbol::Pipe => Value::Int(l | r),
+        OpSymbol::Xor => Value::Int(l ^ r),
+        OpSymbol::DoubleLAngleBracket => Value::Int(l << shift(r)?),
+        OpSymbol::DoubleRAngleBracket => Value::Int(l >> shift(r)?),
+        _ => {
+            return Err(FractalError::Unsupported(format!(
+                "the `{}` operator at runtime",
+                op
+            )))
+        }
+    })
+}
+
+/// applies a prefix operator to its already evaluated operand
+pub(crate) fn eval_unary(op: &UnaryOpSymbol, arg: Value) -> Result<Value, FractalError> {
+    Ok(match (op, arg) {
+        (UnaryOpSymbol::Minus, Value::Int(int)) => Value::Int(-int),
+        (UnaryOpSymbol::Bang, Value::Bool(b)) => Value::Bool(!b),
+        // the two's complement of an integer with its bits inverted
+        (UnaryOpSymbol::Bang, Value::Int(int)) => Value::Int(-int - 1),
+        (op, arg) => {
+            return Err(FractalError::InvalidValues(format!(
+                "`{}` applied to {:?}",
+                op, arg
+            )))
+        }
+    })
+}
+
+/// converts the right side of a shift into a number of bits
+fn shift(bits: BigInt) -> Result<usize, FractalError> {
+    usize::try_from(&bits).map_err(|_| FractalError::InvalidShift(bits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn int(i: isize) -> Value {
+        Value::Int(BigInt::from(i))
+    }
+
+    #[test]
+    fn test_eval_binary() {
+        use OpSymbol::*;
+        for (op, l, r, expected) in &[
+            (Percent, -7, 3, int(-1)),
+            (Ampersand, 12, 10, int(8)),
+            (Pipe, 12, 10, int(14)),
+            (Xor, 12, 10, int(6)),
+            (Ampersand, -1, 255, int(255)),
+            (DoubleLAngleBracket, 3, 4, int(48)),
+            (DoubleRAngleBracket, -16, 2, int(-4)),
+            (LAngleBracketEqual, 2, 2, true.into()),
+            (RAngleBracketEqual, 1, 2, false.into()),
+        ] {
+            assert_eq!(eval_binary(op, int(*l), int(*r)).unwrap(), *expected);
+        }
+
+        assert_eq!(
+            eval_binary(&DoubleEqual, "a".into(), "a".into()).unwrap(),
+            true.into()
+        );
+        assert_eq!(
+            eval_binary(&BangEqual, 'a'.into(), 'b'.into()).unwrap(),
+            true.into()
+        );
+        assert!(matches!(
+            eval_binary(&Percent, int(1), int(0)),
+            Err(FractalError::DivisionByZero)
+        ));
+        assert!(matches!(
+            eval_binary(&DoubleLAngleBracket, int(1), int(-1)),
+            Err(FractalError::InvalidShift(_))
+        ));
+        assert!(matches!(
+            eval_binary(&Plus, int(1), "a".into()),
+            Err(FractalError::InvalidValues(_))
+        ));
+    }
+
+    #[test]
+    fn test_eval_unary() {
+        assert_eq!(eval_unary(&UnaryOpSymbol::Minus, int(3)).unwrap(), int(-3));
+        assert_eq!(eval_unary(&UnaryOpSymbol::Bang, int(0)).unwrap(), int(-1));
+        assert_eq!(
+            eval_unary(&UnaryOpSymbol::Bang, true.into()).unwrap(),
+            false.into()
+        );
+        assert!(matches!(
+            eval_unary(&UnaryOpSymbol::Minus, "a".into()),
+            Err(FractalError::InvalidValues(_))
+        ));
+    }
+}
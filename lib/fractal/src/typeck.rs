@@ -0,0 +1,1285 @@
+// type checking of the bindings in a universe against their signatures
+//
+// signatures may contain type variables (e.g. `'a -> 'a`), which are quantified over the whole
+// signature. inside of the function they stand for some unknown type, while at each use of the
+// function they are instantiated with fresh inference variables that are solved by unification
+//
+// the widths of integer types are only checked against literals. values don't carry their type at
+// runtime, so arithmetic on them is arbitrary precision and never wraps or traps
+
+use crate::{builtins, FractalError, UniverseItem};
+use num_bigint::BigInt;
+use parser::ast::{Expression, OpSymbol, Pattern, Type, UnaryOpSymbol};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    convert::TryFrom,
+};
+
+/// the names of the types that are built into the language
+const BUILTIN_TYPES: &[&str] = &["ilarge", "f32", "f64", "string", "char", "bool", "unit"];
+
+/// the widest an integer type may be. widths are only checked against literals, so this mostly
+/// keeps the bounds computed for them small
+const MAX_INTEGER_WIDTH: usize = 128;
+
+/// the prefix of inference variable names. type variables written in source begin with a letter,
+/// so the two can never clash
+const INFERENCE_PREFIX: char = '?';
+
+/// a type along with the type variables in it that are quantified over
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<String>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn monomorphic(ty: Type) -> Self {
+        Self { vars: vec![], ty }
+    }
+
+    /// quantifies over every type variable in the type, as is done for signatures
+    fn signature(ty: &Type) -> Self {
+        Self {
+            vars: free_variables(ty),
+            ty: ty.clone(),
+        }
+    }
+}
+
+/// the types of the local bindings in scope
+type Locals = HashMap<String, Scheme>;
+
+/// a type checker over the bindings of a universe
+pub struct TypeChecker<'u> {
+    items: HashMap<&'u str, &'u UniverseItem>,
+    substitution: RefCell<HashMap<String, Type>>,
+    next_variable: Cell<usize>,
+}
+
+impl<'u> TypeChecker<'u> {
+    pub fn new(items: HashMap<&'u str, &'u UniverseItem>) -> Self {
+        Self {
+            items,
+            substitution: RefCell::new(HashMap::new()),
+            next_variable: Cell::new(0),
+        }
+    }
+
+    /// checks that a binding's signature is well-formed and that its value matches it
+    pub fn check_item(&self, name: &str, item: &UniverseItem) -> Result<(), FractalError> {
+        match item {
+            UniverseItem::FnDecl { sig, args, body } => {
+                self.well_formed(sig)?;
+
+                // functions without arguments are typed as their return value
+                let (arg_types, ret) = match sig {
+                    Type::FnSig {
+                        args: arg_types,
+                        ret,
+                    } if arg_types.len() == args.len() => (
+                        arg_types.iter().map(|ty| ty.as_ref()).collect(),
+                        ret.as_ref(),
+                    ),
+                    Type::FnSig {
+                        args: arg_types, ..
+                    } => {
+                        return Err(FractalError::ArgumentCount {
+                            name: String::from(name),
+                            expected: arg_types.len(),
+                            found: args.len(),
+                        })
+                    }
+                    _ if args.is_empty() => (vec![], sig),
+                    _ => {
+                        return Err(FractalError::ArgumentCount {
+                            name: String::from(name),
+                            expected: 0,
+                            found: args.len(),
+                        })
+                    }
+                };
+
+                // the signature's type variables are left as-is inside of the body, so they only
+                // unify with themselves
+                let mut locals: Locals = args
+                    .iter()
+                    .cloned()
+                    .zip(arg_types.into_iter().cloned().map(Scheme::monomorphic))
+                    .collect();
+                self.check_block(&mut locals, body, ret)
+            }
+            UniverseItem::VariantDecl { variants } => {
+                for variant in variants {
+                    for field in &variant.fields {
+                        self.well_formed(field)?;
+                        if !free_variables(field).is_empty() {
+                            return Err(FractalError::InvalidType(
+                                field.clone(),
+                                "type variables are not supported in variant fields",
+                            ));
+                        }
+                    }
+                }
+                Ok(())
+            }
+            // operators call their function with both of their operands
+            UniverseItem::Operator { function } => match self.items.get(function.as_str()) {
+                Some(UniverseItem::FnDecl { args, .. }) if args.len() != 2 => {
+                    Err(FractalError::ArgumentCount {
+                        name: function.clone(),
+                        expected: 2,
+                        found: args.len(),
+                    })
+                }
+                Some(UniverseItem::Constructor { fields, .. }) if fields.len() != 2 => {
+                    Err(FractalError::ConstructorArity {
+                        name: function.clone(),
+                        expected: 2,
+                        found: fields.len(),
+                    })
+                }
+                Some(UniverseItem::FnDecl { .. }) | Some(UniverseItem::Constructor { .. }) => {
+                    Ok(())
+                }
+                Some(_) => Err(FractalError::NotAFunction(function.clone())),
+                None => Err(FractalError::UnboundName(function.clone())),
+            },
+            UniverseItem::Constructor { .. } | UniverseItem::None => Ok(()),
+        }
+    }
+
+    /// checks that a type refers to known types and that its parameters are valid
+    pub fn well_formed(&self, ty: &Type) -> Result<(), FractalError> {
+        match ty {
+            Type::Identifier(name) => {
+                if BUILTIN_TYPES.contains(&name.as_str())
+                    || matches!(
+                        self.items.get(name.as_str()),
+                        Some(UniverseItem::VariantDecl { .. })
+                    )
+                {
+                    Ok(())
+                } else if name == "int" || name == "uint" || name == "list" {
+                    Err(FractalError::InvalidType(
+                        ty.clone(),
+                        "missing type parameters",
+                    ))
+                } else {
+                    Err(FractalError::UnknownType(name.clone()))
+                }
+            }
+            Type::Variable(_) => Ok(()),
+            Type::Application { name, args } => match (name.as_str(), args.as_slice()) {
+                ("int", [Type::Nat(bits)]) | ("uint", [Type::Nat(bits)]) => {
+                    integer_width(bits, ty).map(|_| ())
+                }
+                ("int", [Type::Variable(_)]) | ("uint", [Type::Variable(_)]) => Ok(()),
+                ("list", [Type::Nat(_), element]) | ("list", [Type::Variable(_), element]) => {
+                    self.well_formed(element)
+                }
+                ("int", _) | ("uint", _) | ("list", _) => Err(FractalError::InvalidType(
+                    ty.clone(),
+                    "invalid type parameters",
+                )),
+                _ => Err(FractalError::InvalidType(
+                    ty.clone(),
+                    "the type does not take parameters",
+                )),
+            },
+            Type::FnSig { args, ret } => {
+                for arg in args {
+                    self.well_formed(arg)?;
+                }
+                self.well_formed(ret)
+            }
+            Type::Nat(_) => Err(FractalError::InvalidType(
+                ty.clone(),
+                "a natural number is not a type",
+            )),
+            Type::Tag(_) => Err(FractalError::InvalidType(ty.clone(), "a tag is not a type")),
+            Type::None => Err(FractalError::InvalidType(ty.clone(), "missing type")),
+        }
+    }
+
+    fn check_block(
+        &self,
+        locals: &mut Locals,
+        body: &[Box<Expression>],
+        expected: &Type,
+    ) -> Result<(), FractalError> {
+        match body.split_last() {
+            Some((last, init)) => {
+                for expr in init {
+                    self.infer(locals, expr)?;
+                }
+                self.check(locals, last, expected)
+            }
+            None => self.unify(expected, &unit()),
+        }
+    }
+
+    fn infer_block(
+        &self,
+        locals: &mut Locals,
+        body: &[Box<Expression>],
+    ) -> Result<Type, FractalError> {
+        let mut last = unit();
+        for expr in body {
+            last = self.infer(locals, expr)?;
+        }
+        Ok(last)
+    }
+
+    /// checks an expression against the type it is expected to have
+    fn check(
+        &self,
+        locals: &mut Locals,
+        expr: &Expression,
+        expected: &Type,
+    ) -> Result<(), FractalError> {
+        let expected = self.resolve(expected);
+        match expr {
+            // integer literals take on whatever integer type they're used as
+            Expression::Integer(value) if is_integer(&expected) => check_range(value, &expected),
+            // as do float literals with float types
+            Expression::Float(value) if is_float(&expected) => {
+                check_float_range(value.0, &expected)
+            }
+            Expression::OpCall { op, args } if is_arithmetic(op) && is_integer(&expected) => {
+                for arg in args {
+                    self.check(locals, arg, &expected)?;
+                }
+                Ok(())
+            }
+            // negated literals are checked as a whole, as `-128` fits inside of an `int 8` while
+            // `128` doesn't
+            Expression::UnaryOpCall {
+                op: UnaryOpSymbol::Minus,
+                arg,
+            } if is_integer(&expected) => match arg.as_ref() {
+                Expression::Integer(value) => check_range(&-value, &expected),
+                arg => self.check(locals, arg, &expected),
+            },
+            Expression::UnaryOpCall {
+                op: UnaryOpSymbol::Bang,
+                arg,
+            } if is_integer(&expected) => self.check(locals, arg, &expected),
+            Expression::List(items) => match list_parts(&expected) {
+                Some((length, element)) => {
+                    let found = BigInt::from(items.len());
+                    match self.resolve(length) {
+                        Type::Nat(length) if length != found => {
+                            return Err(FractalError::LengthMismatch {
+                                expected: length,
+                                found,
+                            })
+                        }
+                        length => self.unify(&length, &Type::Nat(found))?,
+                    }
+                    for item in items {
+                        self.check(locals, item, element)?;
+                    }
+                    Ok(())
+                }
+                None => {
+                    let found = self.infer(locals, expr)?;
+                    self.unify(&expected, &found)
+                }
+            },
+            Expression::Match { expr, args } => {
+                let scrutinee = self.infer(locals, expr)?;
+                for arm in args {
+                    let (pat, body) = destructure(arm)?;
+                    let mut arm_locals = locals.clone();
+                    self.bind_pattern(&mut arm_locals, pat, &scrutinee)?;
+                    self.check_block(&mut arm_locals, body, &expected)?;
+                }
+                Ok(())
+            }
+            // the operands a section is missing take on the types of the expected arguments, so
+            // that `(* 2)` may be used as an `(int 8) -> int 8`
+            Expression::Section { op, l, r } => match &expected {
+                Type::FnSig { args, ret } if args.len() == missing_operands(l, r) => {
+                    let params = args.iter().map(|arg| arg.as_ref().clone()).collect();
+                    let (mut scope, call) = section(locals, op, l, r, params);
+                    self.check(&mut scope, &call, ret)
+                }
+                _ => {
+                    let found = self.infer(locals, expr)?;
+                    self.unify(&expected, &found)
+                }
+            },
+            // the arguments of a lambda take on the types of the expected ones
+            Expression::Lambda { args, body } => match &expected {
+                Type::FnSig { args: params, ret } if params.len() == args.len() => {
+                    let params = params.iter().map(|param| param.as_ref().clone());
+                    let mut scope = lambda_scope(locals, args, params);
+                    self.check_block(&mut scope, body, ret)
+                }
+                _ => {
+                    let found = self.infer(locals, expr)?;
+                    self.unify(&expected, &found)
+                }
+            },
+            Expression::ValueDecl {
+                assigns,
+                body: Some(body),
+            } => {
+                let mut scope = locals.clone();
+                for assign in assigns {
+                    self.infer(&mut scope, assign)?;
+                }
+                self.check_block(&mut scope, body, &expected)
+            }
+            _ => {
+                let found = self.infer(locals, expr)?;
+                self.unify(&expected, &found)
+            }
+        }
+    }
+
+    /// infers the type of an expression, adding any bindings it introduces to the scope
+    fn infer(&self, locals: &mut Locals, expr: &Expression) -> Result<Type, FractalError> {
+        Ok(match expr {
+            Expression::Integer(_) => Type::Identifier(String::from("ilarge")),
+            // unsuffixed floats are `f64`s unless they're used as an `f32`
+            Expression::Float(value) => {
+                let ty = Type::Identifier(String::from("f64"));
+                check_float_range(value.0, &ty)?;
+                ty
+            }
+            Expression::StringLiteral(_) => Type::Identifier(String::from("string")),
+            Expression::Char(_) => Type::Identifier(String::from("char")),
+            Expression::Identifier(name) => match locals.get(name) {
+                Some(scheme) => self.instantiate(scheme),
+                None => match self.items.get(name.as_str()) {
+                    Some(UniverseItem::FnDecl { sig, .. }) => {
+                        self.instantiate(&Scheme::signature(sig))
+                    }
+                    Some(UniverseItem::Constructor { ty, fields }) => constructor(ty, fields),
+                    _ => return Err(FractalError::UnboundName(name.clone())),
+                },
+            },
+            Expression::FnCall { name, args } => self.infer_call(locals, name, args)?,
+            Expression::Apply { callee, args } => {
+                let ty = self.infer(locals, callee)?;
+                self.infer_application(locals, &callee_name(callee), &ty, args)?
+            }
+            Expression::OpCall { op, args } => {
+                let (l, r) = match args.as_slice() {
+                    [l, r] => (l, r),
+                    // TODO(superwhiskers): remove panic
+                    _ => panic!("operator calls must have two arguments"),
+                };
+
+                // user-defined operators are calls to the function they're bound to
+                if let OpSymbol::Custom(_) = op {
+                    let function = match self.items.get(op.to_string().as_str()) {
+                        Some(UniverseItem::Operator { function }) => function,
+                        _ => return Err(FractalError::UnboundName(op.to_string())),
+                    };
+                    return self.infer_call(
+                        locals,
+                        function,
+                        &[l.as_ref().clone(), r.as_ref().clone()],
+                    );
+                }
+
+                if let OpSymbol::DoubleAmpersand | OpSymbol::DoublePipe = op {
+                    let bool = Type::Identifier(String::from("bool"));
+                    self.check(locals, l, &bool)?;
+                    self.check(locals, r, &bool)?;
+                    return Ok(bool);
+                }
+
+                // an integer literal on the left takes on the type of the right side
+                let ty = if let Expression::Integer(_) = l.as_ref() {
+                    let ty = self.infer(locals, r)?;
+                    self.check(locals, l, &ty)?;
+                    ty
+                } else {
+                    let ty = self.infer(locals, l)?;
+                    self.check(locals, r, &ty)?;
+                    ty
+                };
+                let ty = self.default_integer(&ty)?;
+
+                match op {
+                    OpSymbol::Circumflex => {
+                        return Err(FractalError::Unsupported(format!(
+                            "the `{}` operator on values",
+                            op
+                        )))
+                    }
+                    // any two values of the same type may be compared for equality
+                    OpSymbol::DoubleEqual | OpSymbol::BangEqual => {
+                        Type::Identifier(String::from("bool"))
+                    }
+                    _ if !is_integer(&ty) => {
+                        return Err(FractalError::TypeMismatch {
+                            expected: Type::Identifier(String::from("ilarge")),
+                            found: self.zonk(&ty),
+                        })
+                    }
+                    OpSymbol::LAngleBracket
+                    | OpSymbol::RAngleBracket
+                    | OpSymbol::LAngleBracketEqual
+                    | OpSymbol::RAngleBracketEqual => Type::Identifier(String::from("bool")),
+                    _ => ty,
+                }
+            }
+            Expression::Section { op, l, r } => {
+                let params: Vec<Type> = (0..missing_operands(l, r)).map(|_| self.fresh()).collect();
+                let (mut scope, call) = section(locals, op, l, r, params.clone());
+                fn_sig(params, self.infer(&mut scope, &call)?)
+            }
+            Expression::Lambda { args, body } => {
+                let params: Vec<Type> = args.iter().map(|_| self.fresh()).collect();
+                let mut scope = lambda_scope(locals, args, params.iter().cloned());
+                fn_sig(params, self.infer_block(&mut scope, body)?)
+            }
+            Expression::UnaryOpCall { op, arg } => {
+                let ty = self.infer(locals, arg)?;
+                match op {
+                    UnaryOpSymbol::Minus => {
+                        let ty = self.default_integer(&ty)?;
+                        if !is_integer(&ty) {
+                            return Err(FractalError::TypeMismatch {
+                                expected: Type::Identifier(String::from("ilarge")),
+                                found: self.zonk(&ty),
+                            });
+                        }
+                        ty
+                    }
+                    // `!` negates booleans and inverts the bits of integers
+                    UnaryOpSymbol::Bang => match self.resolve(&ty) {
+                        ty if is_integer(&ty) => ty,
+                        _ => {
+                            let bool = Type::Identifier(String::from("bool"));
+                            self.unify(&bool, &ty)?;
+                            bool
+                        }
+                    },
+                }
+            }
+            Expression::Match { expr, args } => {
+                let scrutinee = self.infer(locals, expr)?;
+                let mut result = None;
+                for arm in args {
+                    let (pat, body) = destructure(arm)?;
+                    let mut arm_locals = locals.clone();
+                    self.bind_pattern(&mut arm_locals, pat, &scrutinee)?;
+                    match &result {
+                        Some(ty) => self.check_block(&mut arm_locals, body, ty)?,
+                        None => result = Some(self.infer_block(&mut arm_locals, body)?),
+                    }
+                }
+                result.unwrap_or_else(unit)
+            }
+            Expression::ValueDecl { assigns, body } => match body {
+                Some(body) => {
+                    let mut scope = locals.clone();
+                    for assign in assigns {
+                        self.infer(&mut scope, assign)?;
+                    }
+                    self.infer_block(&mut scope, body)?
+                }
+                // without a body, the bindings are added to the enclosing block
+                None => {
+                    for assign in assigns {
+                        self.infer(locals, assign)?;
+                    }
+                    unit()
+                }
+            },
+            Expression::ValueAssign { pat, expr } => {
+                let ty = self.infer(locals, expr)?;
+                match pat {
+                    // plain bindings are generalised, allowing them to be used at several types
+                    Pattern::Identifier(name) if !self.is_constructor(name) => {
+                        let scheme = self.generalize(locals, &ty);
+                        locals.insert(name.clone(), scheme);
+                    }
+                    _ => self.bind_pattern(locals, pat, &ty)?,
+                }
+                unit()
+            }
+            Expression::TypeDecl { ty, expr } => {
+                self.well_formed(ty)?;
+                self.check(locals, expr, ty)?;
+                ty.clone()
+            }
+            Expression::List(items) => {
+                let element = match items.split_first() {
+                    Some((first, rest)) => {
+                        let element = self.infer(locals, first)?;
+                        for item in rest {
+                            self.check(locals, item, &element)?;
+                        }
+                        element
+                    }
+                    None => self.fresh(),
+                };
+                list(Type::Nat(BigInt::from(items.len())), element)
+            }
+            Expression::TagAssign { .. } => {
+                return Err(FractalError::Unsupported(String::from(
+                    "tag assignments in function bodies",
+                )))
+            }
+            // TODO(superwhiskers): remove panic
+            Expression::Destructure { .. } => panic!("destructure outside of a match expression"),
+        })
+    }
+
+    fn infer_call(
+        &self,
+        locals: &mut Locals,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Type, FractalError> {
+        if name == "println" {
+            if args.len() > 1 {
+                return Err(FractalError::ArgumentCount {
+                    name: String::from(name),
+                    expected: 1,
+                    found: args.len(),
+                });
+            }
+            for arg in args {
+                self.check(locals, arg, &Type::Identifier(String::from("string")))?;
+            }
+            return Ok(unit());
+        }
+
+        if builtins::LIST_BUILTINS.contains(&name) {
+            return self.infer_list_builtin(locals, name, args);
+        }
+
+        let callee = match locals.get(name) {
+            Some(scheme) => self.instantiate(scheme),
+            None => match self.items.get(name) {
+                Some(UniverseItem::FnDecl {
+                    sig, args: params, ..
+                }) if !params.is_empty() => self.instantiate(&Scheme::signature(sig)),
+                Some(UniverseItem::FnDecl { .. }) => {
+                    return Err(FractalError::ArgumentCount {
+                        name: String::from(name),
+                        expected: 0,
+                        found: args.len(),
+                    })
+                }
+                Some(UniverseItem::Constructor { ty, fields }) => {
+                    if fields.len() != args.len() {
+                        return Err(FractalError::ConstructorArity {
+                            name: String::from(name),
+                            expected: fields.len(),
+                            found: args.len(),
+                        });
+                    }
+                    constructor(ty, fields)
+                }
+                _ => return Err(FractalError::UnboundName(String::from(name))),
+            },
+        };
+
+        self.infer_application(locals, name, &callee, args)
+    }
+
+    /// infers the result of calling a value of the given type, named by `name` in any errors
+    fn infer_application(
+        &self,
+        locals: &mut Locals,
+        name: &str,
+        callee: &Type,
+        args: &[Expression],
+    ) -> Result<Type, FractalError> {
+        let (params, ret) = match self.resolve(callee) {
+            Type::FnSig { args, ret } => (args.into_iter().map(|arg| *arg).collect(), *ret),
+            // a binding of an unknown type is assumed to be a function taking these arguments
+            Type::Variable(var) if is_inference(&var) => {
+                let params: Vec<Type> = args.iter().map(|_| self.fresh()).collect();
+                let ret = self.fresh();
+                self.unify(
+                    callee,
+                    &Type::FnSig {
+                        args: params.iter().cloned().map(Box::new).collect(),
+                        ret: Box::new(ret.clone()),
+                    },
+                )?;
+                (params, ret)
+            }
+            _ => return Err(FractalError::NotAFunction(String::from(name))),
+        };
+
+        if params.len() != args.len() {
+            return Err(FractalError::ArgumentCount {
+                name: String::from(name),
+                expected: params.len(),
+                found: args.len(),
+            });
+        }
+
+        for (arg, param) in args.iter().zip(&params) {
+            self.check(locals, arg, param)?;
+        }
+
+        Ok(ret)
+    }
+
+    fn infer_list_builtin(
+        &self,
+        locals: &mut Locals,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Type, FractalError> {
+        let expected = match name {
+            "len" => 1,
+            "fold" => 3,
+            _ => 2,
+        };
+        if args.len() != expected {
+            return Err(FractalError::ArgumentCount {
+                name: String::from(name),
+                expected,
+                found: args.len(),
+            });
+        }
+
+        let length = self.fresh();
+        let element = self.fresh();
+        self.check(locals, &args[0], &list(length.clone(), element.clone()))?;
+
+        let ilarge = Type::Identifier(String::from("ilarge"));
+        Ok(match name {
+            "len" => ilarge,
+            "get" => {
+                self.check(locals, &args[1], &ilarge)?;
+                element
+            }
+            "push" => {
+                self.check(locals, &args[1], &element)?;
+                list(
+                    self.add_lengths(&length, &Type::Nat(BigInt::from(1))),
+                    element,
+                )
+            }
+            "concat" => {
+                let other = self.fresh();
+                self.check(locals, &args[1], &list(other.clone(), element.clone()))?;
+                list(self.add_lengths(&length, &other), element)
+            }
+            "map" => {
+                let result = self.fresh();
+                self.check(locals, &args[1], &fn_sig(vec![element], result.clone()))?;
+                list(length, result)
+            }
+            "fold" => {
+                // the function is checked first so that the initial value can take on its type
+                let accumulator = self.fresh();
+                self.check(
+                    locals,
+                    &args[2],
+                    &fn_sig(vec![accumulator.clone(), element], accumulator.clone()),
+                )?;
+                self.check(locals, &args[1], &accumulator)?;
+                accumulator
+            }
+            // TODO(superwhiskers): remove panic
+            _ => panic!("unknown list builtin: {}", name),
+        })
+    }
+
+    /// sums two list lengths if both are known
+    ///
+    /// there is no arithmetic on type variables, so a sum involving one is left unconstrained
+    fn add_lengths(&self, a: &Type, b: &Type) -> Type {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Nat(a), Type::Nat(b)) => Type::Nat(a + b),
+            _ => self.fresh(),
+        }
+    }
+
+    /// checks a pattern against the type of the value it matches, binding any identifiers in it
+    fn bind_pattern(
+        &self,
+        locals: &mut Locals,
+        pat: &Pattern,
+        ty: &Type,
+    ) -> Result<(), FractalError> {
+        match pat {
+            Pattern::Wildcard => Ok(()),
+            Pattern::Identifier(name) if self.is_constructor(name) => {
+                self.bind_constructor(locals, name, &[], ty)
+            }
+            Pattern::Identifier(name) => {
+                locals.insert(name.clone(), Scheme::monomorphic(ty.clone()));
+                Ok(())
+            }
+            Pattern::Integer(value) => match self.resolve(ty) {
+                ty if is_integer(&ty) => check_range(value, &ty),
+                _ => self.unify(ty, &Type::Identifier(String::from("ilarge"))),
+            },
+            Pattern::StringLiteral(_) => self.unify(ty, &Type::Identifier(String::from("string"))),
+            Pattern::Char(_) => self.unify(ty, &Type::Identifier(String::from("char"))),
+            Pattern::Range { start, end } => {
+                for bound in start.iter().chain(end.iter()) {
+                    self.bind_pattern(locals, bound, ty)?;
+                }
+                Ok(())
+            }
+            Pattern::Constructor { name, args } => self.bind_constructor(locals, name, args, ty),
+        }
+    }
+
+    fn bind_constructor(
+        &self,
+        locals: &mut Locals,
+        name: &str,
+        args: &[Pattern],
+        ty: &Type,
+    ) -> Result<(), FractalError> {
+        let (variant, fields) = match self.items.get(name) {
+            Some(UniverseItem::Constructor { ty, fields }) => (ty, fields),
+            _ => return Err(FractalError::UnknownConstructor(String::from(name))),
+        };
+
+        self.unify(ty, &Type::Identifier(variant.clone()))?;
+        if fields.len() != args.len() {
+            return Err(FractalError::ConstructorArity {
+                name: String::from(name),
+                expected: fields.len(),
+                found: args.len(),
+            });
+        }
+
+        for (arg, field) in args.iter().zip(fields) {
+            self.bind_pattern(locals, arg, field)?;
+        }
+        Ok(())
+    }
+
+    fn is_constructor(&self, name: &str) -> bool {
+        matches!(self.items.get(name), Some(UniverseItem::Constructor { .. }))
+    }
+
+    /// assumes that operands of an unknown type are integers
+    fn default_integer(&self, ty: &Type) -> Result<Type, FractalError> {
+        Ok(match self.resolve(ty) {
+            Type::Variable(var) if is_inference(&var) => {
+                let ilarge = Type::Identifier(String::from("ilarge"));
+                self.unify(&ilarge, ty)?;
+                ilarge
+            }
+            ty => ty,
+        })
+    }
+
+    /// creates a new inference variable
+    fn fresh(&self) -> Type {
+        let id = self.next_variable.get();
+        self.next_variable.set(id + 1);
+        Type::Variable(format!("{}{}", INFERENCE_PREFIX, id))
+    }
+
+    /// replaces the quantified variables of a scheme with fresh inference variables
+    fn instantiate(&self, scheme: &Scheme) -> Type {
+        let substitution: HashMap<String, Type> = scheme
+            .vars
+            .iter()
+            .map(|var| (var.clone(), self.fresh()))
+            .collect();
+        substitute(&scheme.ty, &substitution)
+    }
+
+    /// quantifies over the inference variables in a type that aren't used by the enclosing scope
+    fn generalize(&self, locals: &Locals, ty: &Type) -> Scheme {
+        let ty = self.zonk(ty);
+        let in_scope: Vec<String> = locals
+            .values()
+            .flat_map(|scheme| free_variables(&self.zonk(&scheme.ty)))
+            .collect();
+        let vars = free_variables(&ty)
+            .into_iter()
+            .filter(|var| is_inference(var) && !in_scope.contains(var))
+            .collect();
+        Scheme { vars, ty }
+    }
+
+    /// follows the substitution until the outermost part of the type is known
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Variable(var) = &ty {
+            match self.substitution.borrow().get(var) {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// applies the substitution to the entirety of a type
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::FnSig { args, ret } => Type::FnSig {
+                args: args.iter().map(|arg| Box::new(self.zonk(arg))).collect(),
+                ret: Box::new(self.zonk(&ret)),
+            },
+            Type::Application { name, args } => Type::Application {
+                name,
+                args: args.iter().map(|arg| self.zonk(arg)).collect(),
+            },
+            ty => ty,
+        }
+    }
+
+    /// unifies the type a value was expected to have with the one that it has
+    fn unify(&self, expected: &Type, found: &Type) -> Result<(), FractalError> {
+        self.unify_inner(expected, found).map_err(|err| {
+            err.unwrap_or_else(|| FractalError::TypeMismatch {
+                expected: self.zonk(expected),
+                found: self.zonk(found),
+            })
+        })
+    }
+
+    // the error is None when the types simply don't match, leaving it up to `unify` to report
+    // the outermost types involved
+    fn unify_inner(&self, expected: &Type, found: &Type) -> Result<(), Option<FractalError>> {
+        match (self.resolve(expected), self.resolve(found)) {
+            (Type::Variable(a), Type::Variable(b)) if a == b => Ok(()),
+            (Type::Variable(var), ty) | (ty, Type::Variable(var)) if is_inference(&var) => {
+                if free_variables(&self.zonk(&ty)).contains(&var) {
+                    return Err(None);
+                }
+                self.substitution.borrow_mut().insert(var, ty);
+                Ok(())
+            }
+            (Type::Identifier(a), Type::Identifier(b)) if a == b => Ok(()),
+            (Type::Nat(a), Type::Nat(b)) if a == b => Ok(()),
+            (
+                Type::Application {
+                    name: expected_name,
+                    args: expected_args,
+                },
+                Type::Application {
+                    name: found_name,
+                    args: found_args,
+                },
+            ) if expected_name == found_name && expected_args.len() == found_args.len() => {
+                // report mismatched list lengths on their own, as the element types are often fine
+                if let ([expected_length, expected_element], [found_length, found_element]) =
+                    (expected_args.as_slice(), found_args.as_slice())
+                {
+                    if expected_name == "list" {
+                        self.unify_inner(expected_element, found_element)?;
+                        return match (self.resolve(expected_length), self.resolve(found_length)) {
+                            (Type::Nat(expected), Type::Nat(found)) if expected != found => {
+                                Err(Some(FractalError::LengthMismatch { expected, found }))
+                            }
+                            _ => self.unify_inner(expected_length, found_length),
+                        };
+                    }
+                }
+
+                for (expected, found) in expected_args.iter().zip(&found_args) {
+                    self.unify_inner(expected, found)?;
+                }
+                Ok(())
+            }
+            (
+                Type::FnSig {
+                    args: expected_args,
+                    ret: expected_ret,
+                },
+                Type::FnSig {
+                    args: found_args,
+                    ret: found_ret,
+                },
+            ) if expected_args.len() == found_args.len() => {
+                for (expected, found) in expected_args.iter().zip(&found_args) {
+                    self.unify_inner(expected, found)?;
+                }
+                self.unify_inner(&expected_ret, &found_ret)
+            }
+            _ => Err(None),
+        }
+    }
+}
+
+/// retrieves every type variable in a type, in order of appearance
+pub fn free_variables(ty: &Type) -> Vec<String> {
+    fn collect(ty: &Type, vars: &mut Vec<String>) {
+        match ty {
+            Type::Variable(var) if !vars.contains(var) => vars.push(var.clone()),
+            Type::FnSig { args, ret } => {
+                for arg in args {
+                    collect(arg, vars);
+                }
+                collect(ret, vars);
+            }
+            Type::Application { args, .. } => {
+                for arg in args {
+                    collect(arg, vars);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut vars = Vec::new();
+    collect(ty, &mut vars);
+    vars
+}
+
+/// replaces the type variables in a type using the provided mapping
+pub fn substitute(ty: &Type, substitution: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Variable(var) => substitution.get(var).cloned().unwrap_or_else(|| ty.clone()),
+        Type::FnSig { args, ret } => Type::FnSig {
+            args: args
+                .iter()
+                .map(|arg| Box::new(substitute(arg, substitution)))
+                .collect(),
+            ret: Box::new(substitute(ret, substitution)),
+        },
+        Type::Application { name, args } => Type::Application {
+            name: name.clone(),
+            args: args
+                .iter()
+                .map(|arg| substitute(arg, substitution))
+                .collect(),
+        },
+        _ => ty.clone(),
+    }
+}
+
+fn is_inference(var: &str) -> bool {
+    var.starts_with(INFERENCE_PREFIX)
+}
+
+fn unit() -> Type {
+    Type::Identifier(String::from("unit"))
+}
+
+fn list(length: Type, element: Type) -> Type {
+    Type::Application {
+        name: String::from("list"),
+        args: vec![length, element],
+    }
+}
+
+fn fn_sig(args: Vec<Type>, ret: Type) -> Type {
+    Type::FnSig {
+        args: args.into_iter().map(Box::new).collect(),
+        ret: Box::new(ret),
+    }
+}
+
+/// the type of a constructor used as a value
+fn constructor(ty: &str, fields: &[Type]) -> Type {
+    if fields.is_empty() {
+        Type::Identifier(String::from(ty))
+    } else {
+        fn_sig(fields.to_vec(), Type::Identifier(String::from(ty)))
+    }
+}
+
+/// splits a list type into its length and element type
+fn list_parts(ty: &Type) -> Option<(&Type, &Type)> {
+    match ty {
+        Type::Application { name, args } if name == "list" => match args.as_slice() {
+            [length, element] => Some((length, element)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub(crate) fn is_integer(ty: &Type) -> bool {
+    match ty {
+        Type::Identifier(name) => name == "ilarge",
+        Type::Application { name, .. } => name == "int" || name == "uint",
+        _ => false,
+    }
+}
+
+fn is_float(ty: &Type) -> bool {
+    matches!(ty, Type::Identifier(name) if name == "f32" || name == "f64")
+}
+
+/// whether an operator takes two integers of some type and produces another of that type
+fn is_arithmetic(op: &OpSymbol) -> bool {
+    matches!(
+        op,
+        OpSymbol::Plus
+            | OpSymbol::Minus
+            | OpSymbol::Star
+            | OpSymbol::ForwardSlash
+            | OpSymbol::Percent
+            | OpSymbol::Ampersand
+            | OpSymbol::Pipe
+            | OpSymbol::Xor
+            | OpSymbol::DoubleLAngleBracket
+            | OpSymbol::DoubleRAngleBracket
+    )
+}
+
+fn destructure(arm: &Expression) -> Result<(&Pattern, &[Box<Expression>]), FractalError> {
+    match arm {
+        Expression::Destructure { pat, body } => Ok((pat, body)),
+        // TODO(superwhiskers): remove panic
+        _ => panic!("unexpected match arm: {:?}", arm),
+    }
+}
+
+fn missing_operands(l: &Option<Box<Expression>>, r: &Option<Box<Expression>>) -> usize {
+    l.is_none() as usize + r.is_none() as usize
+}
+
+/// the operator call a section stands for, along with a scope binding its missing operands to
+/// locals of the provided types. their names begin with the inference prefix, so they can't
+/// shadow any in source
+fn section(
+    locals: &Locals,
+    op: &OpSymbol,
+    l: &Option<Box<Expression>>,
+    r: &Option<Box<Expression>>,
+    params: Vec<Type>,
+) -> (Locals, Expression) {
+    let mut scope = locals.clone();
+    let mut params = params.into_iter();
+    let mut operand = |operand: &Option<Box<Expression>>, name: &str| match operand {
+        Some(expr) => expr.clone(),
+        None => {
+            let name = format!("{}{}", INFERENCE_PREFIX, name);
+            // TODO(superwhiskers): remove expect
+            let ty = params.next().expect("a type for each missing operand");
+            scope.insert(name.clone(), Scheme::monomorphic(ty));
+            Box::new(Expression::Identifier(name))
+        }
+    };
+    let args = vec![operand(l, "l"), operand(r, "r")];
+    (
+        scope,
+        Expression::OpCall {
+            op: op.clone(),
+            args,
+        },
+    )
+}
+
+/// the scope a lambda's body is checked in, which is the one it's written in along with its
+/// arguments
+fn lambda_scope(
+    locals: &Locals,
+    args: &[String],
+    params: impl IntoIterator<Item = Type>,
+) -> Locals {
+    let mut scope = locals.clone();
+    scope.extend(
+        args.iter()
+            .cloned()
+            .zip(params.into_iter().map(Scheme::monomorphic)),
+    );
+    scope
+}
+
+/// a name for an applied expression to be referred to by in errors
+fn callee_name(callee: &Expression) -> String {
+    match callee {
+        Expression::Identifier(name) => name.clone(),
+        Expression::FnCall { name, .. } => format!("{} ...", name),
+        Expression::Section { op, .. } => format!("({})", op),
+        _ => String::from("(...)"),
+    }
+}
+
+/// converts the width of an integer type, ensuring that it's within the supported widths
+fn integer_width(bits: &BigInt, ty: &Type) -> Result<usize, FractalError> {
+    match usize::try_from(bits) {
+        Ok(0) => Err(FractalError::InvalidType(
+            ty.clone(),
+            "integers must be at least one bit wide",
+        )),
+        Ok(bits) if bits <= MAX_INTEGER_WIDTH => Ok(bits),
+        _ => Err(FractalError::InvalidType(
+            ty.clone(),
+            "integers may be at most 128 bits wide",
+        )),
+    }
+}
+
+/// ensures that an integer fits inside of the provided integer type
+fn check_range(value: &BigInt, ty: &Type) -> Result<(), FractalError> {
+    if let Type::Application { name, args } = ty {
+        if let [Type::Nat(bits)] = args.as_slice() {
+            let bits = integer_width(bits, ty)?;
+            let (min, max) = match name.as_str() {
+                "uint" => (BigInt::from(0), BigInt::from(1) << bits),
+                _ => {
+                    let bound = BigInt::from(1) << (bits - 1);
+                    (-bound.clone(), bound)
+                }
+            };
+            if *value < min || *value >= max {
+                return Err(FractalError::IntegerOutOfRange {
+                    value: value.clone(),
+                    ty: ty.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ensures that a float literal doesn't overflow the provided float type
+fn check_float_range(value: f64, ty: &Type) -> Result<(), FractalError> {
+    let overflows = match ty {
+        Type::Identifier(name) if name == "f32" => (value as f32).is_infinite(),
+        _ => value.is_infinite(),
+    };
+    if overflows {
+        return Err(FractalError::FloatOutOfRange {
+            value,
+            ty: ty.clone(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser::{ast::Variant, fixity::FixityTable, lexer, snowflake::TypeExpressionParser};
+
+    fn ty(source: &str) -> Type {
+        TypeExpressionParser::new()
+            .parse(&FixityTable::default(), lexer::lex(source))
+            .unwrap()
+    }
+
+    fn checker<'u>(items: &'u [(&'static str, UniverseItem)]) -> TypeChecker<'u> {
+        TypeChecker::new(items.iter().map(|(name, item)| (*name, item)).collect())
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let items = [(
+            "shape",
+            UniverseItem::VariantDecl {
+                variants: vec![Variant {
+                    name: String::from("circle"),
+                    fields: vec![ty("ilarge")],
+                }],
+            },
+        )];
+        let checker = checker(&items);
+
+        assert!(checker.well_formed(&ty("int 32")).is_ok());
+        assert!(checker.well_formed(&ty("uint 8")).is_ok());
+        assert!(checker.well_formed(&ty("list 4 (int 8)")).is_ok());
+        assert!(checker.well_formed(&ty("(list 2 shape) -> int 64")).is_ok());
+        assert!(checker.well_formed(&ty("(list 'n 'a) -> 'a")).is_ok());
+        assert!(matches!(
+            checker.well_formed(&ty("int 0")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(matches!(
+            checker.well_formed(&ty("uint 0")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(checker.well_formed(&ty("int 128")).is_ok());
+        assert!(matches!(
+            checker.well_formed(&ty("uint 129")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(matches!(
+            checker.well_formed(&ty("(int 99999999999) -> ilarge")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(matches!(
+            checker.well_formed(&ty("list int 4")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(matches!(
+            checker.well_formed(&ty("(int 8) -> 4")),
+            Err(FractalError::InvalidType(..))
+        ));
+        assert!(matches!(
+            checker.well_formed(&ty("list 4 square")),
+            Err(FractalError::UnknownType(name)) if name == "square"
+        ));
+    }
+
+    #[test]
+    fn test_unify() {
+        let checker = checker(&[]);
+
+        assert!(checker
+            .unify(&ty("list 4 (int 8)"), &ty("list 4 (int 8)"))
+            .is_ok());
+        assert!(matches!(
+            checker.unify(&ty("list 4 (int 8)"), &ty("list 3 (int 8)")),
+            Err(FractalError::LengthMismatch { expected, found })
+                if expected == BigInt::from(4) && found == BigInt::from(3)
+        ));
+        assert!(matches!(
+            checker.unify(&ty("list 4 (int 8)"), &ty("list 3 (int 16)")),
+            Err(FractalError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            checker.unify(&ty("int 8"), &ty("int 16")),
+            Err(FractalError::TypeMismatch { .. })
+        ));
+
+        // type variables from signatures only unify with themselves
+        assert!(checker.unify(&ty("'a"), &ty("'a")).is_ok());
+        assert!(matches!(
+            checker.unify(&ty("'a"), &ty("ilarge")),
+            Err(FractalError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let checker = checker(&[]);
+
+        let scheme = Scheme::signature(&ty("(list 'n 'a) -> 'a"));
+        let instance = checker.instantiate(&scheme);
+        checker
+            .unify(&instance, &ty("(list 3 string) -> string"))
+            .unwrap();
+        assert_eq!(checker.zonk(&instance), ty("(list 3 string) -> string"));
+
+        // each instantiation is independent of the others
+        let instance = checker.instantiate(&scheme);
+        assert!(checker
+            .unify(&instance, &ty("(list 2 ilarge) -> ilarge"))
+            .is_ok());
+        let instance = checker.instantiate(&scheme);
+        assert!(checker
+            .unify(&instance, &ty("(list 2 ilarge) -> string"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_range() {
+        assert!(check_range(&BigInt::from(127), &ty("int 8")).is_ok());
+        assert!(check_range(&BigInt::from(-128), &ty("int 8")).is_ok());
+        assert!(check_range(&BigInt::from(128), &ty("int 8")).is_err());
+        assert!(check_range(&BigInt::from(-129), &ty("int 8")).is_err());
+        assert!(check_range(&(BigInt::from(1) << 100), &ty("ilarge")).is_ok());
+
+        assert!(check_range(&BigInt::from(255), &ty("uint 8")).is_ok());
+        assert!(check_range(&BigInt::from(0), &ty("uint 8")).is_ok());
+        assert!(check_range(&BigInt::from(256), &ty("uint 8")).is_err());
+        assert!(check_range(&BigInt::from(-1), &ty("uint 8")).is_err());
+        assert!(matches!(
+            check_range(&BigInt::from(5), &ty("int 99999999999")),
+            Err(FractalError::InvalidType(..))
+        ));
+    }
+}
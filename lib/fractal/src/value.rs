@@ -0,0 +1,204 @@
+// the values produced by evaluation
+//
+// types are erased once a program has been checked, so values don't carry them around
+
+use num_bigint::BigInt;
+use parser::ast::{Expression, OpSymbol};
+use std::{collections::HashMap, fmt};
+
+/// a runtime value
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(BigInt),
+    Float(f64),
+    String(String),
+    Char(char),
+    Bool(bool),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
+
+    // a value of a variant type, built by the named constructor
+    Variant {
+        name: String,
+        fields: Vec<Value>,
+    },
+
+    // a reference to a function or constructor binding by its name
+    Function(String),
+    // an operator holding the operands it has been given, which is called with those it is
+    // missing
+    Section {
+        op: OpSymbol,
+        l: Option<Box<Value>>,
+        r: Option<Box<Value>>,
+    },
+    // a lambda along with the locals that were in scope where it was evaluated
+    Closure {
+        args: Vec<String>,
+        body: Vec<Box<Expression>>,
+        captures: HashMap<String, Value>,
+    },
+    // a compiled function along with the values passed before the arguments it is called with,
+    // which is how compiled code represents closures and sections
+    Partial {
+        function: String,
+        args: Vec<Value>,
+    },
+    Unit,
+}
+
+impl Value {
+    // writes a value nested inside of another, quoting strings and parenthesizing variants with
+    // fields so that the result is unambiguous
+    fn fmt_nested(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::String(string) => write!(f, "{:?}", string),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::Variant { fields, .. } if !fields.is_empty() => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(int) => write!(f, "{}", int),
+            Value::Float(float) => write!(f, "{:?}", float),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {} = ", name)?;
+                    value.fmt_nested(f)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant { name, fields } => {
+                write!(f, "{}", name)?;
+                for field in fields {
+                    write!(f, " ")?;
+                    field.fmt_nested(f)?;
+                }
+                Ok(())
+            }
+            Value::Function(name) => write!(f, "<fn {}>", name),
+            Value::Section { op, l, r } => {
+                write!(f, "(")?;
+                if let Some(l) = l {
+                    l.fmt_nested(f)?;
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", op)?;
+                if let Some(r) = r {
+                    write!(f, " ")?;
+                    r.fmt_nested(f)?;
+                }
+                write!(f, ")")
+            }
+            Value::Closure { args, .. } => write!(f, "<closure \\{}>", args.join(" ")),
+            Value::Partial { function, .. } => write!(f, "<closure {}>", function),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(int: BigInt) -> Self {
+        Value::Int(int)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Value::String(String::from(string))
+    }
+}
+
+impl From<char> for Value {
+    fn from(c: char) -> Self {
+        Value::Char(c)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Int(BigInt::from(-4)).to_string(), "-4");
+        assert_eq!(Value::Float(2.0).to_string(), "2.0");
+        assert_eq!(Value::from("hi").to_string(), "hi");
+        assert_eq!(Value::from('λ').to_string(), "λ");
+        assert_eq!(Value::Unit.to_string(), "()");
+        assert_eq!(Value::Function("id".into()).to_string(), "<fn id>");
+        assert_eq!(
+            Value::Section {
+                op: OpSymbol::Star,
+                l: None,
+                r: Some(Box::new(Value::Int(BigInt::from(2)))),
+            }
+            .to_string(),
+            "(* 2)"
+        );
+        assert_eq!(
+            Value::Closure {
+                args: vec!["x".into(), "y".into()],
+                body: vec![],
+                captures: HashMap::new(),
+            }
+            .to_string(),
+            r"<closure \x y>"
+        );
+        assert_eq!(
+            Value::List(vec!["bar".into(), "baz".into()]).to_string(),
+            r#"["bar", "baz"]"#
+        );
+        assert_eq!(Value::List(vec![]).to_string(), "[]");
+        assert_eq!(
+            Value::List(vec!['a'.into(), '\n'.into()]).to_string(),
+            r#"['a', '\n']"#
+        );
+        assert_eq!(
+            Value::Record(vec![
+                ("x".into(), Value::Int(BigInt::from(1))),
+                ("ok".into(), true.into())
+            ])
+            .to_string(),
+            "{ x = 1, ok = true }"
+        );
+        assert_eq!(
+            Value::Variant {
+                name: "some".into(),
+                fields: vec![Value::Variant {
+                    name: "rect".into(),
+                    fields: vec![Value::Int(BigInt::from(1)), "a".into()],
+                }],
+            }
+            .to_string(),
+            r#"some (rect 1 "a")"#
+        );
+    }
+}
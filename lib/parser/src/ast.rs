@@ -7,7 +7,9 @@
 // file, you can obtain one at http://mozilla.org/MPL/2.0/.
 //
 
+use crate::fixity::Fixity;
 use num_bigint::BigInt;
+use std::fmt;
 
 // "top level" statements that are not an expression
 // while anything can be a statement, I think a goal should be that
@@ -24,6 +26,22 @@ pub enum Statement {
         name: String,
         body: Type,
     },
+    // a tagged union, e.g. `type shape = circle int | rect int int`
+    VariantDecl {
+        name: String,
+        variants: Vec<Variant>,
+    },
+    // a user-defined operator bound to a function, e.g. `infixl 6 <+> = combine`
+    OperatorDecl {
+        op: OpSymbol,
+        fixity: Fixity,
+        function: String,
+    },
+    // a statement preceded by doc comments, e.g. `--| adds two numbers`
+    Documented {
+        docs: Vec<String>,
+        statement: Box<Statement>,
+    },
     // ValueDecl {
     //     pat: Pattern,
     //     expr: Expression,
@@ -33,6 +51,16 @@ pub enum Statement {
     None,
 }
 
+impl Statement {
+    // the statement without the doc comments attached to it
+    pub fn undocumented(&self) -> &Statement {
+        match self {
+            Statement::Documented { statement, .. } => statement.undocumented(),
+            _ => self,
+        }
+    }
+}
+
 impl Default for Statement {
     fn default() -> Self {
         Self::None
@@ -48,6 +76,13 @@ pub enum Type {
     Tag(Tag),
     Nat(BigInt),
     Identifier(String),
+    // a type variable, e.g. `'a`
+    Variable(String),
+    // a type applied to parameters, e.g. `int 32` or `list 4 int`
+    Application {
+        name: String,
+        args: Vec<Type>,
+    },
 
     // dummy variant used for implementing Default
     None,
@@ -59,16 +94,83 @@ impl Default for Type {
     }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // writes a type, parenthesizing it if it wouldn't be a single atom otherwise
+        fn write_atom(f: &mut fmt::Formatter<'_>, ty: &Type) -> fmt::Result {
+            match ty {
+                Type::FnSig { .. } | Type::Application { .. } | Type::Tag(_) => {
+                    write!(f, "({})", ty)
+                }
+                _ => write!(f, "{}", ty),
+            }
+        }
+
+        match self {
+            Type::FnSig { args, ret } => {
+                for arg in args {
+                    write_atom(f, arg)?;
+                    write!(f, " ")?;
+                }
+                write!(f, "-> {}", ret)
+            }
+            Type::Tag(tag) => write!(f, "tag {}", tag),
+            Type::Nat(nat) => write!(f, "{}", nat),
+            Type::Identifier(ident) => write!(f, "{}", ident),
+            Type::Variable(var) => write!(f, "'{}", var),
+            Type::Application { name, args } => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    write!(f, " ")?;
+                    write_atom(f, arg)?;
+                }
+                Ok(())
+            }
+            Type::None => write!(f, "_"),
+        }
+    }
+}
+
+// a single constructor of a VariantDecl along with the types of its fields
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Expression {
     OpCall {
         op: OpSymbol,
         args: Vec<Box<Expression>>,
     },
+    // a prefix operator applied to an expression, e.g. `-n` or `!done`
+    UnaryOpCall {
+        op: UnaryOpSymbol,
+        arg: Box<Expression>,
+    },
+    // an operator missing one or both of its operands, e.g. `(+ 1)`, `(1 +)` or `(+)`, which is a
+    // function taking the operands that are missing
+    Section {
+        op: OpSymbol,
+        l: Option<Box<Expression>>,
+        r: Option<Box<Expression>>,
+    },
     FnCall {
         name: String,
         args: Vec<Expression>,
     },
+    // a call of anything other than a name, e.g. `(choose f g) x`
+    Apply {
+        callee: Box<Expression>,
+        args: Vec<Expression>,
+    },
+    // an anonymous function, e.g. `\x y => x + y`, which captures the locals in scope where it is
+    // written
+    Lambda {
+        args: Vec<String>,
+        body: Vec<Box<Expression>>,
+    },
     Match {
         expr: Box<Expression>,
         args: Vec<Expression>,
@@ -88,18 +190,79 @@ pub enum Expression {
     },
     TagAssign {
         tag: Tag,
-        expr: Tag
+        expr: Tag,
     },
     TypeDecl {
         ty: Type,
         expr: Box<Expression>,
     },
     Integer(BigInt),
+    Float(Float),
     Identifier(String),
     StringLiteral(String),
+    Char(char),
     List(Vec<Box<Expression>>),
 }
 
+// a float literal, which is compared by its bits so that expressions may still be compared for
+// equality
+#[derive(Debug, Clone, Copy)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl Expression {
+    // a callee applied to arguments, which is a FnCall when the callee is a name
+    pub fn apply(callee: Expression, args: Vec<Expression>) -> Self {
+        match callee {
+            Expression::Identifier(name) => Expression::FnCall { name, args },
+            callee => Expression::Apply {
+                callee: Box::new(callee),
+                args,
+            },
+        }
+    }
+
+    // a byte literal, e.g. `b'x'`, which is a `uint 8`
+    pub fn byte(byte: u8) -> Self {
+        Expression::TypeDecl {
+            ty: Type::Application {
+                name: String::from("uint"),
+                args: vec![Type::Nat(BigInt::from(8))],
+            },
+            expr: Box::new(Expression::Integer(BigInt::from(byte))),
+        }
+    }
+
+    // a byte string literal, e.g. `b"bytes"`, which is a list of `uint 8`s
+    pub fn byte_string(bytes: &[u8]) -> Self {
+        Expression::TypeDecl {
+            ty: Type::Application {
+                name: String::from("list"),
+                args: vec![
+                    Type::Nat(BigInt::from(bytes.len())),
+                    Type::Application {
+                        name: String::from("uint"),
+                        args: vec![Type::Nat(BigInt::from(8))],
+                    },
+                ],
+            },
+            expr: Box::new(Expression::List(
+                bytes
+                    .iter()
+                    .map(|&byte| Box::new(Expression::byte(byte)))
+                    .collect(),
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Pattern {
     Wildcard,
@@ -110,6 +273,56 @@ pub enum Pattern {
     Integer(BigInt),
     Identifier(String),
     StringLiteral(String),
+    Char(char),
+    // a constructor applied to sub-patterns, e.g. `circle r` or `some (circle _)`
+    //
+    // nullary constructors parse as an Identifier and are resolved later on
+    Constructor {
+        name: String,
+        args: Vec<Pattern>,
+    },
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Range { start, end } => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "..")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+            Pattern::Integer(int) => write!(f, "{}", int),
+            Pattern::Identifier(ident) => write!(f, "{}", ident),
+            // the lexer keeps the quotes around string literals, which are replaced so that the
+            // string is escaped the same way as a char
+            Pattern::StringLiteral(string) => {
+                let string = string
+                    .strip_prefix('"')
+                    .and_then(|string| string.strip_suffix('"'))
+                    .unwrap_or(string);
+                write!(f, "{:?}", string)
+            }
+            Pattern::Char(c) => write!(f, "{:?}", c),
+            Pattern::Constructor { name, args } => {
+                write!(f, "{}", name)?;
+                for arg in args {
+                    match arg {
+                        Pattern::Constructor { args, .. } if !args.is_empty() => {
+                            write!(f, " ({})", arg)?
+                        }
+                        _ => write!(f, " {}", arg)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -120,16 +333,103 @@ pub enum Tag {
     Identifier(String),
 }
 
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::OpCall { op, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}", args.join(&format!(" {} ", op)))
+            }
+            Tag::Assign { pats } => {
+                let pats: Vec<String> = pats.iter().map(|pat| pat.to_string()).collect();
+                write!(f, "#{{ {} }}", pats.join(" "))
+            }
+            Tag::PrimaryIdentifier(ident) => write!(f, "*{}", ident),
+            Tag::Identifier(ident) => write!(f, "{}", ident),
+        }
+    }
+}
+
 // named OpSymbol so it has some "genericness" for future use
 // in something like macros
 // - @bree
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum OpSymbol {
     Plus,
     Minus,
     Star,
     ForwardSlash,
+    Percent,
     LAngleBracket,
     RAngleBracket,
+    LAngleBracketEqual,
+    RAngleBracketEqual,
+    DoubleEqual,
+    BangEqual,
+    DoubleAmpersand,
+    DoublePipe,
+    Ampersand,
+    Pipe,
+    DoubleLAngleBracket,
+    DoubleRAngleBracket,
+    // `^` between values. `^` between tags is a Circumflex, whose meaning is left up to the tag
+    // library
+    Xor,
     Circumflex,
+    // `f $ x` and `x |> f`, which apply `f` to `x` and are turned into calls as they are parsed
+    Dollar,
+    PipeRAngleBracket,
+    // a user-defined operator, e.g. `<+>`
+    Custom(String),
+}
+
+impl fmt::Display for OpSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                OpSymbol::Plus => "+",
+                OpSymbol::Minus => "-",
+                OpSymbol::Star => "*",
+                OpSymbol::ForwardSlash => "/",
+                OpSymbol::Percent => "%",
+                OpSymbol::LAngleBracket => "<",
+                OpSymbol::RAngleBracket => ">",
+                OpSymbol::LAngleBracketEqual => "<=",
+                OpSymbol::RAngleBracketEqual => ">=",
+                OpSymbol::DoubleEqual => "==",
+                OpSymbol::BangEqual => "!=",
+                OpSymbol::DoubleAmpersand => "&&",
+                OpSymbol::DoublePipe => "||",
+                OpSymbol::Ampersand => "&",
+                OpSymbol::Pipe => "|",
+                OpSymbol::DoubleLAngleBracket => "<<",
+                OpSymbol::DoubleRAngleBracket => ">>",
+                OpSymbol::Xor | OpSymbol::Circumflex => "^",
+                OpSymbol::Dollar => "$",
+                OpSymbol::PipeRAngleBracket => "|>",
+                OpSymbol::Custom(symbols) => symbols.as_str(),
+            }
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub enum UnaryOpSymbol {
+    Minus,
+    Bang,
+}
+
+impl fmt::Display for UnaryOpSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                UnaryOpSymbol::Minus => "-",
+                UnaryOpSymbol::Bang => "!",
+            }
+        )
+    }
 }
@@ -0,0 +1,30 @@
+//
+// parser - snowflake's parser
+//
+// copyright (c) 2020 the snowflake authors <whiskerdev@protonmail.com>
+// this source code form is subject to the terms of the mozilla public
+// license, v. 2.0. if a copy of the mpl was not distributed with this
+// file, you can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+use crate::{ast, indentation::IndentationError};
+use num_bigint::BigInt;
+use thiserror::Error;
+
+/// the errors raised by the lexer and the grammar itself
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error(transparent)]
+    Indentation(#[from] IndentationError),
+
+    #[error("Unable to apply `{0}` to parameters as it is not a type name")]
+    NotATypeName(ast::Type),
+
+    #[error(
+        "Unable to chain `{0}` with `{1}` without parentheses, as their precedences are equal"
+    )]
+    AmbiguousOperators(ast::OpSymbol, ast::OpSymbol),
+
+    #[error("Unable to use {0} as a precedence, as precedences range from 0 to 255")]
+    InvalidPrecedence(BigInt),
+}
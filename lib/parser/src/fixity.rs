@@ -0,0 +1,325 @@
+//
+// parser - snowflake's parser
+//
+// copyright (c) 2020 the snowflake authors <whiskerdev@protonmail.com>
+// this source code form is subject to the terms of the mozilla public
+// license, v. 2.0. if a copy of the mpl was not distributed with this
+// file, you can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+use crate::{
+    ast::{Expression, OpSymbol},
+    error::Error,
+    token::Token,
+};
+use std::{collections::HashMap, convert::TryFrom};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+    /// the operator can't be chained with others of the same precedence without parentheses
+    None,
+}
+
+/// how tightly an operator binds its operands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fixity {
+    /// operators with a higher precedence bind tighter
+    pub precedence: u8,
+    pub associativity: Associativity,
+}
+
+impl Fixity {
+    pub const fn new(precedence: u8, associativity: Associativity) -> Self {
+        Fixity {
+            precedence,
+            associativity,
+        }
+    }
+}
+
+/// the fixity of operators that were never given one
+pub const DEFAULT_FIXITY: Fixity = Fixity::new(9, Associativity::Left);
+
+/// the fixities of the built-in operators
+const BUILTIN_FIXITIES: &[(OpSymbol, Fixity)] = &[
+    (OpSymbol::Dollar, Fixity::new(0, Associativity::Right)),
+    (
+        OpSymbol::PipeRAngleBracket,
+        Fixity::new(0, Associativity::Left),
+    ),
+    (OpSymbol::DoublePipe, Fixity::new(1, Associativity::Left)),
+    (
+        OpSymbol::DoubleAmpersand,
+        Fixity::new(2, Associativity::Left),
+    ),
+    (OpSymbol::DoubleEqual, Fixity::new(3, Associativity::None)),
+    (OpSymbol::BangEqual, Fixity::new(3, Associativity::None)),
+    (OpSymbol::LAngleBracket, Fixity::new(3, Associativity::None)),
+    (OpSymbol::RAngleBracket, Fixity::new(3, Associativity::None)),
+    (
+        OpSymbol::LAngleBracketEqual,
+        Fixity::new(3, Associativity::None),
+    ),
+    (
+        OpSymbol::RAngleBracketEqual,
+        Fixity::new(3, Associativity::None),
+    ),
+    (OpSymbol::Pipe, Fixity::new(4, Associativity::Left)),
+    (OpSymbol::Xor, Fixity::new(5, Associativity::Left)),
+    (OpSymbol::Ampersand, Fixity::new(6, Associativity::Left)),
+    (
+        OpSymbol::DoubleLAngleBracket,
+        Fixity::new(7, Associativity::Left),
+    ),
+    (
+        OpSymbol::DoubleRAngleBracket,
+        Fixity::new(7, Associativity::Left),
+    ),
+    (OpSymbol::Plus, Fixity::new(8, Associativity::Left)),
+    (OpSymbol::Minus, Fixity::new(8, Associativity::Left)),
+    (OpSymbol::Star, Fixity::new(9, Associativity::Left)),
+    (OpSymbol::ForwardSlash, Fixity::new(9, Associativity::Left)),
+    (OpSymbol::Percent, Fixity::new(9, Associativity::Left)),
+];
+
+/// the fixities of every operator, used to group a flat chain of operator calls
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixityTable {
+    fixities: HashMap<OpSymbol, Fixity>,
+}
+
+impl Default for FixityTable {
+    fn default() -> Self {
+        FixityTable {
+            fixities: BUILTIN_FIXITIES.iter().cloned().collect(),
+        }
+    }
+}
+
+impl FixityTable {
+    /// the fixities of the built-in operators along with those of the operators declared among the
+    /// tokens of a program, so that operators may be used before the statement declaring them.
+    /// declarations with an invalid precedence are skipped, leaving the parser to report them
+    pub fn declared<'t>(tokens: impl IntoIterator<Item = &'t Token>) -> Self {
+        let mut table = Self::default();
+        let tokens: Vec<&Token> = tokens.into_iter().collect();
+        for window in tokens.windows(3) {
+            if let [declaration, Token::Integer(precedence), Token::Operator(op)] = window {
+                let associativity = match declaration {
+                    Token::Infixl => Associativity::Left,
+                    Token::Infixr => Associativity::Right,
+                    Token::Infix => Associativity::None,
+                    _ => continue,
+                };
+                if let Ok(precedence) = u8::try_from(precedence) {
+                    table.declare(
+                        OpSymbol::Custom(op.clone()),
+                        Fixity::new(precedence, associativity),
+                    );
+                }
+            }
+        }
+        table
+    }
+
+    /// sets the fixity of an operator, replacing any it already had
+    pub fn declare(&mut self, op: OpSymbol, fixity: Fixity) {
+        self.fixities.insert(op, fixity);
+    }
+
+    pub fn fixity(&self, op: &OpSymbol) -> Fixity {
+        self.fixities.get(op).copied().unwrap_or(DEFAULT_FIXITY)
+    }
+
+    /// groups the operands of a chain such as `1 + 2 * 3`, given as its first operand followed by
+    /// each operator and the operand to its right
+    pub fn resolve(
+        &self,
+        first: Expression,
+        rest: Vec<(OpSymbol, Expression)>,
+    ) -> Result<Expression, Error> {
+        let mut operands = vec![first];
+        let mut operators: Vec<(OpSymbol, Fixity)> = vec![];
+
+        for (op, operand) in rest {
+            let fixity = self.fixity(&op);
+            while let Some((top, top_fixity)) = operators.last() {
+                if top_fixity.precedence == fixity.precedence
+                    && (top_fixity.associativity != fixity.associativity
+                        || fixity.associativity == Associativity::None)
+                {
+                    return Err(Error::AmbiguousOperators(top.clone(), op));
+                }
+
+                if top_fixity.precedence > fixity.precedence
+                    || (top_fixity.precedence == fixity.precedence
+                        && fixity.associativity == Associativity::Left)
+                {
+                    reduce(&mut operands, &mut operators);
+                } else {
+                    break;
+                }
+            }
+            operators.push((op, fixity));
+            operands.push(operand);
+        }
+
+        while !operators.is_empty() {
+            reduce(&mut operands, &mut operators);
+        }
+
+        // TODO(superwhiskers): remove expect
+        Ok(operands.pop().expect("a chain always has an operand left"))
+    }
+}
+
+/// applies the topmost operator to the two topmost operands. `$` and `|>` become a call of the
+/// operand on the side they point away from
+fn reduce(operands: &mut Vec<Expression>, operators: &mut Vec<(OpSymbol, Fixity)>) {
+    // TODO(superwhiskers): remove expect
+    let (op, _) = operators.pop().expect("there is an operator to reduce");
+    let r = operands.pop().expect("an operator has a right operand");
+    let l = operands.pop().expect("an operator has a left operand");
+    operands.push(match op {
+        OpSymbol::Dollar => Expression::apply(l, vec![r]),
+        OpSymbol::PipeRAngleBracket => Expression::apply(r, vec![l]),
+        op => Expression::OpCall {
+            op,
+            args: vec![Box::new(l), Box::new(r)],
+        },
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_bigint::BigInt;
+
+    fn int(i: isize) -> Expression {
+        Expression::Integer(BigInt::from(i))
+    }
+
+    fn op(l: Expression, op: OpSymbol, r: Expression) -> Expression {
+        Expression::OpCall {
+            op,
+            args: vec![Box::new(l), Box::new(r)],
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        use OpSymbol::*;
+        let table = FixityTable::default();
+
+        assert_eq!(
+            table.resolve(int(1), vec![(Minus, int(2)), (Minus, int(3))]),
+            Ok(op(op(int(1), Minus, int(2)), Minus, int(3)))
+        );
+        assert_eq!(
+            table.resolve(
+                int(1),
+                vec![(DoublePipe, int(2)), (DoubleAmpersand, int(3))]
+            ),
+            Ok(op(int(1), DoublePipe, op(int(2), DoubleAmpersand, int(3))))
+        );
+        assert_eq!(
+            table.resolve(int(1), vec![(Ampersand, int(2)), (DoubleEqual, int(3))]),
+            Ok(op(op(int(1), Ampersand, int(2)), DoubleEqual, int(3)))
+        );
+        assert_eq!(
+            table.resolve(
+                int(1),
+                vec![(Plus, int(2)), (Star, int(3)), (LAngleBracket, int(4))]
+            ),
+            Ok(op(
+                op(int(1), Plus, op(int(2), Star, int(3))),
+                LAngleBracket,
+                int(4)
+            ))
+        );
+        assert_eq!(
+            table.resolve(
+                int(1),
+                vec![(LAngleBracket, int(2)), (RAngleBracket, int(3))]
+            ),
+            Err(Error::AmbiguousOperators(LAngleBracket, RAngleBracket))
+        );
+    }
+
+    #[test]
+    fn test_resolve_application() {
+        use OpSymbol::*;
+        let table = FixityTable::default();
+        let name = |name: &str| Expression::Identifier(String::from(name));
+        let call = |name: &str, arg: Expression| Expression::FnCall {
+            name: String::from(name),
+            args: vec![arg],
+        };
+
+        assert_eq!(
+            table.resolve(
+                name("f"),
+                vec![(Dollar, name("g")), (Dollar, op(int(1), Plus, int(2)))]
+            ),
+            Ok(call("f", call("g", op(int(1), Plus, int(2)))))
+        );
+        assert_eq!(
+            table.resolve(
+                int(1),
+                vec![
+                    (PipeRAngleBracket, name("f")),
+                    (PipeRAngleBracket, name("g"))
+                ]
+            ),
+            Ok(call("g", call("f", int(1))))
+        );
+        assert_eq!(
+            table.resolve(
+                int(1),
+                vec![(Dollar, name("f")), (PipeRAngleBracket, name("g"))]
+            ),
+            Err(Error::AmbiguousOperators(Dollar, PipeRAngleBracket))
+        );
+    }
+
+    #[test]
+    fn test_declared() {
+        let tokens: Vec<Token> = crate::lexer::lex(indoc::indoc! {"
+            infixr 2 <+> = plus
+            infix 300 <-> = minus
+            infixl 3 <*> = times
+        "})
+        .map(|item| item.unwrap().1)
+        .collect();
+        let table = FixityTable::declared(&tokens);
+
+        let custom = |op: &str| OpSymbol::Custom(String::from(op));
+        assert_eq!(
+            table.fixity(&custom("<+>")),
+            Fixity::new(2, Associativity::Right)
+        );
+        assert_eq!(table.fixity(&custom("<->")), DEFAULT_FIXITY);
+        assert_eq!(
+            table.fixity(&custom("<*>")),
+            Fixity::new(3, Associativity::Left)
+        );
+        assert_eq!(
+            table.fixity(&OpSymbol::Plus),
+            Fixity::new(8, Associativity::Left)
+        );
+    }
+
+    #[test]
+    fn test_declare() {
+        use OpSymbol::*;
+        let mut table = FixityTable::default();
+        table.declare(Plus, Fixity::new(10, Associativity::Right));
+
+        assert_eq!(
+            table.resolve(int(1), vec![(Star, int(2)), (Plus, int(3)), (Plus, int(4))]),
+            Ok(op(int(1), Star, op(int(2), Plus, op(int(3), Plus, int(4)))))
+        );
+    }
+}
@@ -7,28 +7,148 @@
 // file, you can obtain one at http://mozilla.org/MPL/2.0/.
 //
 
+use std::ops::Range;
+use thiserror::Error;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Indentation {
     Indent,
+    /// closes the given number of levels, each of which was opened by an `Indent`
     Dedent(usize),
     Ondent,
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+/// how tabs in indentation are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tabs {
+    Reject,
+    /// expands tabs to the next multiple of the given number of columns
+    Expand(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndentationConfig {
+    /// the number of columns making up one level of indentation, inferred from the first
+    /// indented line if not set
+    pub width: Option<usize>,
+    pub tabs: Tabs,
+}
+
+impl Default for IndentationConfig {
+    fn default() -> Self {
+        IndentationConfig {
+            width: None,
+            tabs: Tabs::Reject,
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum IndentationError {
+    #[error(
+        "Indentation level {found} does not match any enclosing level, expected one of {}",
+        display_levels(.expected)
+    )]
+    Dedent {
+        found: usize,
+        expected: Vec<usize>,
+        span: Range<usize>,
+    },
+
+    #[error(
+        "Indentation of {columns} column(s) is not a multiple of the indentation width of {width}"
+    )]
+    Width {
+        columns: usize,
+        width: usize,
+        span: Range<usize>,
+    },
+
+    #[error("Tabs are not allowed in indentation")]
+    Tab { span: Range<usize> },
+}
+
+impl IndentationError {
+    /// the range of the source holding the offending indentation
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            IndentationError::Dedent { span, .. }
+            | IndentationError::Width { span, .. }
+            | IndentationError::Tab { span } => span.clone(),
+        }
+    }
+}
+
+fn display_levels(levels: &[usize]) -> String {
+    let levels: Vec<String> = levels.iter().map(|level| level.to_string()).collect();
+    levels.join(", ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct IndentationLevel {
     pub stack: Vec<usize>,
+    pub width: Option<usize>,
+    pub tabs: Tabs,
+}
+
+impl Default for IndentationLevel {
+    fn default() -> Self {
+        IndentationLevel::new()
+    }
 }
 
 impl IndentationLevel {
     pub fn new() -> IndentationLevel {
-        IndentationLevel { stack: vec![] }
+        IndentationLevel::with_config(IndentationConfig::default())
+    }
+
+    pub fn with_config(config: IndentationConfig) -> IndentationLevel {
+        IndentationLevel {
+            stack: vec![],
+            width: config.width,
+            tabs: config.tabs,
+        }
     }
 
     pub fn level(&self) -> usize {
         *self.stack.last().unwrap_or(&0)
     }
 
-    pub fn update(&mut self, level: usize) -> Result<Indentation, &'static str> {
+    /// converts the whitespace beginning a line into a level of indentation, inferring the
+    /// width of a level if this is the first indented line
+    pub fn measure(
+        &mut self,
+        whitespace: &str,
+        span: Range<usize>,
+    ) -> Result<usize, IndentationError> {
+        let mut columns = 0;
+        for c in whitespace.chars() {
+            columns = match (c, self.tabs) {
+                ('\t', Tabs::Reject) => return Err(IndentationError::Tab { span }),
+                ('\t', Tabs::Expand(stop)) => (columns / stop + 1) * stop,
+                _ => columns + 1,
+            };
+        }
+
+        if columns == 0 {
+            return Ok(0);
+        }
+        let width = *self.width.get_or_insert(columns);
+        if columns % width != 0 {
+            return Err(IndentationError::Width {
+                columns,
+                width,
+                span,
+            });
+        }
+        Ok(columns / width)
+    }
+
+    pub fn update(
+        &mut self,
+        level: usize,
+        span: Range<usize>,
+    ) -> Result<Indentation, IndentationError> {
         if level > self.level() {
             self.stack.push(level);
             Ok(Indentation::Indent)
@@ -40,7 +160,13 @@ impl IndentationLevel {
                 }
                 Ok(Indentation::Dedent(stack_level - self.stack.len()))
             } else {
-                Err("indentation level while dedenting does not match any previously indented level.")
+                Err(IndentationError::Dedent {
+                    found: level,
+                    expected: std::iter::once(0)
+                        .chain(self.stack.iter().copied())
+                        .collect(),
+                    span,
+                })
             }
         } else {
             Ok(Indentation::Ondent)
@@ -55,34 +181,80 @@ mod test {
     #[test]
     fn test_indentation() {
         let mut indentation = IndentationLevel::new();
-        assert_eq!(indentation.update(1), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(1, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 1);
-        assert_eq!(indentation.update(0), Ok(Indentation::Dedent(1)));
+        assert_eq!(indentation.update(0, 0..0), Ok(Indentation::Dedent(1)));
         assert_eq!(indentation.level(), 0);
-        assert_eq!(indentation.update(1), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(1, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 1);
-        assert_eq!(indentation.update(3), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(3, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 3);
         assert_eq!(
-            indentation.update(2),
-            Err("indentation level while dedenting does not match any previously indented level.")
+            indentation.update(2, 4..6),
+            Err(IndentationError::Dedent {
+                found: 2,
+                expected: vec![0, 1, 3],
+                span: 4..6,
+            })
         );
         assert_eq!(indentation.level(), 3);
-        assert_eq!(indentation.update(0), Ok(Indentation::Dedent(2)));
+        assert_eq!(indentation.update(0, 0..0), Ok(Indentation::Dedent(2)));
         assert_eq!(indentation.level(), 0);
     }
 
     #[test]
     fn test_indentation_stack_size() {
         let mut indentation = IndentationLevel::new();
-        assert_eq!(indentation.update(1), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(1, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 1);
-        assert_eq!(indentation.update(2), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(2, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 2);
-        assert_eq!(indentation.update(3), Ok(Indentation::Indent));
+        assert_eq!(indentation.update(3, 0..0), Ok(Indentation::Indent));
         assert_eq!(indentation.level(), 3);
         assert_eq!(indentation.stack.len(), 3);
-        assert_eq!(indentation.update(2), Ok(Indentation::Dedent(1)));
+        assert_eq!(indentation.update(2, 0..0), Ok(Indentation::Dedent(1)));
         assert_eq!(indentation.stack.len(), 2)
     }
+
+    #[test]
+    fn test_indentation_width() {
+        let mut indentation = IndentationLevel::new();
+        assert_eq!(indentation.measure("", 0..0), Ok(0));
+        assert_eq!(indentation.width, None);
+        assert_eq!(indentation.measure("    ", 0..4), Ok(1));
+        assert_eq!(indentation.width, Some(4));
+        assert_eq!(indentation.measure("        ", 0..8), Ok(2));
+        assert_eq!(
+            indentation.measure("      ", 0..6),
+            Err(IndentationError::Width {
+                columns: 6,
+                width: 4,
+                span: 0..6,
+            })
+        );
+
+        let mut indentation = IndentationLevel::with_config(IndentationConfig {
+            width: Some(2),
+            ..IndentationConfig::default()
+        });
+        assert_eq!(indentation.measure("    ", 0..4), Ok(2));
+        assert!(indentation.measure(" ", 0..1).is_err());
+    }
+
+    #[test]
+    fn test_indentation_tabs() {
+        let mut indentation = IndentationLevel::new();
+        assert_eq!(
+            indentation.measure("  \t", 0..3),
+            Err(IndentationError::Tab { span: 0..3 })
+        );
+
+        let mut indentation = IndentationLevel::with_config(IndentationConfig {
+            width: Some(4),
+            tabs: Tabs::Expand(4),
+        });
+        assert_eq!(indentation.measure("\t", 0..1), Ok(1));
+        assert_eq!(indentation.measure("  \t", 0..3), Ok(1));
+        assert_eq!(indentation.measure("\t    ", 0..5), Ok(2));
+    }
 }
@@ -1,10 +1,11 @@
-use crate::indentation;
-use crate::indentation::Indentation;
+use crate::error::Error;
+use crate::indentation::{Indentation, IndentationConfig, IndentationError, IndentationLevel};
 use crate::token::Token;
 use logos::Logos;
+use std::{collections::VecDeque, iter};
 
 pub type Spanned<Tok, Loc, Error> = Result<(Loc, Tok, Loc), Error>;
-pub type Item = Spanned<Token, usize, String>;
+pub type Item = Spanned<Token, usize, Error>;
 
 fn spanned_token_into_item(span: (Token, logos::Span)) -> Item {
     let range = span.1;
@@ -12,47 +13,107 @@ fn spanned_token_into_item(span: (Token, logos::Span)) -> Item {
     Ok((range.start, token, range.end))
 }
 
-// todo: possibly change the way this works to be part of the lexing process itself
-// todo: clean up dedent insertion code to not use Arc, and to use proper spans.
+// drops comments. doc comments are treated as comments unless they begin a line at the top level
+fn strip_comments<I>(tokens: I) -> impl Iterator<Item = (Token, logos::Span)>
+where
+    I: Iterator<Item = (Token, logos::Span)>,
+{
+    let mut top_level = true;
+    tokens.filter(move |(token, _)| match token {
+        Token::Comment => false,
+        Token::DocComment(_) => top_level,
+        Token::Indentation(whitespace) => {
+            top_level = whitespace.is_empty();
+            true
+        }
+        _ => {
+            top_level = false;
+            true
+        }
+    })
+}
+
+// converts a change in indentation to usable Indent, Dedent, and Newline tokens. every Dedent is
+// followed by a Newline, ending the line that began the block
+fn line_break(
+    indentation: &mut IndentationLevel,
+    level: usize,
+    range: logos::Span,
+) -> Result<Vec<(Token, logos::Span)>, IndentationError> {
+    let whitespace = range.start + 1..range.end;
+    Ok(match indentation.update(level, whitespace)? {
+        Indentation::Indent => vec![(Token::Newline, range.clone()), (Token::Indent, range)],
+        Indentation::Dedent(count) => {
+            let mut tokens = vec![(Token::Newline, range.clone())];
+            for _ in 0..count {
+                tokens.push((Token::Dedent, range.clone()));
+                tokens.push((Token::Newline, range.clone()));
+            }
+            tokens
+        }
+        Indentation::Ondent => vec![(Token::Newline, range)],
+    })
+}
+
 pub fn lex<'a>(source: &'a str) -> impl Iterator<Item = Item> + 'a {
-    let mut indentation = indentation::IndentationLevel::new();
-    let lexer = Token::lexer(source);
-
-    lexer
-        .spanned()
-        // todo: find a possible way to do with without a box
-        // convert Indentation tokens to usable Indent, Dedent, and Newline tokens.
-        .flat_map(move |(tok, range)| match tok {
-            Token::Indentation(level) => match indentation.update(level) {
-                Ok(indent) => match indent {
-                    Indentation::Indent => {
-                        vec![(Token::Newline, range.clone()), (Token::Indent, range)]
-                    }
-                    Indentation::Dedent(count) => {
-                        let mut tokens = vec![
-                            (Token::Newline, range.clone()),
-                            (Token::Dedent, range.clone()),
-                        ];
-                        if count > 1 {
-                            for _ in 0..count {
-                                tokens.push((Token::Dedent, range.clone()));
-                            }
-                        }
-                        tokens
-                    }
-                    Indentation::Ondent => vec![(Token::Newline, range)],
-                },
-                Err(err) => vec![(Token::Error(String::from(err)), range)],
-            },
-            _ => vec![(tok, range)],
-        })
-        .map(spanned_token_into_item)
+    lex_with_config(source, IndentationConfig::default())
+}
+
+// todo: possibly change the way this works to be part of the lexing process itself
+pub fn lex_with_config<'a>(
+    source: &'a str,
+    config: IndentationConfig,
+) -> impl Iterator<Item = Item> + 'a {
+    let mut indentation = IndentationLevel::with_config(config);
+    let mut tokens = strip_comments(Token::lexer(source).spanned()).peekable();
+    let mut pending = VecDeque::new();
+
+    let mut finished = false;
+
+    iter::from_fn(move || loop {
+        if let Some(token) = pending.pop_front() {
+            return Some(spanned_token_into_item(token));
+        }
+        if finished {
+            return None;
+        }
+
+        match tokens.next() {
+            Some((Token::Indentation(whitespace), range)) => {
+                // blank, whitespace-only and comment-only lines are skipped, so only the
+                // indentation of the line after them counts
+                if matches!(tokens.peek(), Some((Token::Indentation(_), _)) | None) {
+                    continue;
+                }
+                let line = indentation
+                    .measure(&whitespace, range.start + 1..range.end)
+                    .and_then(|level| line_break(&mut indentation, level, range));
+                match line {
+                    Ok(line) => pending.extend(line),
+                    Err(err) => return Some(Err(err.into())),
+                }
+            }
+            Some(token) => pending.push_back(token),
+            // the end of the input ends the last line and closes every block still open, one for
+            // each level left on the stack
+            None => {
+                finished = true;
+                let end = source.len();
+                for _ in indentation.stack.drain(..) {
+                    pending.push_back((Token::Newline, end..end));
+                    pending.push_back((Token::Dedent, end..end));
+                }
+            }
+        }
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::indentation::Tabs;
     use indoc::indoc;
+    use proptest::prelude::*;
 
     #[test]
     fn test_lex() {
@@ -81,14 +142,16 @@ mod test {
                 Token::Identifier(String::from("c")),
                 Token::Newline,
                 Token::Dedent,
+                Token::Newline,
                 Token::Identifier(String::from("d")),
                 Token::Newline,
                 Token::Indent,
                 Token::Identifier(String::from("e")),
                 Token::Newline,
                 Token::Dedent,
+                Token::Newline,
                 Token::Dedent,
-                Token::Dedent,
+                Token::Newline,
                 Token::Identifier(String::from("f")),
                 Token::Newline,
                 Token::Identifier(String::from("g")),
@@ -97,8 +160,8 @@ mod test {
                 Token::Symbol('_'),
                 Token::Newline,
                 Token::Dedent,
-                Token::Identifier(String::from("i")),
                 Token::Newline,
+                Token::Identifier(String::from("i")),
             ]
         )
     }
@@ -124,7 +187,7 @@ mod test {
                 Token::Identifier(String::from("c")),
                 Token::Newline,
                 Token::Dedent,
-                Token::Dedent,
+                Token::Newline,
                 Token::Dedent,
             ]
         )
@@ -156,4 +219,301 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    fn test_lex_comments() {
+        let input = indoc! {"
+            --| doc
+            a -- comment
+                -- comment
+                b
+                    --| not doc
+              {- block
+            comment -}
+            c {- block -}
+        "};
+
+        let lexed: Vec<Token> = lex(input).map(|t| t.unwrap().1).collect();
+        assert_eq!(
+            lexed,
+            vec![
+                Token::DocComment(String::from("doc")),
+                Token::Newline,
+                Token::Identifier(String::from("a")),
+                Token::Newline,
+                Token::Indent,
+                Token::Identifier(String::from("b")),
+                Token::Newline,
+                Token::Dedent,
+                Token::Newline,
+                Token::Identifier(String::from("c")),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_lex_blank_lines() {
+        let input = "\na\n  b\n    \n\n  c\n\n      d";
+
+        let lexed: Vec<Token> = lex(input).map(|t| t.unwrap().1).collect();
+        assert_eq!(
+            lexed,
+            vec![
+                Token::Newline,
+                Token::Identifier(String::from("a")),
+                Token::Newline,
+                Token::Indent,
+                Token::Identifier(String::from("b")),
+                Token::Newline,
+                Token::Identifier(String::from("c")),
+                Token::Newline,
+                Token::Indent,
+                Token::Identifier(String::from("d")),
+                Token::Newline,
+                Token::Dedent,
+                Token::Newline,
+                Token::Dedent,
+            ]
+        )
+    }
+
+    #[test]
+    fn test_lex_indentation_width() {
+        let four = "a\n    b\n        c\nd";
+        let two = "a\n  b\n    c\nd";
+        let tabs = "a\n\tb\n\t\tc\nd";
+
+        let lexed = |source, config| -> Vec<Token> {
+            lex_with_config(source, config)
+                .map(|t| t.unwrap().1)
+                .collect()
+        };
+        let expected = lexed(two, IndentationConfig::default());
+        assert_eq!(lexed(four, IndentationConfig::default()), expected);
+        assert_eq!(
+            lexed(
+                tabs,
+                IndentationConfig {
+                    width: Some(4),
+                    tabs: Tabs::Expand(4),
+                }
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_lex_indentation_errors() {
+        let error = |source, config| {
+            lex_with_config(source, config)
+                .find_map(Result::err)
+                .unwrap()
+        };
+
+        assert_eq!(
+            error("a\n  b\n   c", IndentationConfig::default()),
+            Error::Indentation(IndentationError::Width {
+                columns: 3,
+                width: 2,
+                span: 6..9,
+            })
+        );
+        assert_eq!(
+            error(
+                "a\n  b",
+                IndentationConfig {
+                    width: Some(4),
+                    ..IndentationConfig::default()
+                }
+            ),
+            Error::Indentation(IndentationError::Width {
+                columns: 2,
+                width: 4,
+                span: 2..4,
+            })
+        );
+        assert_eq!(
+            error("a\n\tb", IndentationConfig::default()),
+            Error::Indentation(IndentationError::Tab { span: 2..3 })
+        );
+        assert_eq!(
+            error("a\n  b\n      c\n    d", IndentationConfig::default()),
+            Error::Indentation(IndentationError::Dedent {
+                found: 2,
+                expected: vec![0, 1, 3],
+                span: 14..18,
+            })
+        );
+    }
+
+    // a line of a generated document, with the blocks nested beneath it
+    #[derive(Debug, Clone, PartialEq)]
+    struct Node {
+        name: String,
+        children: Vec<Node>,
+    }
+
+    // lines which are ignored by indentation tracking
+    #[derive(Debug, Clone)]
+    enum Filler {
+        Blank,
+        Whitespace(usize),
+        Comment(usize),
+    }
+
+    fn nodes() -> impl Strategy<Value = Vec<Node>> {
+        // names begin with `x` so that they are never keywords
+        let name = "x[a-z0-9_]{0,3}";
+        let leaf = name.prop_map(|name| Node {
+            name,
+            children: vec![],
+        });
+        let node = leaf.prop_recursive(5, 64, 4, move |inner| {
+            (name, prop::collection::vec(inner, 0..4))
+                .prop_map(|(name, children)| Node { name, children })
+        });
+        prop::collection::vec(node, 1..5)
+    }
+
+    fn filler() -> impl Strategy<Value = Option<Filler>> {
+        prop_oneof![
+            4 => Just(None),
+            1 => Just(Some(Filler::Blank)),
+            1 => (0..12usize).prop_map(|n| Some(Filler::Whitespace(n))),
+            1 => (0..12usize).prop_map(|n| Some(Filler::Comment(n))),
+        ]
+    }
+
+    // flattens nodes into lines of their depth, indentation and name. the children of a node are indented by
+    // one or two levels, depending on the length of its name, so deeper indents are covered too
+    fn lines(
+        nodes: &[Node],
+        depth: usize,
+        columns: usize,
+        width: usize,
+    ) -> Vec<(usize, usize, String)> {
+        let mut flattened = vec![];
+        for node in nodes {
+            flattened.push((depth, columns, node.name.clone()));
+            let indent = width * (1 + node.name.len() % 2);
+            flattened.extend(lines(&node.children, depth + 1, columns + indent, width));
+        }
+        flattened
+    }
+
+    fn render(lines: &[(usize, usize, String)], fillers: &[Option<Filler>]) -> String {
+        let mut rendered = vec![];
+        for (i, filler) in fillers.iter().enumerate() {
+            match filler {
+                None => (),
+                Some(Filler::Blank) => rendered.push(String::new()),
+                Some(Filler::Whitespace(n)) => rendered.push(" ".repeat(*n)),
+                Some(Filler::Comment(n)) => rendered.push(format!("{}-- comment", " ".repeat(*n))),
+            }
+            if let Some((_, columns, name)) = lines.get(i) {
+                rendered.push(format!("{}{}", " ".repeat(*columns), name));
+            }
+        }
+        rendered.join("\n")
+    }
+
+    // the tokens expected for lines of the given depths, following the rules for line breaks
+    // rather than the indentation stack
+    fn model(lines: &[(usize, usize, String)], leading: bool) -> Vec<Token> {
+        let mut tokens = vec![];
+        if leading {
+            tokens.push(Token::Newline);
+        }
+        let mut previous = 0;
+        for (i, (depth, _, name)) in lines.iter().enumerate() {
+            if i > 0 {
+                tokens.push(Token::Newline);
+                if *depth > previous {
+                    tokens.push(Token::Indent);
+                }
+                for _ in *depth..previous {
+                    tokens.push(Token::Dedent);
+                    tokens.push(Token::Newline);
+                }
+            }
+            tokens.push(Token::Identifier(name.clone()));
+            previous = *depth;
+        }
+        for _ in 0..previous {
+            tokens.push(Token::Newline);
+            tokens.push(Token::Dedent);
+        }
+        tokens
+    }
+
+    // rebuilds the nodes of a document from its tokens, failing if a block is closed without
+    // having been opened, or opened without a line to belong to
+    fn rebuild(tokens: &[Token]) -> Result<Vec<Node>, String> {
+        let mut blocks: Vec<Vec<Node>> = vec![vec![]];
+        for token in tokens {
+            match token {
+                Token::Identifier(name) => blocks.last_mut().unwrap().push(Node {
+                    name: name.clone(),
+                    children: vec![],
+                }),
+                Token::Indent => blocks.push(vec![]),
+                Token::Dedent => {
+                    let children = blocks.pop().unwrap();
+                    let parent = blocks
+                        .last_mut()
+                        .ok_or("a block was closed without being opened")?
+                        .last_mut()
+                        .ok_or("a block was opened without a line to belong to")?;
+                    parent.children = children;
+                }
+                Token::Newline => (),
+                token => return Err(format!("unexpected token {:?}", token)),
+            }
+        }
+        match blocks.len() {
+            1 => Ok(blocks.pop().unwrap()),
+            _ => Err(String::from("a block was never closed")),
+        }
+    }
+
+    fn document() -> impl Strategy<Value = (Vec<Node>, usize, Vec<Option<Filler>>)> {
+        (nodes(), 1..5usize).prop_flat_map(|(nodes, width)| {
+            let count = lines(&nodes, 0, 0, width).len() + 1;
+            (
+                Just(nodes),
+                Just(width),
+                prop::collection::vec(filler(), count),
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_lex_balanced((nodes, width, fillers) in document()) {
+            let lines = lines(&nodes, 0, 0, width);
+            let source = render(&lines, &fillers);
+            let config = IndentationConfig {
+                width: Some(width),
+                ..IndentationConfig::default()
+            };
+            let lexed: Vec<Token> = lex_with_config(&source, config)
+                .map(|t| t.unwrap().1)
+                .collect();
+
+            // every Dedent closes an Indent before it, and every Indent is closed
+            let mut depth = 0isize;
+            for token in &lexed {
+                match token {
+                    Token::Indent => depth += 1,
+                    Token::Dedent => depth -= 1,
+                    _ => (),
+                }
+                prop_assert!(depth >= 0);
+            }
+            prop_assert_eq!(depth, 0);
+
+            prop_assert_eq!(&lexed, &model(&lines, fillers[0].is_some()));
+            prop_assert_eq!(rebuild(&lexed), Ok(nodes));
+        }
+    }
 }
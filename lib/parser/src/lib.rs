@@ -10,22 +10,34 @@
 #[macro_use]
 extern crate lalrpop_util;
 pub mod ast;
+pub mod error;
+pub mod fixity;
 pub mod indentation;
 pub mod lexer;
 pub mod token;
 
 lalrpop_mod!(pub snowflake);
 
-// pub fn parse<'a>(
-//     input: &'a str,
-// ) -> Result<ast::Statement, lalrpop_util::ParseError<usize, token::Token, String>> {
-//     let input = token::Token::lexer(input)
-//         .spanned()
-//         .map(spanned_token_into_item);
-//     let mut indentation = indentation::IndentationLevel::new();
-//     // todo: make into ProgramParser
-//     snowflake::StatementParser::new().parse(&mut indentation, input)
-// }
+/// parses a program, reading the fixities of the operators it declares beforehand so that they
+/// may be used anywhere inside of it
+// the error is the one returned by lalrpop's parsers, which is left unboxed so that it may be
+// matched on the same way as theirs
+#[allow(clippy::result_large_err)]
+pub fn parse<I>(
+    tokens: I,
+) -> Result<Vec<ast::Statement>, lalrpop_util::ParseError<usize, token::Token, error::Error>>
+where
+    I: IntoIterator<Item = lexer::Item>,
+{
+    let tokens: Vec<lexer::Item> = tokens.into_iter().collect();
+    let fixities = fixity::FixityTable::declared(
+        tokens
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .map(|(_, token, _)| token),
+    );
+    snowflake::ProgramParser::new().parse(&fixities, tokens)
+}
 
 #[cfg(test)]
 mod test {
@@ -37,6 +49,8 @@ mod test {
     use ast::Statement;
     use ast::Tag;
     use ast::Type;
+    use ast::Variant;
+    use fixity::{Associativity, Fixity, FixityTable};
     use indoc::indoc;
     use num_bigint::BigInt;
     use snowflake::*;
@@ -88,7 +102,7 @@ mod test {
         ($path:ty where $($input:expr => $test:expr),*) => {
             $({
                 let input = lexer::lex($input);
-                let program = <$path>::new().parse(input).unwrap();
+                let program = <$path>::new().parse(&FixityTable::default(), input).unwrap();
                 assert_eq!(program, $test)
             })*
         };
@@ -112,7 +126,10 @@ mod test {
             "name" =>
                 String::from("name"),
             "name_with_underscores_numbers_and_is_long_1234" =>
-                String::from("name_with_underscores_numbers_and_is_long_1234")
+                String::from("name_with_underscores_numbers_and_is_long_1234"),
+            "x0" => String::from("x0"),
+            "größe" => String::from("größe"),
+            "λ_2" => String::from("λ_2")
         }
     }
 
@@ -121,7 +138,10 @@ mod test {
         test_parse! {
           IntegerParser where
           "132" => BigInt::from(132),
-          "123_456_789" => BigInt::from(123_456_789)
+          "123_456_789" => BigInt::from(123_456_789),
+          "0x1F" => BigInt::from(31),
+          "0o7_7" => BigInt::from(63),
+          "0b101" => BigInt::from(5)
         }
     }
 
@@ -140,7 +160,45 @@ mod test {
           ),
           "name_with_underscores_numbers_and_is_long_1234" => Expression::Identifier(
             String::from("name_with_underscores_numbers_and_is_long_1234")
-          )
+          ),
+          "0xffu8" => Expression::TypeDecl {
+            ty: Type::Application {
+              name: "uint".into(),
+              args: vec![8.into()]
+            },
+            expr: Box::new(255.into())
+          },
+          "1_000i32" => Expression::TypeDecl {
+            ty: Type::Application {
+              name: "int".into(),
+              args: vec![32.into()]
+            },
+            expr: Box::new(1000.into())
+          },
+          "1.5" => Expression::Float(ast::Float(1.5)),
+          "2e3f32" => Expression::TypeDecl {
+            ty: Type::Identifier("f32".into()),
+            expr: Box::new(Expression::Float(ast::Float(2e3)))
+          },
+          "'\\n'" => Expression::Char('\n'),
+          "'λ'" => Expression::Char('λ'),
+          "b'x'" => Expression::byte(b'x'),
+          "b\"hi\"" => Expression::TypeDecl {
+            ty: Type::Application {
+              name: "list".into(),
+              args: vec![
+                2.into(),
+                Type::Application {
+                  name: "uint".into(),
+                  args: vec![8.into()]
+                }
+              ]
+            },
+            expr: Box::new(Expression::List(vec![
+              Box::new(Expression::byte(b'h')),
+              Box::new(Expression::byte(b'i'))
+            ]))
+          }
         }
     }
 
@@ -155,17 +213,187 @@ mod test {
                     Box::new(1.into()),
                 ]
             },
-            // should parse as
-            // (1 + (2 * (3 - (4 / 5))))
-            // although alternatively it could be made to parse like
-            // ((((1 + 2) * 3) - 4) / 5)
+            // multiplication and division bind tighter than addition and subtraction, and
+            // each of them groups to the left
+            // ((1 + (2 * 3)) - (4 / 5))
             "1 + 2 * 3 - 4 / 5" => {
                 use ast::OpSymbol::*;
-                ops(1, Plus, ops(2, Star, ops(3, Minus, ops(4, ForwardSlash, 5))))
+                ops(ops(1, Plus, ops(2, Star, 3)), Minus, ops(4, ForwardSlash, 5))
+            },
+            "8 - 4 - 2" => {
+                use ast::OpSymbol::*;
+                ops(ops(8, Minus, 4), Minus, 2)
+            },
+            "a + 1 < b * 2" => {
+                use ast::OpSymbol::*;
+                ops(ops("a", Plus, 1), LAngleBracket, ops("b", Star, 2))
+            },
+            "(1 - 2) * 3" => {
+                use ast::OpSymbol::*;
+                ops(ops(1, Minus, 2), Star, 3)
+            },
+            "n % 2 == 0 && a <= b || c != d" => {
+                use ast::OpSymbol::*;
+                ops(
+                    ops(
+                        ops(ops("n", Percent, 2), DoubleEqual, 0),
+                        DoubleAmpersand,
+                        ops("a", LAngleBracketEqual, "b")
+                    ),
+                    DoublePipe,
+                    ops("c", BangEqual, "d")
+                )
+            },
+            "a & 1 << 4 | b ^ c >> 2" => {
+                use ast::OpSymbol::*;
+                ops(
+                    ops("a", Ampersand, ops(1, DoubleLAngleBracket, 4)),
+                    Pipe,
+                    ops("b", Xor, ops("c", DoubleRAngleBracket, 2))
+                )
+            }
+        }
+        assert_eq!(
+            OpCallParser::new().parse(&FixityTable::default(), lexer::lex("a < b > c")),
+            Err(lalrpop_util::ParseError::User {
+                error: error::Error::AmbiguousOperators(
+                    ast::OpSymbol::LAngleBracket,
+                    ast::OpSymbol::RAngleBracket
+                )
+            })
+        );
+    }
+
+    #[test]
+    fn parse_unary_op_call() {
+        fn unary(op: ast::UnaryOpSymbol, arg: impl Into<ast::Expression>) -> ast::Expression {
+            ast::Expression::UnaryOpCall {
+                op,
+                arg: Box::new(arg.into()),
+            }
+        }
+
+        test_parse! {
+            ExpressionParser where
+            "-1" => unary(ast::UnaryOpSymbol::Minus, 1),
+            "-a * b" => ops(unary(ast::UnaryOpSymbol::Minus, "a"), OpSymbol::Star, "b"),
+            "a - -b" => ops("a", OpSymbol::Minus, unary(ast::UnaryOpSymbol::Minus, "b")),
+            "!!done || x" => ops(
+                unary(ast::UnaryOpSymbol::Bang, unary(ast::UnaryOpSymbol::Bang, "done")),
+                OpSymbol::DoublePipe,
+                "x"
+            ),
+            // application binds tighter than any operator, so arguments beginning with a prefix
+            // operator must be parenthesized
+            "f a - 1" => ops(
+                Expression::FnCall {
+                    name: "f".into(),
+                    args: vec!["a".into()]
+                },
+                OpSymbol::Minus,
+                1
+            ),
+            "-f a" => unary(
+                ast::UnaryOpSymbol::Minus,
+                Expression::FnCall {
+                    name: "f".into(),
+                    args: vec!["a".into()]
+                }
+            ),
+            "f (-1)" => Expression::FnCall {
+                name: "f".into(),
+                args: vec![unary(ast::UnaryOpSymbol::Minus, 1)]
+            }
+        }
+    }
+
+    #[test]
+    fn parse_section() {
+        fn section(
+            op: OpSymbol,
+            l: Option<ast::Expression>,
+            r: Option<ast::Expression>,
+        ) -> ast::Expression {
+            ast::Expression::Section {
+                op,
+                l: l.map(Box::new),
+                r: r.map(Box::new),
+            }
+        }
+
+        test_parse! {
+            ExpressionParser where
+            "(+ 1)" => section(OpSymbol::Plus, None, Some(1.into())),
+            "(2 *)" => section(OpSymbol::Star, Some(2.into()), None),
+            "(-)" => section(OpSymbol::Minus, None, None),
+            "(<+> x)" => section(OpSymbol::Custom("<+>".into()), None, Some("x".into())),
+            "(f x +)" => section(
+                OpSymbol::Plus,
+                Some(Expression::FnCall {
+                    name: "f".into(),
+                    args: vec!["x".into()]
+                }),
+                None
+            ),
+            // `-` followed by an operand is a negation
+            "(- 1)" => ast::Expression::UnaryOpCall {
+                op: ast::UnaryOpSymbol::Minus,
+                arg: Box::new(1.into()),
+            },
+            "map xs (* 2)" => Expression::FnCall {
+                name: "map".into(),
+                args: vec!["xs".into(), section(OpSymbol::Star, None, Some(2.into()))]
             }
         }
     }
 
+    #[test]
+    fn parse_operator_decl() {
+        let custom = |op: &str| OpSymbol::Custom(op.into());
+        let program = parse(lexer::lex(indoc! {"
+            f => a <+> b <*> c <+> d
+            infixl 6 <+> = plus
+            infixl 7 <*> = times
+        "}))
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Statement::FnDecl {
+                    name: "f".into(),
+                    args: vec![],
+                    body: vec![Box::new(ops(
+                        ops("a", custom("<+>"), ops("b", custom("<*>"), "c")),
+                        custom("<+>"),
+                        "d"
+                    ))]
+                },
+                Statement::OperatorDecl {
+                    op: custom("<+>"),
+                    fixity: Fixity::new(6, Associativity::Left),
+                    function: "plus".into(),
+                },
+                Statement::OperatorDecl {
+                    op: custom("<*>"),
+                    fixity: Fixity::new(7, Associativity::Left),
+                    function: "times".into(),
+                },
+            ]
+        );
+
+        // undeclared operators share a fixity with `*`
+        test_parse! {
+            ExpressionParser where
+            "a + b <> c" => ops("a", OpSymbol::Plus, ops("b", custom("<>"), "c"))
+        }
+        assert_eq!(
+            parse(lexer::lex("infixr 256 <+> = plus")),
+            Err(lalrpop_util::ParseError::User {
+                error: error::Error::InvalidPrecedence(BigInt::from(256))
+            })
+        );
+    }
+
     #[test]
     fn parse_block() {
         test_parse! {
@@ -240,10 +468,34 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_lambda() {
+        fn lambda(args: &[&str], body: ast::Expression) -> ast::Expression {
+            ast::Expression::Lambda {
+                args: args.iter().map(|&arg| arg.into()).collect(),
+                body: vec![Box::new(body)],
+            }
+        }
+
+        test_parse! {
+            ExpressionParser where
+            r"\x y => x + y" => lambda(&["x", "y"], ops("x", OpSymbol::Plus, "y")),
+            r"\x => \y => x" => lambda(&["x"], lambda(&["y"], "x".into())),
+            r"map xs (\x => x * 2)" => Expression::FnCall {
+                name: "map".into(),
+                args: vec!["xs".into(), lambda(&["x"], ops("x", OpSymbol::Star, 2))]
+            },
+            r"(\x => x) 1" => Expression::Apply {
+                callee: Box::new(lambda(&["x"], "x".into())),
+                args: vec![1.into()]
+            }
+        }
+    }
+
     #[test]
     fn parse_fn_call() {
         test_parse! {
-            FnCallParser where
+            ApplicationParser where
             "add 1 2" => Expression::FnCall {
                 name: "add".into(),
                 args: vec![
@@ -265,6 +517,65 @@ mod test {
                     "b".into(),
                     "c".into(),
                 ]
+            },
+            "println 1.5" => Expression::FnCall {
+                name: "println".into(),
+                args: vec![Expression::Float(ast::Float(1.5))]
+            }
+        }
+    }
+
+    #[test]
+    fn parse_application() {
+        fn call(name: &str, args: Vec<ast::Expression>) -> ast::Expression {
+            ast::Expression::FnCall {
+                name: name.into(),
+                args,
+            }
+        }
+
+        test_parse! {
+            ExpressionParser where
+            "println fib 5" => call("println", vec!["fib".into(), 5.into()]),
+            "println (fib 5)" => call("println", vec![call("fib", vec![5.into()])]),
+            "(choose f g) x" => ast::Expression::Apply {
+                callee: Box::new(call("choose", vec!["f".into(), "g".into()])),
+                args: vec!["x".into()]
+            },
+            "(* 2) 4" => ast::Expression::Apply {
+                callee: Box::new(ast::Expression::Section {
+                    op: OpSymbol::Star,
+                    l: None,
+                    r: Some(Box::new(2.into()))
+                }),
+                args: vec![4.into()]
+            },
+            "f a * g b" => ops(
+                call("f", vec!["a".into()]),
+                OpSymbol::Star,
+                call("g", vec!["b".into()])
+            ),
+            // `$` groups to the right and `|>` to the left, both binding looser than any other
+            // operator
+            "println $ fib $ n - 1" => call(
+                "println",
+                vec![call("fib", vec![ops("n", OpSymbol::Minus, 1)])]
+            ),
+            "n - 1 |> fib |> println" => call(
+                "println",
+                vec![call("fib", vec![ops("n", OpSymbol::Minus, 1)])]
+            ),
+            "choose f g $ x" => ast::Expression::Apply {
+                callee: Box::new(call("choose", vec!["f".into(), "g".into()])),
+                args: vec!["x".into()]
+            },
+            "x |> (+ 1)" => ast::Expression::Apply {
+                callee: Box::new(ast::Expression::Section {
+                    op: OpSymbol::Plus,
+                    l: None,
+                    r: Some(Box::new(1.into()))
+                }),
+                args: vec!["x".into()]
             }
         }
     }
@@ -279,6 +590,19 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_list() {
+        test_parse! {
+            ExpressionParser where
+            "[]" => Expression::List(vec![]),
+            "[1, a]" => Expression::List(vec![Box::new(1.into()), Box::new("a".into())]),
+            "push [] 1" => Expression::FnCall {
+                name: "push".into(),
+                args: vec![Expression::List(vec![]), 1.into()]
+            }
+        }
+    }
+
     #[test]
     fn parse_statement() {
         test_parse! {
@@ -341,7 +665,7 @@ mod test {
         "};
 
         let input = lexer::lex(bad_example);
-        let _program = ProgramParser::new().parse(input).unwrap();
+        let _program = parse(input).unwrap();
         // assert_eq!(program.is_err(), false)
     }
 
@@ -352,19 +676,18 @@ mod test {
             cat_function dog_function
         "};
         let input = lexer::lex(bad_example);
-        let _program = ExpressionParser::new().parse(input).unwrap();
+        let _program = ExpressionParser::new()
+            .parse(&FixityTable::default(), input)
+            .unwrap();
         // assert_eq!(program.is_err(), false)
     }
     
     #[test]
     fn assignment_test() {
-        // todo: remove \n requirement after certain expr/statement
         let assign_input = indoc! {"
         add a =>
             let b = 0 in
                 a + b
-            
-        
         "};
 
         test_parse! {
@@ -394,19 +717,16 @@ mod test {
             fib :: isize -> isize
         "};
 
-        // todo: add end of input newline/dedent insertions for situations like this.
         // todo: add a way to match n
         let fn_decl_input = indoc! {"
             fib n =>
-                (fib n - 1) + (fib n - 2)
-            
+                fib (n - 1) + fib (n - 2)
         "};
 
         let full_input = indoc! {"
             fib :: isize -> isize
             fib n =>
-                (fib n - 1) + (fib n - 2)
-
+                fib (n - 1) + fib (n - 2)
         "};
 
         test_parse! {
@@ -481,6 +801,86 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_blank_lines() {
+        // the blank lines hold trailing spaces, and the input ends within two blocks
+        let input = "add a b =>\n  let c = a + b\n    \n\n  match c =>\n    0 => a\n  \n    _ => c";
+
+        test_parse! {
+            ProgramParser where
+            input => vec![
+                Statement::FnDecl {
+                    name: "add".into(),
+                    args: vec!["a".into(), "b".into()],
+                    body: vec![
+                        Box::new(Expression::ValueDecl {
+                            assigns: vec![Box::new(Expression::ValueAssign {
+                                pat: "c".into(),
+                                expr: Box::new(ops("a", OpSymbol::Plus, "b"))
+                            })],
+                            body: None
+                        }),
+                        Box::new(Expression::Match {
+                            expr: Box::new("c".into()),
+                            args: vec![
+                                Expression::Destructure {
+                                    pat: 0.into(),
+                                    body: vec![Box::new("a".into())]
+                                },
+                                Expression::Destructure {
+                                    pat: Pattern::Wildcard,
+                                    body: vec![Box::new("c".into())]
+                                },
+                            ]
+                        })
+                    ]
+                }
+            ]
+        }
+    }
+
+    #[test]
+    fn parse_comments() {
+        let input = indoc! {"
+            -- a line comment
+            --| adds two numbers
+            --|
+            --| {- not a block comment -}
+            add :: int int -> int
+            add a b =>
+                -- comments don't affect indentation
+              {- nor do
+            block comments -}
+                a + b {- -- -}
+
+        "};
+
+        test_parse! {
+            ProgramParser where
+            input => vec![
+                Statement::Documented {
+                    docs: vec![
+                        "adds two numbers".into(),
+                        "".into(),
+                        "{- not a block comment -}".into(),
+                    ],
+                    statement: Box::new(Statement::TypeDecl {
+                        name: "add".into(),
+                        body: Type::FnSig {
+                            args: vec![Box::new("int".into()), Box::new("int".into())],
+                            ret: Box::new("int".into())
+                        }
+                    })
+                },
+                Statement::FnDecl {
+                    name: "add".into(),
+                    args: vec!["a".into(), "b".into()],
+                    body: vec![Box::new(ops("a", OpSymbol::Plus, "b"))]
+                }
+            ]
+        }
+    }
+
     // TypeExpression tests.
 
     #[test]
@@ -509,6 +909,63 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_type_application() {
+        test_parse! {
+            TypeExpressionParser where
+            "int 32" => Type::Application {
+                name: "int".into(),
+                args: vec![32.into()]
+            },
+            "list 4 (int 8)" => Type::Application {
+                name: "list".into(),
+                args: vec![
+                    4.into(),
+                    Type::Application {
+                        name: "int".into(),
+                        args: vec![8.into()]
+                    }
+                ]
+            },
+            "(list 4 int) -> int 32" => Type::FnSig {
+                args: vec![Box::new(Type::Application {
+                    name: "list".into(),
+                    args: vec![4.into(), "int".into()]
+                })],
+                ret: Box::new(Type::Application {
+                    name: "int".into(),
+                    args: vec![32.into()]
+                })
+            }
+        }
+
+        let input = lexer::lex("4 int");
+        assert!(TypeExpressionParser::new()
+            .parse(&FixityTable::default(), input)
+            .is_err());
+    }
+
+    #[test]
+    fn parse_type_variable() {
+        test_parse! {
+            TypeExpressionParser where
+            "'a -> 'a" => Type::FnSig {
+                args: vec![Box::new(Type::Variable("a".into()))],
+                ret: Box::new(Type::Variable("a".into()))
+            },
+            "(list 'n 'elem) -> int 'n" => Type::FnSig {
+                args: vec![Box::new(Type::Application {
+                    name: "list".into(),
+                    args: vec![Type::Variable("n".into()), Type::Variable("elem".into())]
+                })],
+                ret: Box::new(Type::Application {
+                    name: "int".into(),
+                    args: vec![Type::Variable("n".into())]
+                })
+            }
+        }
+    }
+
     #[test]
     fn parse_type_decl() {
         test_parse! {
@@ -556,6 +1013,79 @@ mod test {
                         ops(1, OpSymbol::Plus, 1)
                     )
                 ]
+            },
+            "'a'..'z' => 1\n" => Expression::Destructure {
+                pat: Pattern::Range {
+                    start: Some(Box::new(Pattern::Char('a'))),
+                    end: Some(Box::new(Pattern::Char('z')))
+                },
+                body: vec![Box::new(1.into())]
+            },
+            "b'x' => 1\n" => Expression::Destructure {
+                pat: Pattern::Integer(BigInt::from(b'x')),
+                body: vec![Box::new(1.into())]
+            }
+        }
+    }
+
+    #[test]
+    fn parse_constructor_pattern() {
+        test_parse! {
+            MatchPartParser where
+            "circle r => r\n" => Expression::Destructure {
+                pat: Pattern::Constructor {
+                    name: "circle".into(),
+                    args: vec!["r".into()]
+                },
+                body: vec![Box::new("r".into())]
+            },
+            "some (rect 0 _) => 0\n" => Expression::Destructure {
+                pat: Pattern::Constructor {
+                    name: "some".into(),
+                    args: vec![Pattern::Constructor {
+                        name: "rect".into(),
+                        args: vec![0.into(), Pattern::Wildcard]
+                    }]
+                },
+                body: vec![Box::new(0.into())]
+            },
+            "none => 0\n" => Expression::Destructure {
+                pat: "none".into(),
+                body: vec![Box::new(0.into())]
+            }
+        }
+    }
+
+    #[test]
+    fn parse_variant_decl() {
+        test_parse! {
+            VariantDeclParser where
+            "type shape = circle int | rect int int | empty" => Statement::VariantDecl {
+                name: "shape".into(),
+                variants: vec![
+                    Variant {
+                        name: "circle".into(),
+                        fields: vec!["int".into()]
+                    },
+                    Variant {
+                        name: "rect".into(),
+                        fields: vec!["int".into(), "int".into()]
+                    },
+                    Variant {
+                        name: "empty".into(),
+                        fields: vec![]
+                    }
+                ]
+            },
+            "type wrapper = wrap (int -> int)" => Statement::VariantDecl {
+                name: "wrapper".into(),
+                variants: vec![Variant {
+                    name: "wrap".into(),
+                    fields: vec![Type::FnSig {
+                        args: vec![Box::new("int".into())],
+                        ret: Box::new("int".into())
+                    }]
+                }]
             }
         }
     }
@@ -649,4 +1179,19 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn display_pattern() {
+        let pats = [
+            Pattern::StringLiteral("\"foo\"".into()),
+            Pattern::Identifier("foo".into()),
+            Pattern::Char('f'),
+            Pattern::Constructor {
+                name: "some".into(),
+                args: vec![Pattern::StringLiteral("\"a b\"".into())],
+            },
+        ];
+        let displayed: Vec<String> = pats.iter().map(|pat| pat.to_string()).collect();
+        assert_eq!(displayed, vec![r#""foo""#, "foo", "'f'", r#"some "a b""#]);
+    }
 }
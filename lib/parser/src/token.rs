@@ -7,30 +7,219 @@
 // file, you can obtain one at http://mozilla.org/MPL/2.0/.
 //
 
+use crate::ast;
 use logos::Logos;
 use num_bigint::BigInt;
+use std::convert::TryFrom;
 
 fn lex_char(lex: &mut logos::Lexer<Token>) -> Option<char> {
-    lex.source().chars().nth(lex.span().start)
+    lex.slice().chars().next()
+}
+
+// parses an integer in any of the supported bases, ignoring underscores
+fn parse_integer(text: &str) -> Option<BigInt> {
+    let (radix, digits) = match text.get(..2) {
+        Some("0x") => (16, &text[2..]),
+        Some("0o") => (8, &text[2..]),
+        Some("0b") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    let digits: String = digits.chars().filter(|&c| c != '_').collect();
+    BigInt::parse_bytes(digits.as_bytes(), radix)
+}
+
+fn lex_integer(lex: &mut logos::Lexer<Token>) -> Option<BigInt> {
+    parse_integer(lex.slice())
+}
+
+// splits an integer from its suffix, which becomes the type of the literal. `i` suffixes are
+// signed integers, and `u` suffixes unsigned ones
+fn lex_suffixed_integer(lex: &mut logos::Lexer<Token>) -> Option<(BigInt, ast::Type)> {
+    let slice = lex.slice();
+    let (digits, suffix) = slice.split_at(slice.rfind(['i', 'u'])?);
+    let name = if suffix.starts_with('i') {
+        "int"
+    } else {
+        "uint"
+    };
+    Some((
+        parse_integer(digits)?,
+        ast::Type::Application {
+            name: String::from(name),
+            args: vec![ast::Type::Nat(suffix[1..].parse().ok()?)],
+        },
+    ))
+}
+
+fn parse_float(text: &str) -> Option<f64> {
+    text.chars()
+        .filter(|&c| c != '_')
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+fn lex_float(lex: &mut logos::Lexer<Token>) -> Option<f64> {
+    parse_float(lex.slice())
+}
+
+// splits a float from its suffix, which names the type of the literal
+fn lex_suffixed_float(lex: &mut logos::Lexer<Token>) -> Option<(f64, ast::Type)> {
+    let slice = lex.slice();
+    let (digits, suffix) = slice.split_at(slice.rfind('f')?);
+    Some((
+        parse_float(digits)?,
+        ast::Type::Identifier(String::from(suffix)),
+    ))
+}
+
+// skips the rest of a block comment, which may contain other block comments, failing if it is
+// never closed
+fn lex_block_comment(lex: &mut logos::Lexer<Token>) -> bool {
+    let remainder = lex.remainder();
+    let mut depth = 1;
+    let mut offset = 0;
+    while let Some(c) = remainder[offset..].chars().next() {
+        let rest = &remainder[offset..];
+        if rest.starts_with("{-") {
+            depth += 1;
+            offset += 2;
+        } else if rest.starts_with("-}") {
+            depth -= 1;
+            offset += 2;
+            if depth == 0 {
+                lex.bump(offset);
+                return true;
+            }
+        } else {
+            offset += c.len_utf8();
+        }
+    }
+    lex.bump(remainder.len());
+    false
+}
+
+// reads the characters of a char or byte literal, resolving escapes such as `\n` and `\u{3bb}`
+fn unescape(text: &str) -> Option<Vec<char>> {
+    let mut chars = text.chars();
+    let mut unescaped = vec![];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                char::from(u8::from_str_radix(&digits, 16).ok()?)
+            }
+            'u' => {
+                let digits: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                std::char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?
+            }
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+// reads a char literal, which must hold exactly one character
+fn lex_char_literal(lex: &mut logos::Lexer<Token>) -> Option<char> {
+    let slice = lex.slice();
+    match unescape(&slice[1..slice.len() - 1])?.as_slice() {
+        [c] => Some(*c),
+        _ => None,
+    }
+}
+
+// reads the bytes of a byte or byte string literal, dropping its prefix and quotes
+fn lex_bytes(lex: &mut logos::Lexer<Token>) -> Option<Vec<u8>> {
+    let slice = lex.slice();
+    unescape(&slice[2..slice.len() - 1])?
+        .into_iter()
+        .map(|c| u8::try_from(u32::from(c)).ok())
+        .collect()
+}
+
+// retrieves the text of a doc comment, without the space following `--|`
+fn lex_doc_comment(lex: &mut logos::Lexer<Token>) -> String {
+    let text = &lex.slice()[3..];
+    String::from(text.strip_prefix(' ').unwrap_or(text).trim_end())
 }
 
 #[derive(Logos, Clone, Debug, PartialEq)]
 pub enum Token {
-    #[regex("[a-zA-Z][a-zA-Z1-9_]*", |lex| lex.slice().parse())]
+    // identifiers follow unicode's XID properties, apart from not beginning with an underscore
+    #[regex(r"\p{XID_Start}\p{XID_Continue}*", |lex| lex.slice().parse())]
     Identifier(String),
 
-    #[regex(r"[0-9]+\.[0-9]+", |lex| lex.slice().parse())]
+    #[regex(r"'\p{XID_Start}\p{XID_Continue}*", |lex| lex.slice()[1..].parse())]
+    TypeVariable(String),
+
+    #[regex(r"[0-9][0-9_]*\.[0-9][0-9_]*([eE][+-]?[0-9_]+)?", lex_float)]
+    #[regex(r"[0-9][0-9_]*[eE][+-]?[0-9_]+", lex_float)]
     Float(f64),
 
-    #[regex("[0-9][0-9_]*", |lex| lex.slice().parse())]
+    // a float along with the type from its suffix, e.g. `1.0f32`
+    #[regex(
+        r"[0-9][0-9_]*(\.[0-9][0-9_]*)?([eE][+-]?[0-9_]+)?f(32|64)",
+        lex_suffixed_float
+    )]
+    SuffixedFloat((f64, ast::Type)),
+
+    #[regex("[0-9][0-9_]*", lex_integer)]
+    #[regex("0x[0-9a-fA-F_]+", lex_integer)]
+    #[regex("0o[0-7_]+", lex_integer)]
+    #[regex("0b[01_]+", lex_integer)]
     Integer(BigInt),
+
+    // an integer along with the type from its suffix, e.g. `42u8`
+    #[regex(
+        "([0-9][0-9_]*|0x[0-9a-fA-F_]+|0o[0-7_]+|0b[01_]+)[iu][0-9]+",
+        lex_suffixed_integer
+    )]
+    SuffixedInteger((BigInt, ast::Type)),
     // todo: remove ""
     #[regex("\"[^\"]+\"", |lex| lex.slice().parse())]
     StringLiteral(String),
 
-    // replaced with inserted tokens
-    #[regex("\n(  )*", |lex| ((lex.slice().len() - 1) / 2))]
-    Indentation(usize),
+    // a character, e.g. `'a'` or `'\n'`
+    //
+    // identifier starts are matched separately from other characters, as logos otherwise mistakes
+    // non-ascii characters for the beginning of a type variable
+    #[regex(
+        r#"'(\p{XID_Start}|[^'\\\n\p{XID_Start}]|\\([nrt0\\'"]|x[0-7][0-9a-fA-F]|u\{[0-9a-fA-F]+\}))'"#,
+        lex_char_literal
+    )]
+    Char(char),
+
+    // an ascii character or escaped byte, e.g. `b'a'` or `b'\xff'`
+    #[regex(r#"b'([ -&(-\[\]-~]|\\([nrt0\\'"]|x[0-9a-fA-F][0-9a-fA-F]))'"#, |lex| lex_bytes(lex).map(|bytes| bytes[0]))]
+    Byte(u8),
+
+    // a string of ascii characters or escaped bytes, e.g. `b"\x00abc"`
+    #[regex(
+        r#"b"([ !#-\[\]-~]|\\([nrt0\\'"]|x[0-9a-fA-F][0-9a-fA-F]))*""#,
+        lex_bytes
+    )]
+    ByteString(Vec<u8>),
+
+    // replaced with inserted tokens, holding the whitespace beginning the line
+    #[regex("\n[ \t]*", |lex| String::from(&lex.slice()[1..]))]
+    Indentation(String),
+
+    // removed by the lexer, along with the indentation of lines holding nothing else
+    #[regex("--([^|\n][^\n]*)?")]
+    #[token("{-", lex_block_comment)]
+    Comment,
+
+    // only kept by the lexer when it begins a line at the top level
+    #[regex("--\\|[^\n]*", lex_doc_comment)]
+    DocComment(String),
 
     #[token("match")]
     Match,
@@ -44,6 +233,18 @@ pub enum Token {
     #[token("tag")]
     Tag,
 
+    #[token("type")]
+    Type,
+
+    #[token("infixl")]
+    Infixl,
+
+    #[token("infixr")]
+    Infixr,
+
+    #[token("infix")]
+    Infix,
+
     // todo: eventually give proper names to some of these
     // "non-symbol" character combinations
     #[token("=")]
@@ -64,6 +265,38 @@ pub enum Token {
     #[token("->")]
     SmallArrowRight,
 
+    #[token("==")]
+    EqualEqual,
+
+    #[token("!=")]
+    BangEqual,
+
+    #[token("<=")]
+    LAngleEqual,
+
+    #[token(">=")]
+    RAngleEqual,
+
+    #[token("<<")]
+    LAngleLAngle,
+
+    #[token(">>")]
+    RAngleRAngle,
+
+    #[token("&&")]
+    AmpersandAmpersand,
+
+    #[token("||")]
+    PipePipe,
+
+    #[token("|>")]
+    PipeRAngle,
+
+    // a sequence of symbols that isn't one of the above, naming a user-defined operator such as
+    // `<+>`. these may not begin with `!`, so that `!!x` remains a double negation
+    #[regex(r"[$%&*+\-/<=>?@^|~][!$%&*+\-/<=>?@^|~]+", |lex| String::from(lex.slice()))]
+    Operator(String),
+
     // todo: remove the need for this
     #[token("#{")]
     TagStart,
@@ -74,7 +307,9 @@ pub enum Token {
     #[regex(r"[!-/:-@\[-`{-~()_<>]", lex_char)]
     Symbol(char),
 
-    #[regex(r".", lex_char, priority = 0)]
+    // any character that can't begin an identifier. `.` overlaps with the identifier classes, which
+    // leads logos to match only part of a multibyte character
+    #[regex(r"\P{XID_Start}", lex_char, priority = 0)]
     Unknown(char),
 
     #[error]
@@ -84,7 +319,6 @@ pub enum Token {
     Newline,
     Indent,
     Dedent,
-    Error(String),
 }
 
 #[cfg(test)]
@@ -114,19 +348,19 @@ mod tests {
                 Identifier(String::from("isize")),
                 SmallArrowRight,
                 Identifier(String::from("isize")),
-                Indentation(0),
+                Indentation(String::new()),
                 Identifier(String::from("fib")),
                 Identifier(String::from("n")),
                 LargeArrowRight,
                 Match,
                 Identifier(String::from("n")),
                 LargeArrowRight,
-                Indentation(1),
+                Indentation(String::from("  ")),
                 DotDot,
                 Integer(BigInt::from(2)),
                 LargeArrowRight,
                 Identifier(String::from("n")),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Symbol('_'),
                 LargeArrowRight,
                 Symbol('('),
@@ -143,18 +377,44 @@ mod tests {
                 Integer(BigInt::from(2)),
                 Symbol(')'),
                 // the final newline is from indoc
-                Indentation(0),
-                Indentation(0),
+                Indentation(String::new()),
+                Indentation(String::new()),
                 Identifier(String::from("main")),
                 LargeArrowRight,
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Identifier(String::from("println")),
                 StringLiteral(String::from("\"Hello World!\"")),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Identifier(String::from("println")),
                 Identifier(String::from("fib")),
                 Integer(BigInt::from(5)),
-                Indentation(0),
+                Indentation(String::new()),
+            ]
+        )
+    }
+
+    #[test]
+    fn lex_comment_test() {
+        let source = indoc! {"
+            --| doc
+            a -- comment
+            {- block {- nested -} -} b ---| not doc
+            {- unterminated
+        "};
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                DocComment(String::from("doc")),
+                Indentation(String::new()),
+                Identifier(String::from("a")),
+                Comment,
+                Indentation(String::new()),
+                Comment,
+                Identifier(String::from("b")),
+                Comment,
+                Indentation(String::new()),
+                LexError,
             ]
         )
     }
@@ -176,21 +436,184 @@ mod tests {
             vec![
                 Identifier(String::from("block")),
                 LargeArrowRight,
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Integer(BigInt::from(123)),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Identifier(String::from("abc")),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Integer(BigInt::from(123)),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 In,
-                Indentation(1),
+                Indentation(String::from("  ")),
                 TagStart,
                 Symbol('}'),
-                Indentation(1),
+                Indentation(String::from("  ")),
                 Tag,
-                Indentation(0),
+                Indentation(String::new()),
             ]
         )
     }
+
+    #[test]
+    fn lex_operator_test() {
+        let source = "a == b != c <= d >= e << f >> g && !h || -i % j & k | l ^ m => n = o";
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Identifier(String::from("a")),
+                EqualEqual,
+                Identifier(String::from("b")),
+                BangEqual,
+                Identifier(String::from("c")),
+                LAngleEqual,
+                Identifier(String::from("d")),
+                RAngleEqual,
+                Identifier(String::from("e")),
+                LAngleLAngle,
+                Identifier(String::from("f")),
+                RAngleRAngle,
+                Identifier(String::from("g")),
+                AmpersandAmpersand,
+                Symbol('!'),
+                Identifier(String::from("h")),
+                PipePipe,
+                Symbol('-'),
+                Identifier(String::from("i")),
+                Symbol('%'),
+                Identifier(String::from("j")),
+                Symbol('&'),
+                Identifier(String::from("k")),
+                Symbol('|'),
+                Identifier(String::from("l")),
+                Symbol('^'),
+                Identifier(String::from("m")),
+                LargeArrowRight,
+                Identifier(String::from("n")),
+                Equal,
+                Identifier(String::from("o")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_user_operator_test() {
+        let source = "infixl 6 <+> = plus\na <+> b |> c->d ==> e -- f\n!!g <$ h - -i";
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Infixl,
+                Integer(BigInt::from(6)),
+                Operator(String::from("<+>")),
+                Equal,
+                Identifier(String::from("plus")),
+                Indentation(String::new()),
+                Identifier(String::from("a")),
+                Operator(String::from("<+>")),
+                Identifier(String::from("b")),
+                PipeRAngle,
+                Identifier(String::from("c")),
+                SmallArrowRight,
+                Identifier(String::from("d")),
+                Operator(String::from("==>")),
+                Identifier(String::from("e")),
+                Comment,
+                Indentation(String::new()),
+                Symbol('!'),
+                Symbol('!'),
+                Identifier(String::from("g")),
+                Operator(String::from("<$")),
+                Identifier(String::from("h")),
+                Symbol('-'),
+                Symbol('-'),
+                Identifier(String::from("i")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_unicode_test() {
+        let source = "naïve + λx0 → 'α \"héllo, wörld\" (日本語)";
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Identifier(String::from("naïve")),
+                Symbol('+'),
+                Identifier(String::from("λx0")),
+                Unknown('→'),
+                TypeVariable(String::from("α")),
+                StringLiteral(String::from("\"héllo, wörld\"")),
+                Symbol('('),
+                Identifier(String::from("日本語")),
+                Symbol(')'),
+            ]
+        );
+
+        let spans: Vec<_> = Token::lexer("é+ü")
+            .spanned()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans, vec![0..2, 2..3, 3..5]);
+    }
+
+    #[test]
+    fn lex_number_test() {
+        let source = "1_000 0xff_FF 0o17 0b1010_1010 42u8 0x7fi8 0b1u1 1.5e3 2E-2 1_0.2_5 3e1_0 1.0f32 2f64 0x_ 1..2";
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        let int = |name: &str, bits: usize| ast::Type::Application {
+            name: String::from(name),
+            args: vec![ast::Type::Nat(BigInt::from(bits))],
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                Integer(BigInt::from(1_000)),
+                Integer(BigInt::from(0xffff)),
+                Integer(BigInt::from(0o17)),
+                Integer(BigInt::from(0b1010_1010)),
+                SuffixedInteger((BigInt::from(42), int("uint", 8))),
+                SuffixedInteger((BigInt::from(0x7f), int("int", 8))),
+                SuffixedInteger((BigInt::from(1), int("uint", 1))),
+                Float(1.5e3),
+                Float(2e-2),
+                Float(10.25),
+                Float(3e10),
+                SuffixedFloat((1.0, ast::Type::Identifier(String::from("f32")))),
+                SuffixedFloat((2.0, ast::Type::Identifier(String::from("f64")))),
+                LexError,
+                Integer(BigInt::from(1)),
+                DotDot,
+                Integer(BigInt::from(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_char_test() {
+        let source =
+            r#"'a' '\n' '\'' 'λ' '→' '\u{1F600}' '\x41' 'α 'ab' b'x' b'\xff' b"a\"b\x00" b'é'"#;
+        let tokens: Vec<_> = Token::lexer(source).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Char('a'),
+                Char('\n'),
+                Char('\''),
+                Char('λ'),
+                Char('→'),
+                Char('😀'),
+                Char('A'),
+                TypeVariable(String::from("α")),
+                TypeVariable(String::from("ab")),
+                Symbol('\''),
+                Byte(b'x'),
+                Byte(0xff),
+                ByteString(b"a\"b\x00".to_vec()),
+                Identifier(String::from("b")),
+                Char('é'),
+            ]
+        );
+    }
 }